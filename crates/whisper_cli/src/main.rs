@@ -0,0 +1,873 @@
+//! Transcribes audio files with a *whisper* model, in the manner of the `main` example of
+//! [whisper.cpp].
+//!
+//! [whisper.cpp]: https://github.com/ggerganov/whisper.cpp/
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{CommandFactory, Parser};
+use serde::Serialize;
+use thiserror::Error;
+
+use whisper_cpp::{
+    format_srt, format_text, format_timestamp, format_vtt, read_wav, set_english_spellings,
+    set_logger, EvalManifest, EvalReport, EvalResult, WavError, WhisperLogLevel, WhisperLogger,
+    WhisperMemoryUsage, WhisperModel, WhisperModelInfo, WhisperParams, WhisperParamsConfig,
+    WhisperParamsOverrides, WhisperPreprocessor, WhisperPreset, WhisperQuantType,
+    WhisperQuantizeProgress, WhisperSampling, WhisperSegment, WhisperSession, WhisperSessionError,
+    WhisperTextNormalizer,
+};
+
+/// Every input was transcribed.
+const EXIT_SUCCESS: u8 = 0;
+/// At least one input could not be transcribed, or its outputs could not be written.
+const EXIT_FILE_FAILED: u8 = 1;
+/// The arguments are invalid, which is also the code used by [`clap`].
+const EXIT_USAGE: u8 = 2;
+/// The model, the configuration file or the evaluation manifest could not be loaded.
+const EXIT_SETUP_FAILED: u8 = 3;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "whisper-cli",
+    version,
+    about = "Transcribes 16 kHz wav files with a whisper.cpp model",
+    after_help = "Exit codes:\n  \
+        0  every file was transcribed\n  \
+        1  at least one file failed\n  \
+        2  invalid arguments\n  \
+        3  the model, the configuration file or the evaluation manifest could not be loaded"
+)]
+struct Args {
+    /// Path of the ggml model.
+    #[arg(short, long)]
+    model: PathBuf,
+
+    /// The wav files to transcribe.
+    #[arg(required_unless_present_any = ["eval", "model_info", "quantize"])]
+    files: Vec<PathBuf>,
+
+    /// Transcribe the utterances of a JSON Lines manifest, each line holding the `audio` path and
+    /// the `reference` transcript, then print their word and character error rates. With
+    /// --output-json, the report is written to the manifest path, or --output-file, with a json
+    /// extension appended.
+    #[arg(long, conflicts_with = "files")]
+    eval: Option<PathBuf>,
+
+    /// The normalizer of the transcripts compared by --eval: english (the default), basic or
+    /// verbatim.
+    #[arg(long, value_parser = parse_normalizer, requires = "eval", conflicts_with = "files")]
+    eval_normalizer: Option<WhisperTextNormalizer>,
+
+    /// The British spellings the english normalizer replaces, as in OpenAI's
+    /// `whisper/normalizers/english.json`, instead of the most common ones only.
+    #[arg(long, requires = "eval", conflicts_with = "files")]
+    eval_spellings: Option<PathBuf>,
+
+    /// Print the hyperparameters and tensors of the model, without loading it, then exit.
+    #[arg(long)]
+    model_info: bool,
+
+    /// Quantize the model to q4_0, q4_1, q5_0, q5_1 or q8_0, writing it to --output-file as
+    /// is, then exit.
+    #[arg(long, value_parser = parse_quant_type, requires = "output_file")]
+    quantize: Option<WhisperQuantType>,
+
+    /// A TOML or JSON file holding a preset and parameter overrides, applied before the flags.
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// The preset the parameters start from, replacing the one of the configuration file.
+    #[arg(long)]
+    preset: Option<WhisperPreset>,
+
+    /// Number of threads to use during computation.
+    #[arg(short, long)]
+    threads: Option<u32>,
+
+    /// Time offset in milliseconds.
+    #[arg(long = "offset-t")]
+    offset_ms: Option<u32>,
+
+    /// Duration of audio to process in milliseconds.
+    #[arg(short, long = "duration")]
+    duration_ms: Option<u32>,
+
+    /// Maximum number of text context tokens to store.
+    #[arg(long)]
+    max_context: Option<u32>,
+
+    /// Maximum segment length in characters.
+    #[arg(long)]
+    max_len: Option<u32>,
+
+    /// Split on word rather than on token.
+    #[arg(long)]
+    split_on_word: bool,
+
+    /// Number of best candidates to keep, with greedy sampling.
+    #[arg(long)]
+    best_of: Option<u32>,
+
+    /// Beam size for beam search, which is used instead of greedy sampling if set.
+    #[arg(long)]
+    beam_size: Option<u32>,
+
+    /// Word timestamp probability threshold.
+    #[arg(long)]
+    word_thold: Option<f32>,
+
+    /// Entropy threshold for decoder fail.
+    #[arg(long)]
+    entropy_thold: Option<f32>,
+
+    /// Log probability threshold for decoder fail.
+    #[arg(long)]
+    logprob_thold: Option<f32>,
+
+    /// The initial temperature of sampling.
+    #[arg(long)]
+    temperature: Option<f32>,
+
+    /// Do not use temperature fallback while decoding.
+    #[arg(long)]
+    no_fallback: bool,
+
+    /// Remove the DC offset and the rumble of the audio, and normalize its loudness, before
+    /// transcribing it. Also warns about clipped recordings.
+    #[arg(long)]
+    preprocess: bool,
+
+    /// Speed up audio by x2 (reduced accuracy).
+    #[arg(long)]
+    speed_up: bool,
+
+    /// Translate from source language to english.
+    #[arg(long)]
+    translate: bool,
+
+    /// Enable tinydiarize (requires a tdrz model).
+    #[arg(long)]
+    tinydiarize: bool,
+
+    /// Spoken language ('auto' for auto-detect).
+    #[arg(short, long)]
+    language: Option<String>,
+
+    /// Exit after automatically detecting the language.
+    #[arg(long)]
+    detect_language: bool,
+
+    /// Initial prompt.
+    #[arg(long)]
+    prompt: Option<String>,
+
+    /// Print special tokens.
+    #[arg(long)]
+    print_special: bool,
+
+    /// Print progress.
+    #[arg(long)]
+    print_progress: bool,
+
+    /// Do not print timestamps.
+    #[arg(long)]
+    no_timestamps: bool,
+
+    /// Output result in a text file.
+    #[arg(long)]
+    output_txt: bool,
+
+    /// Output result in a vtt file.
+    #[arg(long)]
+    output_vtt: bool,
+
+    /// Output result in a srt file.
+    #[arg(long)]
+    output_srt: bool,
+
+    /// Output result in a JSON file.
+    #[arg(long)]
+    output_json: bool,
+
+    /// Output file path, without extension. Defaults to the input path, the extension of the
+    /// output being appended to it.
+    #[arg(long)]
+    output_file: Option<PathBuf>,
+
+    /// Do not use the GPU.
+    #[arg(long)]
+    no_gpu: bool,
+
+    /// The index of the GPU to use.
+    #[arg(long, default_value_t = 0)]
+    gpu_device: u32,
+
+    /// Print every message logged by whisper.cpp, instead of only warnings and errors.
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+fn parse_quant_type(name: &str) -> Result<WhisperQuantType, serde_json::Error> {
+    serde_json::from_value(serde_json::Value::String(name.to_string()))
+}
+
+fn parse_normalizer(name: &str) -> Result<WhisperTextNormalizer, String> {
+    match name {
+        "english" => Ok(WhisperTextNormalizer::English),
+        "basic" => Ok(WhisperTextNormalizer::Basic {
+            remove_diacritics: false,
+        }),
+        "verbatim" => Ok(WhisperTextNormalizer::Verbatim),
+        _ => Err(format!(
+            "unknown normalizer `{name}`, expected english, basic or verbatim"
+        )),
+    }
+}
+
+impl Args {
+    /// The overrides set by the flags.
+    fn overrides(&self) -> WhisperParamsOverrides {
+        let strategy = match (self.beam_size, self.best_of) {
+            (Some(beam_size), _) => Some(WhisperSampling::BeamSearch {
+                beam_size,
+                patience: -1.0,
+            }),
+            (None, Some(best_of)) => Some(WhisperSampling::Greedy { best_of }),
+            (None, None) => None,
+        };
+        let flag = |set: bool| set.then_some(true);
+
+        WhisperParamsOverrides {
+            strategy,
+            thread_count: self.threads,
+            max_text_ctx: self.max_context,
+            offset_ms: self.offset_ms,
+            duration_ms: self.duration_ms,
+            translate: flag(self.translate),
+            no_timestamps: flag(self.no_timestamps),
+            print_special: flag(self.print_special),
+            print_progress: flag(self.print_progress),
+            token_timestamps: self.max_len.map(|_| true),
+            thold_pt: self.word_thold,
+            max_len: self.max_len,
+            split_on_word: flag(self.split_on_word),
+            speed_up: flag(self.speed_up),
+            tdrz_enable: flag(self.tinydiarize),
+            initial_prompt: self.prompt.clone(),
+            language: self.language.clone(),
+            detect_language: flag(self.detect_language),
+            temperature: self.temperature,
+            temperature_inc: self.no_fallback.then_some(0.0),
+            entropy_thold: self.entropy_thold,
+            logprob_thold: self.logprob_thold,
+            ..Default::default()
+        }
+    }
+
+    /// The parameters of every transcription: the configuration file, if any, then the preset and
+    /// the flags.
+    fn params(&self) -> Result<WhisperParams, SetupError> {
+        let mut config = match &self.config {
+            Some(path) => read_config(path)?,
+            None => WhisperParamsConfig::default(),
+        };
+
+        if let Some(preset) = self.preset {
+            config.preset = preset;
+        }
+
+        // As in whisper.cpp's `main` example, progress is only printed if requested
+        let mut params = config.preset.params();
+        WhisperParamsOverrides {
+            print_progress: Some(false),
+            ..Default::default()
+        }
+        .apply(&mut params);
+        config.overrides.apply(&mut params);
+        self.overrides().apply(&mut params);
+        Ok(params)
+    }
+}
+
+#[derive(Debug, Error)]
+enum SetupError {
+    #[error("failed to read configuration file {path}: {source}")]
+    ConfigIo {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("invalid TOML configuration file {path}: {source}")]
+    ConfigToml {
+        path: PathBuf,
+        #[source]
+        source: toml::de::Error,
+    },
+    #[error("invalid JSON configuration file {path}: {source}")]
+    ConfigJson {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error("failed to load model: {0}")]
+    Model(#[from] whisper_cpp::WhisperError),
+    #[error(transparent)]
+    Manifest(#[from] whisper_cpp::EvalError),
+    #[error("failed to read the spellings {path}: {source}")]
+    SpellingsIo {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("invalid spellings {path}: {source}")]
+    Spellings {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+/// Reads a [`WhisperParamsConfig`], as JSON if the file extension is `json` and as TOML otherwise.
+fn read_config(path: &Path) -> Result<WhisperParamsConfig, SetupError> {
+    let contents = std::fs::read_to_string(path).map_err(|source| SetupError::ConfigIo {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    if path.extension().is_some_and(|ext| ext == "json") {
+        serde_json::from_str(&contents).map_err(|source| SetupError::ConfigJson {
+            path: path.to_path_buf(),
+            source,
+        })
+    } else {
+        toml::from_str(&contents).map_err(|source| SetupError::ConfigToml {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+}
+
+#[derive(Debug, Error)]
+enum FileError {
+    #[error(transparent)]
+    Audio(#[from] WavError),
+    #[error("transcription failed: {0}")]
+    Session(#[from] WhisperSessionError),
+    #[error("failed to write {path}: {source}")]
+    Output {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+/// The JSON output, in the same shape as the one of whisper.cpp's `main` example.
+#[derive(Serialize)]
+struct JsonOutput<'a> {
+    params: &'a WhisperParams,
+    transcription: Vec<JsonSegment<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonSegment<'a> {
+    timestamps: JsonRange<String>,
+    /// In milliseconds.
+    offsets: JsonRange<i64>,
+    text: &'a str,
+}
+
+#[derive(Serialize)]
+struct JsonRange<T> {
+    from: T,
+    to: T,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    if args.output_file.is_some() && args.files.len() > 1 {
+        let _ = Args::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--output-file can only be used with a single input file",
+            )
+            .print();
+        return ExitCode::from(EXIT_USAGE);
+    }
+
+    if args.model_info {
+        return match WhisperModelInfo::read(&args.model) {
+            Ok(info) => {
+                print_model_info(&info);
+                ExitCode::from(EXIT_SUCCESS)
+            }
+            Err(e) => {
+                eprintln!("error: {e}");
+                ExitCode::from(EXIT_SETUP_FAILED)
+            }
+        };
+    }
+
+    if let (Some(quant_type), Some(output)) = (args.quantize, &args.output_file) {
+        return match whisper_cpp::quantize_with_progress(
+            &args.model,
+            output,
+            quant_type,
+            print_quantize_progress,
+        ) {
+            Ok(stats) => {
+                let mib = |size| size as f64 / (1024.0 * 1024.0);
+                println!(
+                    "tensors:      {} quantized, {} copied",
+                    stats.quantized, stats.copied
+                );
+                println!(
+                    "size:         {:.1} MiB -> {:.1} MiB ({:.1}%)",
+                    mib(stats.input_size),
+                    mib(stats.output_size),
+                    stats.ratio() * 100.0
+                );
+                ExitCode::from(EXIT_SUCCESS)
+            }
+            Err(e) => {
+                eprintln!("error: {e}");
+                ExitCode::from(EXIT_SETUP_FAILED)
+            }
+        };
+    }
+
+    let min_level = if args.verbose {
+        WhisperLogLevel::Debug
+    } else {
+        WhisperLogLevel::Warn
+    };
+    set_logger(
+        WhisperLogger::custom(|record| eprintln!("whisper.cpp: {}", record.message))
+            .with_min_level(min_level),
+    );
+
+    let manifest = match args.eval.as_deref().map(EvalManifest::read).transpose() {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return ExitCode::from(EXIT_SETUP_FAILED);
+        }
+    };
+
+    if let Err(e) = args
+        .eval_spellings
+        .as_deref()
+        .map(load_spellings)
+        .transpose()
+    {
+        eprintln!("error: {e}");
+        return ExitCode::from(EXIT_SETUP_FAILED);
+    }
+
+    let (params, mut session) = match setup(&args) {
+        Ok(setup) => setup,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return ExitCode::from(EXIT_SETUP_FAILED);
+        }
+    };
+
+    if let (Some(manifest), Some(path)) = (manifest, &args.eval) {
+        return evaluate(&args, params, &mut session, manifest, path);
+    }
+
+    let mut failed = false;
+    for file in &args.files {
+        if let Err(e) = transcribe(&args, &params, &mut session, file) {
+            eprintln!("error: {}: {e}", file.display());
+            failed = true;
+        }
+    }
+
+    ExitCode::from(if failed {
+        EXIT_FILE_FAILED
+    } else {
+        EXIT_SUCCESS
+    })
+}
+
+fn setup(args: &Args) -> Result<(WhisperParams, WhisperSession), SetupError> {
+    let params = args.params()?;
+    let device = (!args.no_gpu).then_some(args.gpu_device);
+    let model = WhisperModel::new_from_file(&args.model, device)?;
+    let session = model.new_session_blocking()?;
+
+    Ok((params, session))
+}
+
+fn load_spellings(path: &Path) -> Result<(), SetupError> {
+    let json = std::fs::read_to_string(path).map_err(|source| SetupError::SpellingsIo {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    set_english_spellings(&json).map_err(|source| SetupError::Spellings {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+fn print_model_info(info: &WhisperModelInfo) {
+    let hparams = &info.hparams;
+    println!(
+        "type:         {}{}",
+        hparams.model_type().unwrap_or("unknown"),
+        if hparams.is_multilingual() { "" } else { ".en" }
+    );
+    println!(
+        "ftype:        {} ({})",
+        hparams.ftype,
+        hparams.ftype_name().unwrap_or("unknown")
+    );
+    println!("qntvr:        {}", info.quantization_version);
+    println!(
+        "vocabulary:   {} tokens, {} stored",
+        hparams.n_vocab,
+        info.vocab.len()
+    );
+    println!(
+        "audio:        {} ctx, {} state, {} heads, {} layers",
+        hparams.n_audio_ctx, hparams.n_audio_state, hparams.n_audio_head, hparams.n_audio_layer
+    );
+    println!(
+        "text:         {} ctx, {} state, {} heads, {} layers",
+        hparams.n_text_ctx, hparams.n_text_state, hparams.n_text_head, hparams.n_text_layer
+    );
+    println!(
+        "mel filters:  {} bands of {} bins",
+        info.mel_filters.n_mel, info.mel_filters.n_fft
+    );
+    println!(
+        "tensors:      {}, {:.1} MiB",
+        info.tensors.len(),
+        info.tensor_bytes() as f64 / (1024.0 * 1024.0)
+    );
+    let session = WhisperMemoryUsage::session(&info.hparams, Default::default());
+    println!(
+        "memory:       ~{:.1} MiB per session, for 30 seconds with a single decoder",
+        session.total() as f64 / (1024.0 * 1024.0)
+    );
+    println!();
+
+    for tensor in &info.tensors {
+        let shape = tensor
+            .shape
+            .iter()
+            .map(usize::to_string)
+            .collect::<Vec<_>>()
+            .join(" x ");
+        println!(
+            "{:<48} {:>20} {:>6} {:>12}",
+            tensor.name,
+            shape,
+            tensor.ggml_type.name(),
+            tensor.size()
+        );
+    }
+}
+
+fn print_quantize_progress(progress: WhisperQuantizeProgress<'_>) {
+    let tensor = progress.tensor;
+    println!(
+        "[{:>3}/{}] {:<48} {:>6} -> {:<6} {:>12} -> {}",
+        progress.index + 1,
+        progress.count,
+        tensor.name,
+        tensor.ggml_type.name(),
+        progress.ggml_type.name(),
+        tensor.size(),
+        progress.size
+    );
+}
+
+/// Reads `file`, preprocessing it with `--preprocess`.
+fn read_audio(args: &Args, file: &Path) -> Result<Vec<f32>, WavError> {
+    let mut samples = read_wav(file)?;
+
+    if args.preprocess {
+        let report = WhisperPreprocessor::default()
+            .apply(&mut samples)
+            .expect("the default preprocessor has valid filters");
+
+        if report.input.is_clipped() {
+            eprintln!(
+                "warning: {}: {} samples are clipped",
+                file.display(),
+                report.input.clipped_samples
+            );
+        }
+    }
+
+    Ok(samples)
+}
+
+/// Transcribes `file` on its own, printing its segments and writing the requested outputs.
+fn transcribe(
+    args: &Args,
+    params: &WhisperParams,
+    session: &mut WhisperSession,
+    file: &Path,
+) -> Result<(), FileError> {
+    let samples = read_audio(args, file)?;
+
+    session.reset();
+    session.advance_blocking(params.clone(), &samples)?;
+    let segments = session.segments()?;
+
+    for segment in &segments {
+        if args.no_timestamps {
+            println!("{}", segment.text.trim());
+        } else {
+            println!(
+                "[{} --> {}]  {}",
+                format_timestamp(segment.start, '.'),
+                format_timestamp(segment.end, '.'),
+                segment.text.trim()
+            );
+        }
+    }
+
+    let base = args.output_file.as_deref().unwrap_or(file);
+    let outputs = [
+        (args.output_txt, "txt"),
+        (args.output_srt, "srt"),
+        (args.output_vtt, "vtt"),
+        (args.output_json, "json"),
+    ];
+
+    for (_, extension) in outputs.into_iter().filter(|(enabled, _)| *enabled) {
+        let contents = match extension {
+            "txt" => format_text(&segments),
+            "srt" => format_srt(&segments),
+            "vtt" => format_vtt(&segments),
+            _ => format_json(params, &segments),
+        };
+
+        let mut path = base.as_os_str().to_owned();
+        path.push(".");
+        path.push(extension);
+        let path = PathBuf::from(path);
+
+        std::fs::write(&path, contents).map_err(|source| FileError::Output { path, source })?;
+    }
+
+    Ok(())
+}
+
+/// Transcribes the utterances of `manifest` one after the other, printing their error rates and
+/// the totals, and writing the report with `--output-json`.
+fn evaluate(
+    args: &Args,
+    params: WhisperParams,
+    session: &mut WhisperSession,
+    manifest: EvalManifest,
+    path: &Path,
+) -> ExitCode {
+    let normalizer = args.eval_normalizer.unwrap_or_default();
+    let mut results = Vec::with_capacity(manifest.entries.len());
+
+    for entry in &manifest.entries {
+        let segments = read_audio(args, &entry.audio)
+            .map_err(FileError::from)
+            .and_then(|samples| {
+                session.reset();
+                session.advance_blocking(params.clone(), &samples)?;
+                Ok(session.segments()?)
+            });
+
+        let result = match segments {
+            Ok(segments) => EvalResult::new(entry, &segments, normalizer),
+            Err(e) => {
+                eprintln!("error: {}: {e}", entry.audio.display());
+                EvalResult::failed(entry, e, normalizer)
+            }
+        };
+
+        if result.error.is_none() {
+            println!(
+                "WER {:>6.2}%  CER {:>6.2}%  {}",
+                result.wer() * 100.0,
+                result.cer() * 100.0,
+                entry.audio.display()
+            );
+        }
+        results.push(result);
+    }
+
+    let report = EvalReport::new(params, normalizer, results);
+    println!(
+        "total: WER {:.2}% ({} errors / {} words), CER {:.2}%, {} of {} failed",
+        report.wer() * 100.0,
+        report.words.errors(),
+        report.words.reference_len(),
+        report.cer() * 100.0,
+        report.failed,
+        report.results.len()
+    );
+
+    let mut failed = report.failed > 0;
+    if args.output_json {
+        let mut output = args
+            .output_file
+            .as_deref()
+            .unwrap_or(path)
+            .as_os_str()
+            .to_owned();
+        output.push(".json");
+        let output = PathBuf::from(output);
+
+        // Serializing to a `String` cannot fail
+        let contents = serde_json::to_string_pretty(&report).unwrap();
+        if let Err(source) = std::fs::write(&output, contents) {
+            eprintln!(
+                "error: {}",
+                FileError::Output {
+                    path: output,
+                    source
+                }
+            );
+            failed = true;
+        }
+    }
+
+    ExitCode::from(if failed {
+        EXIT_FILE_FAILED
+    } else {
+        EXIT_SUCCESS
+    })
+}
+
+fn format_json(params: &WhisperParams, segments: &[WhisperSegment]) -> String {
+    let output = JsonOutput {
+        params,
+        transcription: segments
+            .iter()
+            .map(|segment| JsonSegment {
+                timestamps: JsonRange {
+                    from: format_timestamp(segment.start, ','),
+                    to: format_timestamp(segment.end, ','),
+                },
+                offsets: JsonRange {
+                    from: segment.start * 10,
+                    to: segment.end * 10,
+                },
+                text: &segment.text,
+            })
+            .collect(),
+    };
+
+    // Serializing to a `String` cannot fail
+    serde_json::to_string_pretty(&output).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command() {
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn model_info_needs_no_files() {
+        assert!(Args::try_parse_from(["whisper-cli", "-m", "model.bin", "--model-info"]).is_ok());
+        assert!(Args::try_parse_from(["whisper-cli", "-m", "model.bin"]).is_err());
+    }
+
+    #[test]
+    fn quantize_needs_an_output() {
+        let args = Args::try_parse_from([
+            "whisper-cli",
+            "-m",
+            "model.bin",
+            "--quantize",
+            "q5_0",
+            "--output-file",
+            "model-q5_0.bin",
+        ])
+        .unwrap();
+        assert_eq!(args.quantize, Some(WhisperQuantType::Q5_0));
+
+        let parse = |args: &[&str]| {
+            Args::try_parse_from(["whisper-cli", "-m", "model.bin"].iter().chain(args))
+        };
+        assert!(parse(&["--quantize", "q5_0"]).is_err());
+        assert!(parse(&["--quantize", "q3_k", "--output-file", "out.bin"]).is_err());
+    }
+
+    #[test]
+    fn eval_takes_a_manifest_instead_of_files() {
+        let args = Args::try_parse_from([
+            "whisper-cli",
+            "-m",
+            "model.bin",
+            "--eval",
+            "dev.jsonl",
+            "--eval-normalizer",
+            "basic",
+        ])
+        .unwrap();
+        assert_eq!(args.eval, Some(PathBuf::from("dev.jsonl")));
+        assert_eq!(
+            args.eval_normalizer,
+            Some(WhisperTextNormalizer::Basic {
+                remove_diacritics: false
+            })
+        );
+
+        let parse = |args: &[&str]| {
+            Args::try_parse_from(["whisper-cli", "-m", "model.bin"].iter().chain(args))
+        };
+        assert_eq!(
+            parse(&["--eval", "dev.jsonl"]).unwrap().eval_normalizer,
+            None
+        );
+        assert!(parse(&["--eval", "dev.jsonl", "a.wav"]).is_err());
+        assert!(parse(&["--eval", "dev.jsonl", "--eval-normalizer", "nope"]).is_err());
+        assert!(parse(&["--eval-normalizer", "basic", "a.wav"]).is_err());
+    }
+
+    #[test]
+    fn flags_override_the_preset() {
+        let args = Args::try_parse_from([
+            "whisper-cli",
+            "-m",
+            "model.bin",
+            "--preset",
+            "subtitles",
+            "--beam-size",
+            "4",
+            "-l",
+            "fr",
+            "--no-fallback",
+            "a.wav",
+            "b.wav",
+        ])
+        .unwrap();
+
+        let mut expected = WhisperPreset::Subtitles.params();
+        WhisperParamsOverrides {
+            strategy: Some(WhisperSampling::BeamSearch {
+                beam_size: 4,
+                patience: -1.0,
+            }),
+            print_progress: Some(false),
+            language: Some("fr".to_string()),
+            temperature_inc: Some(0.0),
+            ..Default::default()
+        }
+        .apply(&mut expected);
+
+        assert_eq!(args.files, [PathBuf::from("a.wav"), PathBuf::from("b.wav")]);
+        assert_eq!(args.params().unwrap(), expected);
+        assert!(Args::try_parse_from(["whisper-cli", "-m", "model.bin"]).is_err());
+        assert!(Args::try_parse_from(["whisper-cli", "--preset", "nope", "a.wav"]).is_err());
+    }
+}
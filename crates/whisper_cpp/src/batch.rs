@@ -0,0 +1,181 @@
+use std::num::NonZeroUsize;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use thiserror::Error;
+use tokio::sync::mpsc;
+
+use crate::{
+    SessionPool, WhisperError, WhisperModel, WhisperParams, WhisperSegment, WhisperSessionError,
+};
+
+/// Transcribes many inputs with a fixed number of [`WhisperSession`](crate::WhisperSession)s of
+/// the same [`WhisperModel`], sharing its context.
+///
+/// A global budget of threads is split between the sessions, so running a batch never uses more
+/// threads than that, whatever the number of sessions.
+pub struct BatchTranscriber {
+    pool: SessionPool,
+
+    /// The number of threads of each session.
+    threads: Vec<u32>,
+
+    params: WhisperParams,
+}
+
+impl BatchTranscriber {
+    /// Creates a new [`BatchTranscriber`], allocating `sessions` sessions of the provided
+    /// [`WhisperModel`], which share `thread_budget` threads as evenly as possible.
+    ///
+    /// Every input is transcribed with `params`, except for [`WhisperParams::thread_count`], which
+    /// is set by the transcriber. Each input is transcribed on its own, without text context.
+    pub async fn new(
+        model: &WhisperModel,
+        sessions: NonZeroUsize,
+        thread_budget: NonZeroUsize,
+        params: WhisperParams,
+    ) -> Result<Self, WhisperError> {
+        let sessions = sessions.min(thread_budget);
+        let (share, remainder) = (
+            thread_budget.get() / sessions.get(),
+            thread_budget.get() % sessions.get(),
+        );
+        let threads = (0..sessions.get())
+            .map(|i| (share + usize::from(i < remainder)) as u32)
+            .collect();
+
+        Ok(Self {
+            pool: SessionPool::new(model, sessions).await?,
+            threads,
+            params,
+        })
+    }
+
+    /// The number of sessions transcribing inputs at once.
+    ///
+    /// This is less than requested when the thread budget could not give each session a thread.
+    pub fn sessions(&self) -> usize {
+        self.threads.len()
+    }
+
+    /// Transcribes every input, reading its samples with `load` on [`tokio`]'s blocking thread
+    /// pool.
+    ///
+    /// The results are yielded as the inputs complete, not in their original order. A failing
+    /// input, even if `load` panics, does not stop the batch, and dropping the returned
+    /// [`BatchResults`] cancels the inputs not started yet.
+    ///
+    /// ## Panic
+    /// Panics if called outside of a [`tokio`] runtime.
+    pub fn run<I, F, E>(&self, inputs: I, load: F) -> BatchResults<I::Item, E>
+    where
+        I: IntoIterator,
+        I::IntoIter: Send + 'static,
+        I::Item: Send + 'static,
+        F: Fn(&I::Item) -> Result<Vec<f32>, E> + Send + Sync + 'static,
+        E: Send + 'static,
+    {
+        let inputs = Arc::new(Mutex::new(inputs.into_iter().enumerate()));
+        let load = Arc::new(load);
+        let (sender, receiver) = mpsc::channel(self.threads.len());
+
+        for &thread_count in &self.threads {
+            let pool = self.pool.clone();
+            let inputs = inputs.clone();
+            let load = load.clone();
+            let sender = sender.clone();
+            let mut params = self.params.clone();
+            params.thread_count = thread_count;
+
+            tokio::spawn(async move {
+                let mut session = pool.checkout().await;
+
+                loop {
+                    let Some((index, input)) =
+                        inputs.lock().unwrap_or_else(PoisonError::into_inner).next()
+                    else {
+                        break;
+                    };
+
+                    let load = load.clone();
+                    let loaded = tokio::task::spawn_blocking(move || {
+                        let samples = catch_unwind(AssertUnwindSafe(|| load(&input)));
+                        (input, samples)
+                    })
+                    .await;
+                    let Ok((input, samples)) = loaded else {
+                        // The runtime is shutting down
+                        break;
+                    };
+
+                    let result = match samples {
+                        Ok(Ok(samples)) => {
+                            session.reset();
+                            match session.advance(params.clone(), &samples).await {
+                                Ok(()) => session.segments().map_err(BatchError::Session),
+                                Err(e) => Err(BatchError::Session(e)),
+                            }
+                        }
+                        Ok(Err(e)) => Err(BatchError::Load(e)),
+                        Err(_) => Err(BatchError::LoadPanic),
+                    };
+
+                    let result = BatchResult {
+                        index,
+                        input,
+                        result,
+                    };
+                    if sender.send(result).await.is_err() {
+                        // The results are not awaited anymore
+                        break;
+                    }
+                }
+            });
+        }
+
+        BatchResults { receiver }
+    }
+}
+
+/// The outcome of an input of a batch, see [`BatchTranscriber::run`].
+#[derive(Debug)]
+pub struct BatchResult<T, E> {
+    /// The position of the input in the batch.
+    pub index: usize,
+
+    /// The input.
+    pub input: T,
+
+    /// The segments transcribed from the input.
+    pub result: Result<Vec<WhisperSegment>, BatchError<E>>,
+}
+
+#[derive(Debug, Error)]
+pub enum BatchError<E> {
+    #[error("failed to load the input: {0}")]
+    Load(E),
+    #[error("loading the input panicked")]
+    LoadPanic,
+    #[error("failed to transcribe the input: {0}")]
+    Session(#[from] WhisperSessionError),
+}
+
+/// The results of a running batch, in the order the inputs complete.
+pub struct BatchResults<T, E> {
+    receiver: mpsc::Receiver<BatchResult<T, E>>,
+}
+
+impl<T, E> BatchResults<T, E> {
+    /// Waits for the next input to complete, returning [`None`] once every input did.
+    pub async fn next(&mut self) -> Option<BatchResult<T, E>> {
+        self.receiver.recv().await
+    }
+
+    /// Synchronous version of [`BatchResults::next`].
+    ///
+    /// ## Panic
+    /// Panics if called from within an asynchronous execution context.
+    pub fn blocking_next(&mut self) -> Option<BatchResult<T, E>> {
+        self.receiver.blocking_recv()
+    }
+}
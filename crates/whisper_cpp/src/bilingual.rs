@@ -0,0 +1,82 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::WhisperSegment;
+
+/// A subtitle cue holding the same speech in its original language and in English.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WhisperBilingualCue {
+    /// The start time of the cue, in centiseconds.
+    pub start: i64,
+    /// The end time of the cue, in centiseconds.
+    pub end: i64,
+    /// The transcribed text, in the language spoken.
+    pub original: String,
+    /// The text translated to English.
+    pub translation: String,
+}
+
+/// Aligns the segments of a transcription and of a translation of the same audio by time.
+///
+/// Segments from either list overlapping in time are merged into the same cue, as the two runs
+/// rarely split the speech at the same places. A cue holds several segments of at most one of the
+/// lists, so that segments straddling two cues cannot chain a whole passage into one: a segment
+/// which would give both lists several segments starts a new cue instead, which then overlaps the
+/// previous one in time. A segment overlapping nothing in the other list gets a cue of its own,
+/// with the other text left empty.
+pub fn align_bilingual(
+    original: &[WhisperSegment],
+    translation: &[WhisperSegment],
+) -> Vec<WhisperBilingualCue> {
+    let mut cues: Vec<WhisperBilingualCue> = vec![];
+    let mut original = original.iter().peekable();
+    let mut translation = translation.iter().peekable();
+    // The number of original and of translated segments in the last cue
+    let mut counts = (0, 0);
+
+    loop {
+        // Takes the segment starting first, from either list
+        let (segment, is_original) = match (original.peek(), translation.peek()) {
+            (Some(o), Some(t)) if o.start <= t.start => (original.next().unwrap(), true),
+            (_, Some(_)) => (translation.next().unwrap(), false),
+            (Some(_), None) => (original.next().unwrap(), true),
+            (None, None) => break,
+        };
+
+        let merged = if is_original {
+            (counts.0 + 1, counts.1)
+        } else {
+            (counts.0, counts.1 + 1)
+        };
+
+        let cue = match cues.last_mut() {
+            Some(cue) if segment.start < cue.end && (merged.0 <= 1 || merged.1 <= 1) => {
+                counts = merged;
+                cue.end = cue.end.max(segment.end);
+                cue
+            }
+            _ => {
+                counts = (is_original as usize, !is_original as usize);
+                cues.push(WhisperBilingualCue {
+                    start: segment.start,
+                    end: segment.end,
+                    ..Default::default()
+                });
+                cues.last_mut().unwrap()
+            }
+        };
+
+        let text = if is_original {
+            &mut cue.original
+        } else {
+            &mut cue.translation
+        };
+        if !text.is_empty() {
+            text.push(' ');
+        }
+        text.push_str(segment.text.trim());
+    }
+
+    cues
+}
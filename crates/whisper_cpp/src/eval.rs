@@ -0,0 +1,494 @@
+use std::fmt::Display;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    BatchResult, BatchTranscriber, WhisperError, WhisperModel, WhisperParams, WhisperSegment,
+    WhisperTextNormalizer,
+};
+
+/// A word or character of a reference transcript, matched with one of a transcript of the same
+/// audio by the alignment with the fewest edits.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EvalAlignment {
+    /// The token is in both transcripts.
+    Hit { token: String },
+
+    /// The reference token was transcribed as another one.
+    Substitution {
+        reference: String,
+        hypothesis: String,
+    },
+
+    /// The reference token is missing from the transcript.
+    Deletion { reference: String },
+
+    /// The transcript has a token which is not in the reference.
+    Insertion { hypothesis: String },
+}
+
+/// Aligns the words of `reference` and `hypothesis`, which are split on whitespace.
+pub fn align_words(reference: &str, hypothesis: &str) -> Vec<EvalAlignment> {
+    let reference: Vec<_> = reference.split_whitespace().collect();
+    let hypothesis: Vec<_> = hypothesis.split_whitespace().collect();
+    align(&reference, &hypothesis)
+}
+
+/// Aligns the characters of `reference` and `hypothesis`, spaces included.
+pub fn align_chars(reference: &str, hypothesis: &str) -> Vec<EvalAlignment> {
+    let reference: Vec<_> = reference.chars().collect();
+    let hypothesis: Vec<_> = hypothesis.chars().collect();
+    align(&reference, &hypothesis)
+}
+
+/// Finds the alignment of the least edits with the Levenshtein distance, preferring hits and
+/// substitutions over deletions, and deletions over insertions, on ties.
+fn align<T: PartialEq + ToString>(reference: &[T], hypothesis: &[T]) -> Vec<EvalAlignment> {
+    let width = hypothesis.len() + 1;
+    let mut costs = vec![0u32; (reference.len() + 1) * width];
+
+    for (i, cost) in costs.iter_mut().step_by(width).enumerate() {
+        *cost = i as u32;
+    }
+    for (j, cost) in costs[..width].iter_mut().enumerate() {
+        *cost = j as u32;
+    }
+    for i in 1..=reference.len() {
+        for j in 1..=hypothesis.len() {
+            let diagonal =
+                costs[(i - 1) * width + j - 1] + u32::from(reference[i - 1] != hypothesis[j - 1]);
+            let deletion = costs[(i - 1) * width + j] + 1;
+            let insertion = costs[i * width + j - 1] + 1;
+            costs[i * width + j] = diagonal.min(deletion).min(insertion);
+        }
+    }
+
+    let mut alignment = Vec::with_capacity(reference.len().max(hypothesis.len()));
+    let (mut i, mut j) = (reference.len(), hypothesis.len());
+
+    while i > 0 || j > 0 {
+        let cost = costs[i * width + j];
+
+        if i > 0 && j > 0 {
+            let same = reference[i - 1] == hypothesis[j - 1];
+            if costs[(i - 1) * width + j - 1] + u32::from(!same) == cost {
+                alignment.push(match same {
+                    true => EvalAlignment::Hit {
+                        token: reference[i - 1].to_string(),
+                    },
+                    false => EvalAlignment::Substitution {
+                        reference: reference[i - 1].to_string(),
+                        hypothesis: hypothesis[j - 1].to_string(),
+                    },
+                });
+                (i, j) = (i - 1, j - 1);
+                continue;
+            }
+        }
+
+        if i > 0 && costs[(i - 1) * width + j] + 1 == cost {
+            alignment.push(EvalAlignment::Deletion {
+                reference: reference[i - 1].to_string(),
+            });
+            i -= 1;
+        } else {
+            alignment.push(EvalAlignment::Insertion {
+                hypothesis: hypothesis[j - 1].to_string(),
+            });
+            j -= 1;
+        }
+    }
+
+    alignment.reverse();
+    alignment
+}
+
+/// Formats an alignment on three lines: the reference, the hypothesis and the kind of each edit,
+/// `S`, `D` or `I`, with `*` standing for missing tokens.
+///
+/// ```text
+/// REF: the cat sat on the mat ***
+/// HYP: the bat sat ** the mat too
+///          S       D          I
+/// ```
+pub fn format_alignment(alignment: &[EvalAlignment]) -> String {
+    let (mut reference, mut hypothesis, mut edits) = (
+        String::from("REF:"),
+        String::from("HYP:"),
+        String::from("    "),
+    );
+
+    for aligned in alignment {
+        let (left, right, edit) = match aligned {
+            EvalAlignment::Hit { token } => (token.as_str(), token.as_str(), ' '),
+            EvalAlignment::Substitution {
+                reference,
+                hypothesis,
+            } => (reference.as_str(), hypothesis.as_str(), 'S'),
+            EvalAlignment::Deletion { reference } => (reference.as_str(), "", 'D'),
+            EvalAlignment::Insertion { hypothesis } => ("", hypothesis.as_str(), 'I'),
+        };
+        let width = left.chars().count().max(right.chars().count());
+        let pad = |token: &str| match token.is_empty() {
+            true => "*".repeat(width),
+            false => format!("{token:width$}"),
+        };
+
+        reference.push(' ');
+        reference.push_str(&pad(left));
+        hypothesis.push(' ');
+        hypothesis.push_str(&pad(right));
+        edits.push(' ');
+        edits.push_str(&format!("{edit:width$}"));
+    }
+
+    format!(
+        "{}\n{}\n{}",
+        reference.trim_end(),
+        hypothesis.trim_end(),
+        edits.trim_end()
+    )
+}
+
+/// The edits between a reference transcript and another transcript of the same audio.
+///
+/// Counts add up, so that the error rate of a whole test set is the one of the sum of the counts
+/// of its utterances.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    derive_more::Add,
+    derive_more::AddAssign,
+)]
+pub struct EvalCounts {
+    pub hits: usize,
+    pub substitutions: usize,
+    pub deletions: usize,
+    pub insertions: usize,
+}
+
+impl EvalCounts {
+    pub fn from_alignment(alignment: &[EvalAlignment]) -> Self {
+        let mut counts = Self::default();
+
+        for aligned in alignment {
+            match aligned {
+                EvalAlignment::Hit { .. } => counts.hits += 1,
+                EvalAlignment::Substitution { .. } => counts.substitutions += 1,
+                EvalAlignment::Deletion { .. } => counts.deletions += 1,
+                EvalAlignment::Insertion { .. } => counts.insertions += 1,
+            }
+        }
+
+        counts
+    }
+
+    /// Number of substitutions, deletions and insertions.
+    pub fn errors(&self) -> usize {
+        self.substitutions + self.deletions + self.insertions
+    }
+
+    /// Number of tokens of the reference.
+    pub fn reference_len(&self) -> usize {
+        self.hits + self.substitutions + self.deletions
+    }
+
+    /// The error rate: the number of errors over the number of tokens of the reference, which
+    /// exceeds 1 when the transcript has many insertions.
+    ///
+    /// An empty reference counts as a single token, so that its insertions are still errors.
+    pub fn rate(&self) -> f64 {
+        self.errors() as f64 / self.reference_len().max(1) as f64
+    }
+}
+
+impl std::iter::Sum for EvalCounts {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |sum, counts| sum + counts)
+    }
+}
+
+/// An utterance of an [`EvalManifest`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EvalEntry {
+    /// The audio file of the utterance.
+    pub audio: PathBuf,
+
+    /// What is said in the audio.
+    pub reference: String,
+}
+
+/// The utterances an [`Evaluator`] transcribes, along with their reference transcripts.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EvalManifest {
+    pub entries: Vec<EvalEntry>,
+}
+
+#[derive(Debug, Error)]
+pub enum EvalError {
+    #[error("failed to read {}: {source}", path.display())]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("invalid manifest entry on line {line}: {source}")]
+    Entry {
+        line: usize,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+impl EvalManifest {
+    /// Parses a manifest in the JSON Lines format, one entry per line, with relative audio paths
+    /// being relative to `base`. Blank lines are skipped.
+    ///
+    /// ```json
+    /// {"audio": "clips/0001.wav", "reference": "Hello there."}
+    /// {"audio": "clips/0002.wav", "reference": "General Kenobi!"}
+    /// ```
+    pub fn parse(contents: &str, base: &Path) -> Result<Self, EvalError> {
+        let entries = contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                let mut entry: EvalEntry =
+                    serde_json::from_str(line).map_err(|source| EvalError::Entry {
+                        line: index + 1,
+                        source,
+                    })?;
+                entry.audio = base.join(entry.audio);
+                Ok(entry)
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { entries })
+    }
+
+    /// Reads a manifest in the format of [`EvalManifest::parse`], with relative audio paths being
+    /// relative to the directory of the manifest.
+    pub fn read<P>(path: P) -> Result<Self, EvalError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(|source| EvalError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        Self::parse(&contents, path.parent().unwrap_or(Path::new("")))
+    }
+}
+
+/// How well an utterance was transcribed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EvalResult {
+    pub audio: PathBuf,
+
+    /// The normalized reference transcript.
+    pub reference: String,
+
+    /// The normalized transcript.
+    pub hypothesis: String,
+
+    /// The word edits, which give the word error rate (WER).
+    pub words: EvalCounts,
+
+    /// The character edits, which give the character error rate (CER).
+    pub chars: EvalCounts,
+
+    /// The word alignment, see [`format_alignment`].
+    pub alignment: Vec<EvalAlignment>,
+
+    /// Why the utterance could not be transcribed, in which case it is left out of the totals of
+    /// the [`EvalReport`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl EvalResult {
+    /// Compares the transcript made of `segments` with the reference of `entry`, both normalized
+    /// with `normalizer`.
+    pub fn new(
+        entry: &EvalEntry,
+        segments: &[WhisperSegment],
+        normalizer: WhisperTextNormalizer,
+    ) -> Self {
+        let text: String = segments
+            .iter()
+            .map(|segment| segment.text.as_str())
+            .collect();
+        let reference = normalizer.normalize(&entry.reference);
+        let hypothesis = normalizer.normalize(&text);
+        let alignment = align_words(&reference, &hypothesis);
+
+        Self {
+            audio: entry.audio.clone(),
+            words: EvalCounts::from_alignment(&alignment),
+            chars: EvalCounts::from_alignment(&align_chars(&reference, &hypothesis)),
+            reference,
+            hypothesis,
+            alignment,
+            error: None,
+        }
+    }
+
+    /// The result of an utterance which could not be transcribed.
+    pub fn failed(
+        entry: &EvalEntry,
+        error: impl Display,
+        normalizer: WhisperTextNormalizer,
+    ) -> Self {
+        Self {
+            audio: entry.audio.clone(),
+            reference: normalizer.normalize(&entry.reference),
+            hypothesis: String::new(),
+            words: EvalCounts::default(),
+            chars: EvalCounts::default(),
+            alignment: vec![],
+            error: Some(error.to_string()),
+        }
+    }
+
+    pub fn wer(&self) -> f64 {
+        self.words.rate()
+    }
+
+    pub fn cer(&self) -> f64 {
+        self.chars.rate()
+    }
+}
+
+/// The accuracy of a model with some parameters over the utterances of an [`EvalManifest`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EvalReport {
+    /// The parameters the utterances were transcribed with.
+    pub params: WhisperParams,
+
+    /// The normalizer of the transcripts.
+    pub normalizer: WhisperTextNormalizer,
+
+    /// The result of every utterance, in the order of the manifest.
+    pub results: Vec<EvalResult>,
+
+    /// The word edits of every utterance transcribed.
+    pub words: EvalCounts,
+
+    /// The character edits of every utterance transcribed.
+    pub chars: EvalCounts,
+
+    /// Number of utterances which could not be transcribed.
+    pub failed: usize,
+}
+
+impl EvalReport {
+    pub fn new(
+        params: WhisperParams,
+        normalizer: WhisperTextNormalizer,
+        results: Vec<EvalResult>,
+    ) -> Self {
+        let transcribed = results.iter().filter(|result| result.error.is_none());
+
+        Self {
+            words: transcribed.clone().map(|result| result.words).sum(),
+            chars: transcribed.map(|result| result.chars).sum(),
+            failed: results
+                .iter()
+                .filter(|result| result.error.is_some())
+                .count(),
+            params,
+            normalizer,
+            results,
+        }
+    }
+
+    /// The word error rate over every utterance transcribed.
+    pub fn wer(&self) -> f64 {
+        self.words.rate()
+    }
+
+    /// The character error rate over every utterance transcribed.
+    pub fn cer(&self) -> f64 {
+        self.chars.rate()
+    }
+}
+
+/// Transcribes the utterances of [`EvalManifest`]s with a [`BatchTranscriber`], and compares
+/// them with their references.
+pub struct Evaluator {
+    transcriber: BatchTranscriber,
+    params: WhisperParams,
+    normalizer: WhisperTextNormalizer,
+}
+
+impl Evaluator {
+    /// Creates a new [`Evaluator`], transcribing with `params` through `sessions` sessions of
+    /// `model` sharing `thread_budget` threads, see [`BatchTranscriber::new`].
+    pub async fn new(
+        model: &WhisperModel,
+        sessions: NonZeroUsize,
+        thread_budget: NonZeroUsize,
+        params: WhisperParams,
+        normalizer: WhisperTextNormalizer,
+    ) -> Result<Self, WhisperError> {
+        Ok(Self {
+            transcriber: BatchTranscriber::new(model, sessions, thread_budget, params.clone())
+                .await?,
+            params,
+            normalizer,
+        })
+    }
+
+    /// Transcribes every utterance of `manifest`, reading their samples with `load`, and reports
+    /// the results.
+    ///
+    /// Utterances which fail to load or to be transcribed are reported as such, without stopping
+    /// the evaluation.
+    ///
+    /// ## Panic
+    /// Panics if called outside of a [`tokio`] runtime.
+    pub async fn run<F, E>(&self, manifest: EvalManifest, load: F) -> EvalReport
+    where
+        F: Fn(&Path) -> Result<Vec<f32>, E> + Send + Sync + 'static,
+        E: Display + Send + 'static,
+    {
+        let mut results = vec![None; manifest.entries.len()];
+        let mut batch = self
+            .transcriber
+            .run(manifest.entries, move |entry: &EvalEntry| {
+                load(&entry.audio)
+            });
+
+        while let Some(BatchResult {
+            index,
+            input,
+            result,
+        }) = batch.next().await
+        {
+            results[index] = Some(match result {
+                Ok(segments) => EvalResult::new(&input, &segments, self.normalizer),
+                Err(e) => EvalResult::failed(&input, e, self.normalizer),
+            });
+        }
+
+        EvalReport::new(
+            self.params.clone(),
+            self.normalizer,
+            results.into_iter().flatten().collect(),
+        )
+    }
+}
@@ -0,0 +1,290 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{WhisperQualityThresholds, WhisperSegment, WhisperSegmentMetrics};
+
+/// What [`WhisperSegmentFilter::apply`] does with the segments it catches.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum WhisperFilterAction {
+    /// Drop the caught segments from the transcript.
+    #[default]
+    Remove,
+
+    /// Keep every segment, only listing the caught ones in the [`WhisperFilterReport`].
+    Mark,
+}
+
+/// A post-processor catching the usual failure modes of Whisper in a transcript: looping phrases,
+/// stock phrases produced over silence, and segments too long or too short for their duration.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct WhisperSegmentFilter {
+    /// What to do with the caught segments.
+    pub action: WhisperFilterAction,
+
+    /// The number of consecutive copies of a phrase, or of a segment, from which it is considered
+    /// a loop.
+    pub min_repeats: usize,
+
+    /// The length in words of the longest phrase looked for in loops.
+    pub max_ngram_size: usize,
+
+    /// The number of characters a loop must span to be caught, so short natural repetitions such
+    /// as "no, no, no" are kept.
+    pub min_repetition_chars: usize,
+
+    /// Phrases Whisper is known to produce over silence, segments made of exactly one of them are
+    /// caught. Case, punctuation and spacing are ignored.
+    pub hallucinations: Vec<String>,
+
+    /// Phrases Whisper produces over silence which are also commonly spoken, segments made of
+    /// exactly one of them are only caught when their metrics also tell they hold no speech, see
+    /// [`WhisperSegmentFilter::apply_with_metrics`]. Case, punctuation and spacing are ignored.
+    pub fillers: Vec<String>,
+
+    /// The thresholds of [`WhisperSegmentMetrics::is_no_speech`], for [`Self::fillers`].
+    pub no_speech: WhisperQualityThresholds,
+
+    /// The lowest plausible number of characters per second of speech.
+    pub min_chars_per_second: f32,
+
+    /// The highest plausible number of characters per second of speech.
+    pub max_chars_per_second: f32,
+}
+
+impl Default for WhisperSegmentFilter {
+    fn default() -> Self {
+        Self {
+            action: WhisperFilterAction::default(),
+            min_repeats: 3,
+            max_ngram_size: 8,
+            min_repetition_chars: 15,
+            hallucinations: [
+                "thanks for watching",
+                "thank you for watching",
+                "thank you very much for watching",
+                "please subscribe",
+                "subscribe to my channel",
+                "subtitles by the amara org community",
+                "transcription by castingwords",
+            ]
+            .map(String::from)
+            .to_vec(),
+            fillers: ["you", "thank you"].map(String::from).to_vec(),
+            no_speech: WhisperQualityThresholds::default(),
+            min_chars_per_second: 0.5,
+            max_chars_per_second: 30.0,
+        }
+    }
+}
+
+impl WhisperSegmentFilter {
+    /// Runs the filter over `segments`, returning the segments kept and a report of the caught
+    /// ones.
+    ///
+    /// With [`WhisperFilterAction::Mark`] every segment is kept, in the same order.
+    ///
+    /// Without their metrics, segments made of one of the [`fillers`](Self::fillers) are kept.
+    pub fn apply(
+        &self,
+        segments: Vec<WhisperSegment>,
+    ) -> (Vec<WhisperSegment>, WhisperFilterReport) {
+        self.apply_with_metrics(segments, &[])
+    }
+
+    /// Runs the filter over `segments`, as [`apply`](Self::apply), `metrics` holding the metrics
+    /// of each segment, as returned by
+    /// [`WhisperSession::segment_metrics`](crate::WhisperSession::segment_metrics).
+    ///
+    /// Segments made of one of the [`fillers`](Self::fillers) are also caught when their metrics
+    /// tell they hold no speech. Segments past the end of `metrics` are handled as by
+    /// [`apply`](Self::apply).
+    pub fn apply_with_metrics(
+        &self,
+        segments: Vec<WhisperSegment>,
+        metrics: &[WhisperSegmentMetrics],
+    ) -> (Vec<WhisperSegment>, WhisperFilterReport) {
+        let normalized: Vec<_> = segments
+            .iter()
+            .map(|segment| normalize(&segment.text))
+            .collect();
+
+        let mut report = WhisperFilterReport::default();
+        let mut kept = Vec::with_capacity(segments.len());
+
+        for (index, segment) in segments.into_iter().enumerate() {
+            let reasons = self.reasons(&segment, metrics.get(index), &normalized, index);
+
+            if reasons.is_empty() {
+                kept.push(segment);
+                continue;
+            }
+
+            if self.action == WhisperFilterAction::Mark {
+                kept.push(segment.clone());
+            }
+
+            report.filtered.push(WhisperFilteredSegment {
+                index,
+                segment,
+                reasons,
+            });
+        }
+
+        (kept, report)
+    }
+
+    /// Lists why the segment at `index` should be caught, `normalized` holding the normalized text
+    /// of every segment.
+    fn reasons(
+        &self,
+        segment: &WhisperSegment,
+        metrics: Option<&WhisperSegmentMetrics>,
+        normalized: &[String],
+        index: usize,
+    ) -> Vec<WhisperFilterReason> {
+        let mut reasons = vec![];
+        let text = &normalized[index];
+
+        if let Some(reason) = self.repetition(text) {
+            reasons.push(reason);
+        }
+
+        // Only the copies after the first one of a looping segment are caught
+        let copies = normalized[..index]
+            .iter()
+            .rev()
+            .take_while(|previous| !text.is_empty() && *previous == text)
+            .count()
+            + 1;
+        let total = copies
+            + normalized[index + 1..]
+                .iter()
+                .take_while(|next| !text.is_empty() && *next == text)
+                .count();
+        if copies > 1 && total >= self.min_repeats {
+            reasons.push(WhisperFilterReason::RepeatedSegment { count: total });
+        }
+
+        let no_speech = metrics.is_some_and(|metrics| metrics.is_no_speech(&self.no_speech));
+        let phrase = self
+            .hallucinations
+            .iter()
+            .chain(self.fillers.iter().filter(|_| no_speech))
+            .find(|p| normalize(p) == *text);
+        if let Some(phrase) = phrase {
+            reasons.push(WhisperFilterReason::Hallucination {
+                phrase: phrase.clone(),
+            });
+        }
+
+        // The rate of segments without a duration is unknown
+        let chars = segment.text.trim().chars().count();
+        let seconds = (segment.end - segment.start) as f32 / 100.0;
+        if chars > 0 && seconds > 0.0 {
+            let chars_per_second = chars as f32 / seconds;
+
+            if !(self.min_chars_per_second..=self.max_chars_per_second).contains(&chars_per_second)
+            {
+                reasons.push(WhisperFilterReason::CharsPerSecond(chars_per_second));
+            }
+        }
+
+        reasons
+    }
+
+    /// Looks for the longest run of consecutive copies of a phrase in `text`.
+    fn repetition(&self, text: &str) -> Option<WhisperFilterReason> {
+        let words: Vec<_> = text.split(' ').filter(|w| !w.is_empty()).collect();
+        // The phrase, its number of copies and the characters they span
+        let mut found: Option<(&[&str], usize, usize)> = None;
+
+        for size in 1..=self.max_ngram_size.min(words.len() / 2) {
+            for start in 0..words.len() - size {
+                let ngram = &words[start..start + size];
+                let count = words[start..]
+                    .chunks_exact(size)
+                    .take_while(|chunk| *chunk == ngram)
+                    .count();
+
+                // Characters spanned by the run, including the separating spaces
+                let chars =
+                    count * (ngram.iter().map(|w| w.chars().count()).sum::<usize>() + size) - 1;
+
+                if count >= self.min_repeats
+                    && chars >= self.min_repetition_chars
+                    && found.is_none_or(|(_, _, best)| chars > best)
+                {
+                    found = Some((ngram, count, chars));
+                }
+            }
+        }
+
+        found.map(|(ngram, count, _)| WhisperFilterReason::Repetition {
+            phrase: ngram.join(" "),
+            count,
+        })
+    }
+}
+
+/// Why a segment was caught by a [`WhisperSegmentFilter`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum WhisperFilterReason {
+    /// The segment repeats a phrase in a loop, `phrase` being normalized.
+    Repetition { phrase: String, count: usize },
+
+    /// The segment is one of `count` consecutive identical segments, and not the first one.
+    RepeatedSegment { count: usize },
+
+    /// The segment only contains a phrase Whisper is known to produce over silence, one of the
+    /// [`WhisperSegmentFilter::fillers`] only if its metrics tell it holds no speech.
+    Hallucination { phrase: String },
+
+    /// The segment has an implausible number of characters per second for its duration.
+    CharsPerSecond(f32),
+}
+
+/// A segment caught by a [`WhisperSegmentFilter`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WhisperFilteredSegment {
+    /// The index of the segment in the segments given to the filter.
+    pub index: usize,
+
+    /// The segment.
+    pub segment: WhisperSegment,
+
+    /// Every reason the segment was caught for.
+    pub reasons: Vec<WhisperFilterReason>,
+}
+
+/// The segments caught by a [`WhisperSegmentFilter`].
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WhisperFilterReport {
+    /// The caught segments, in order.
+    pub filtered: Vec<WhisperFilteredSegment>,
+}
+
+impl WhisperFilterReport {
+    /// Whether the segment at `index` in the segments given to the filter was caught.
+    pub fn is_filtered(&self, index: usize) -> bool {
+        self.filtered
+            .binary_search_by_key(&index, |filtered| filtered.index)
+            .is_ok()
+    }
+}
+
+/// Lowercases `text`, replacing punctuation by spaces and collapsing runs of whitespace.
+fn normalize(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -1,26 +1,106 @@
 use core::ffi::{c_char, c_int, CStr};
 use std::ffi::CString;
 use std::num::NonZeroUsize;
+use std::ops::Range;
 use std::ptr::null_mut;
 use std::slice;
 use std::sync::atomic::Ordering;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 
-use derive_more::{Deref, DerefMut};
+use derive_more::Deref;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tokio::sync::RwLock;
+use tracing::{info_span, Span};
 
 use whisper_cpp_sys::{
     whisper_context, whisper_context_params, whisper_free, whisper_free_state,
-    whisper_full_default_params, whisper_full_get_segment_text_from_state,
-    whisper_full_get_token_id_from_state, whisper_full_n_segments_from_state,
-    whisper_full_n_tokens_from_state, whisper_full_params, whisper_full_params__bindgen_ty_1,
-    whisper_full_params__bindgen_ty_2, whisper_full_with_state,
-    whisper_init_from_file_with_params_no_state, whisper_init_state, whisper_log_set,
+    whisper_full_default_params, whisper_full_get_segment_t0_from_state,
+    whisper_full_get_segment_t1_from_state, whisper_full_get_segment_text_from_state,
+    whisper_full_get_token_data_from_state, whisper_full_get_token_id_from_state,
+    whisper_full_get_token_p_from_state, whisper_full_lang_id_from_state,
+    whisper_full_n_segments_from_state, whisper_full_n_tokens_from_state, whisper_full_params,
+    whisper_full_params__bindgen_ty_1, whisper_full_params__bindgen_ty_2, whisper_full_with_state,
+    whisper_grammar_element, whisper_gretype, whisper_gretype_WHISPER_GRETYPE_ALT,
+    whisper_gretype_WHISPER_GRETYPE_CHAR, whisper_gretype_WHISPER_GRETYPE_CHAR_ALT,
+    whisper_gretype_WHISPER_GRETYPE_CHAR_NOT, whisper_gretype_WHISPER_GRETYPE_CHAR_RNG_UPPER,
+    whisper_gretype_WHISPER_GRETYPE_END, whisper_gretype_WHISPER_GRETYPE_RULE_REF,
+    whisper_init_from_file_with_params_no_state, whisper_init_state, whisper_is_multilingual,
+    whisper_lang_max_id, whisper_lang_str, whisper_lang_str_full, whisper_log_set,
+    whisper_n_text_ctx, whisper_sampling_strategy,
     whisper_sampling_strategy_WHISPER_SAMPLING_BEAM_SEARCH,
-    whisper_sampling_strategy_WHISPER_SAMPLING_GREEDY, whisper_state,
+    whisper_sampling_strategy_WHISPER_SAMPLING_GREEDY, whisper_set_mel_with_state, whisper_state,
+    whisper_token_beg, whisper_token_data, whisper_token_eot, whisper_tokenize,
 };
 
+use crate::logging::capture_errors;
+
+pub use batch::{BatchError, BatchResult, BatchResults, BatchTranscriber};
+pub use bilingual::{align_bilingual, WhisperBilingualCue};
+#[cfg(feature = "eval")]
+pub use eval::{
+    align_chars, align_words, format_alignment, EvalAlignment, EvalCounts, EvalEntry, EvalError,
+    EvalManifest, EvalReport, EvalResult, Evaluator,
+};
+pub use filter::{
+    WhisperFilterAction, WhisperFilterReason, WhisperFilterReport, WhisperFilteredSegment,
+    WhisperSegmentFilter,
+};
+pub use logging::{
+    set_logger, TracingLevels, WhisperErrorLog, WhisperLogLevel, WhisperLogRecord, WhisperLogger,
+    LOG_TARGET,
+};
+pub use mel::{WhisperMel, SAMPLE_RATE};
+pub use memory::{WhisperMemoryParams, WhisperMemoryUsage};
+pub use metrics::{compression_ratio, WhisperQualityThresholds, WhisperSegmentMetrics};
+pub use model_file::WhisperModelFileError;
+pub use model_info::{
+    GgmlType, WhisperHparams, WhisperMelFilters, WhisperModelInfo, WhisperTensorInfo,
+};
+#[cfg(feature = "eval")]
+pub use normalize::{set_english_spellings, WhisperTextNormalizer};
+pub use output::{format_srt, format_text, format_timestamp, format_vtt};
+pub use pool::{PooledSession, SessionPool};
+pub use preprocess::{
+    WhisperAudioStats, WhisperPreprocessError, WhisperPreprocessReport, WhisperPreprocessStep,
+    WhisperPreprocessor,
+};
+pub use presets::{
+    WhisperParamsConfig, WhisperParamsOverrides, WhisperPreset, WhisperPresetParseError,
+};
+pub use quantize::{
+    quantize, quantize_with_progress, WhisperQuantType, WhisperQuantizeError,
+    WhisperQuantizeProgress, WhisperQuantizeStats,
+};
+#[cfg(feature = "registry")]
+pub use registry::{ModelManifest, ModelManifestEntry, ModelRegistry, ModelRegistryError};
+#[cfg(feature = "wav")]
+pub use wav::{decode_wav, read_wav, WavError};
+
+mod batch;
+mod bilingual;
+#[cfg(feature = "eval")]
+mod eval;
+mod filter;
+mod logging;
+mod mel;
+mod memory;
+mod metrics;
+mod model_file;
+mod model_info;
+#[cfg(feature = "eval")]
+mod normalize;
+mod output;
+mod pool;
+mod preprocess;
+mod presets;
+mod quantize;
+#[cfg(feature = "registry")]
+mod registry;
+#[cfg(feature = "wav")]
+mod wav;
+
 /// Boolean indicating if a logger has already been set using [`whisper_log_set`].
 static LOGGER_SET: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
 
@@ -37,40 +117,130 @@ fn set_log() {
     }
 }
 
-#[derive(Clone, Deref, DerefMut)]
-struct WhisperContext(*mut whisper_context);
+/// Returns the short code of a language, such as `"de"`, given its id.
+#[doc(alias = "whisper_lang_str")]
+pub fn language_code(id: i32) -> Option<&'static str> {
+    language_str(id, whisper_lang_str)
+}
+
+/// Returns the full English name of a language, such as `"german"`, given its id.
+#[doc(alias = "whisper_lang_str_full")]
+pub fn language_name(id: i32) -> Option<&'static str> {
+    language_str(id, whisper_lang_str_full)
+}
+
+/// Returns the short code of every language, ordered by id.
+pub fn language_codes() -> Vec<&'static str> {
+    let max_id = unsafe { whisper_lang_max_id() };
+    (0..=max_id).filter_map(language_code).collect()
+}
+
+fn language_str(
+    id: i32,
+    lang_str: unsafe extern "C" fn(c_int) -> *const c_char,
+) -> Option<&'static str> {
+    if !(0..=unsafe { whisper_lang_max_id() }).contains(&id) {
+        return None;
+    }
+
+    // SAFETY: whisper.cpp returns pointers to static strings, or null for unknown ids
+    let res = unsafe { lang_str(id) };
+    if res.is_null() {
+        return None;
+    }
+
+    unsafe { CStr::from_ptr(res) }.to_str().ok()
+}
+
+/// Owning handle to a [`whisper_context`], which holds the model weights and vocabulary.
+///
+/// There must only ever be one handle per context, as it is freed when the handle is dropped, so
+/// this must **NOT** implement [`Clone`]. Share it through an [`Arc`] instead.
+#[derive(Deref)]
+struct WhisperContext {
+    #[deref]
+    raw: *mut whisper_context,
+
+    /// The hyperparameters of the model, as read from its file.
+    hparams: WhisperHparams,
+
+    /// The memory used by the model itself, see [`WhisperModel::memory_usage`].
+    memory: WhisperMemoryUsage,
 
+    /// The mel filters of the model, see [`WhisperModel::mel_filters`].
+    mel_filters: WhisperMelFilters,
+}
+
+// SAFETY: a context is not bound to the thread that created it, whisper.cpp keeps no thread
+// local data about it.
 unsafe impl Send for WhisperContext {}
 
+// SAFETY: the `*_with_state` functions only read from the context, writing exclusively to the
+// provided state, which is what allows whisper.cpp to run many states of the same context in
+// parallel. The only functions writing to the context are the ones using its default state, which
+// this crate never initializes.
 unsafe impl Sync for WhisperContext {}
 
+impl WhisperContext {
+    /// Whether the model was trained on many languages, or only on English.
+    fn is_multilingual(&self) -> bool {
+        unsafe { whisper_is_multilingual(self.raw) != 0 }
+    }
+}
+
 impl Drop for WhisperContext {
     #[doc(alias = "whisper_free")]
     fn drop(&mut self) {
-        unsafe { whisper_free(self.0) }
+        unsafe { whisper_free(self.raw) }
     }
 }
 
 #[derive(Debug, Error)]
 pub enum WhisperError {
-    #[error("failed to initialize the whisper context")]
-    Initialization,
+    #[error("failed to initialize the whisper context ({0})")]
+    Initialization(WhisperErrorLog),
     #[error("failed to initialize a new whisper context state")]
     SessionInitialization(#[from] WhisperSessionError),
+    #[error("invalid model file: {0}")]
+    ModelFile(#[from] WhisperModelFileError),
 }
 
 pub struct WhisperModel {
-    context: Arc<RwLock<WhisperContext>>,
+    /// The context new runs use, which [`WhisperModel::reload`] replaces. Every [`SessionState`]
+    /// holds on to the context it was created from, so a replaced context is only freed once its
+    /// last state is.
+    context: Arc<RwLock<Arc<WhisperContext>>>,
+
+    /// The parent span of the [`WhisperSession`]s of this model, see [`WhisperLogRecord::span`].
+    span: Span,
 }
 
 impl WhisperModel {
     /// Loads a new *ggml* *whisper* model, given its file path. If a device (GPU) index is
     /// provided, the model is loaded into the GPU.
+    ///
+    /// The file is first read with [`WhisperModelInfo`], to check that it is a complete *ggml*
+    /// model, before being handed to *whisper.cpp*.
     #[doc(alias = "whisper_init_from_file_with_params_no_state")]
     pub fn new_from_file<P>(model_path: P, device: Option<u32>) -> Result<Self, WhisperError>
     where
         P: AsRef<std::path::Path>,
     {
+        let model_path = model_path.as_ref();
+        let span = info_span!("whisper_model", path = %model_path.display());
+        let context = span.in_scope(|| Self::load(model_path, device))?;
+
+        Ok(Self {
+            context: Arc::new(RwLock::new(Arc::new(context))),
+            span,
+        })
+    }
+
+    #[doc(alias = "whisper_init_from_file_with_params_no_state")]
+    fn load(
+        model_path: &std::path::Path,
+        device: Option<u32>,
+    ) -> Result<WhisperContext, WhisperError> {
         set_log();
 
         let params = whisper_context_params {
@@ -78,24 +248,74 @@ impl WhisperModel {
             gpu_device: device.unwrap_or(0) as i32,
         };
 
-        let path_bytes = model_path
-            .as_ref()
-            .to_string_lossy()
-            .to_string()
-            .into_bytes();
-        let c_str = CString::new(path_bytes).unwrap();
-
-        let context =
-            unsafe { whisper_init_from_file_with_params_no_state(c_str.as_ptr(), params) };
+        let (c_str, info) = model_file::validate(model_path)?;
+        let (context, log) = capture_errors(|| unsafe {
+            whisper_init_from_file_with_params_no_state(c_str.as_ptr(), params)
+        });
 
         if context.is_null() {
-            return Err(WhisperError::Initialization);
+            return Err(WhisperError::Initialization(log));
         }
 
-        Ok(Self {
-            context: Arc::new(RwLock::new(WhisperContext(context))),
+        Ok(WhisperContext {
+            raw: context,
+            hparams: info.hparams,
+            memory: WhisperMemoryUsage::model(&info),
+            mel_filters: info.mel_filters,
         })
     }
+
+    /// Replaces the model in place with the one at `model_path`, such as a newer or differently
+    /// quantized version, for this model, its clones and every session created from them.
+    ///
+    /// Runs already in progress finish on the previous model, and every session switches to the
+    /// new one on its next run, which starts without the text context of the previous model. The
+    /// previous model is freed once each of its sessions has switched or been dropped, so idle
+    /// sessions should be moved right away with [`WhisperSession::refresh`] or
+    /// [`SessionPool::refresh`]. If loading fails, the current model is kept.
+    ///
+    /// The file is loaded on [`tokio`]'s blocking thread pool.
+    pub async fn reload<P>(&self, model_path: P, device: Option<u32>) -> Result<(), WhisperError>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        let model_path = model_path.as_ref().to_path_buf();
+        let span = self.span.clone();
+        let (context, model_path) = tokio::task::spawn_blocking(move || {
+            let context = span.in_scope(|| Self::load(&model_path, device));
+            context.map(|context| (context, model_path))
+        })
+        .await
+        .expect("loading a model does not panic")?;
+
+        *self.context.write().await = Arc::new(context);
+        self.reloaded(&model_path);
+
+        Ok(())
+    }
+
+    /// Synchronous version of [`WhisperModel::reload`], loading the model on the calling thread.
+    ///
+    /// ## Panic
+    /// Panics if called from within an asynchronous execution context.
+    pub fn reload_blocking<P>(&self, model_path: P, device: Option<u32>) -> Result<(), WhisperError>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        let model_path = model_path.as_ref();
+        let context = self.span.in_scope(|| Self::load(model_path, device))?;
+
+        *self.context.blocking_write() = Arc::new(context);
+        self.reloaded(model_path);
+
+        Ok(())
+    }
+
+    fn reloaded(&self, model_path: &std::path::Path) {
+        self.span
+            .record("path", tracing::field::display(model_path.display()));
+        tracing::info!(parent: &self.span, "reloaded the model");
+    }
     /*
     #[doc(alias = "whisper_init_from_buffer_with_params")]
     pub fn new_from_buffer(buffer: &[u8], use_gpu: bool) -> WhisperModel {
@@ -109,7 +329,69 @@ impl WhisperModel {
      */
 
     pub async fn new_session(&self) -> Result<WhisperSession, WhisperError> {
-        Ok(WhisperSession::new(self.context.clone()).await?)
+        Ok(WhisperSession::new(self.context.clone(), self.session_span()).await?)
+    }
+
+    /// Synchronous version of [`WhisperModel::new_session`], for use outside of an async runtime.
+    ///
+    /// ## Panic
+    /// Panics if called from within an asynchronous execution context.
+    pub fn new_session_blocking(&self) -> Result<WhisperSession, WhisperError> {
+        Ok(WhisperSession::new_blocking(
+            self.context.clone(),
+            self.session_span(),
+        )?)
+    }
+
+    /// Whether the model was trained on many languages, or only on English.
+    #[doc(alias = "whisper_is_multilingual")]
+    pub async fn is_multilingual(&self) -> bool {
+        self.context.read().await.is_multilingual()
+    }
+
+    /// Synchronous version of [`WhisperModel::is_multilingual`], for use outside of an async
+    /// runtime.
+    ///
+    /// ## Panic
+    /// Panics if called from within an asynchronous execution context.
+    #[doc(alias = "whisper_is_multilingual")]
+    pub fn is_multilingual_blocking(&self) -> bool {
+        self.context.blocking_read().is_multilingual()
+    }
+
+    /// The memory used by the model, without its sessions, see [`WhisperMemoryUsage`].
+    pub async fn memory_usage(&self) -> WhisperMemoryUsage {
+        self.context.read().await.memory
+    }
+
+    /// Synchronous version of [`WhisperModel::memory_usage`], for use outside of an async runtime.
+    ///
+    /// ## Panic
+    /// Panics if called from within an asynchronous execution context.
+    pub fn memory_usage_blocking(&self) -> WhisperMemoryUsage {
+        self.context.blocking_read().memory
+    }
+
+    /// The mel filters of the model, to compute the spectrograms it takes with [`WhisperMel::new`].
+    pub async fn mel_filters(&self) -> WhisperMelFilters {
+        self.context.read().await.mel_filters.clone()
+    }
+
+    /// Synchronous version of [`WhisperModel::mel_filters`], for use outside of an async runtime.
+    ///
+    /// ## Panic
+    /// Panics if called from within an asynchronous execution context.
+    pub fn mel_filters_blocking(&self) -> WhisperMelFilters {
+        self.context.blocking_read().mel_filters.clone()
+    }
+
+    /// The span of this model, which every message logged while loading it is attributed to.
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    fn session_span(&self) -> Span {
+        info_span!(parent: &self.span, "whisper_session")
     }
 }
 
@@ -121,17 +403,25 @@ impl Clone for WhisperModel {
     fn clone(&self) -> Self {
         Self {
             context: self.context.clone(),
+            span: self.span.clone(),
         }
     }
 }
 
-#[derive(Clone, Deref, DerefMut)]
+/// Owning handle to a [`whisper_state`], which holds the buffers, caches and results of a run.
+///
+/// There must only ever be one handle per state, as it is freed when the handle is dropped, so
+/// this must **NOT** implement [`Clone`].
+///
+/// Every function taking a state writes to it, so this is not [`Sync`]; concurrent access must be
+/// synchronized externally, which [`WhisperSession`] does with a [`Mutex`].
+#[derive(Deref)]
 struct WhisperState(*mut whisper_state);
 
+// SAFETY: a state is not bound to the thread that created it, whisper.cpp keeps no thread local
+// data about it.
 unsafe impl Send for WhisperState {}
 
-unsafe impl Sync for WhisperState {}
-
 impl Drop for WhisperState {
     #[doc(alias = "whisper_free_state")]
     fn drop(&mut self) {
@@ -139,44 +429,314 @@ impl Drop for WhisperState {
     }
 }
 
+/// A [`WhisperState`], with what its session needs to carry text context over from one run to the
+/// next.
+#[derive(Deref)]
+struct SessionState {
+    #[deref]
+    state: WhisperState,
+
+    /// The context the state was created for, which it can only be used with.
+    ///
+    /// Declared after `state` so it is dropped last, a state must never outlive its context.
+    context: Arc<WhisperContext>,
+
+    /// The ids of the tokens of the model vocabulary which are neither text nor timestamps.
+    control_tokens: Range<i32>,
+
+    /// The largest number of tokens of text context the model uses.
+    max_context: usize,
+
+    /// The tokens given as text context to the last run.
+    prompt: Vec<i32>,
+
+    /// The text context the next run should start from, instead of the one left by the previous
+    /// run, whose results are then ignored.
+    ///
+    /// Only taken once the next run is about to start, so it survives the runs failing before that.
+    seed: Option<Vec<i32>>,
+
+    /// The most decoders and samples of the runs so far, which the buffers of the state grew to.
+    memory_params: WhisperMemoryParams,
+}
+
+impl SessionState {
+    #[doc(alias = "whisper_init_state")]
+    fn new(context: Arc<WhisperContext>) -> Result<Self, WhisperSessionError> {
+        let (state, log) = capture_errors(|| unsafe { whisper_init_state(context.raw) });
+
+        if state.is_null() {
+            return Err(WhisperSessionError::Initialization(log));
+        }
+
+        // The control tokens sit between the end of transcript token and the first timestamp
+        // token, and whisper.cpp only takes up to half of the text context as prompt
+        let (control_tokens, max_context) = unsafe {
+            (
+                whisper_token_eot(context.raw)..whisper_token_beg(context.raw),
+                whisper_n_text_ctx(context.raw) as usize / 2,
+            )
+        };
+
+        Ok(Self {
+            state: WhisperState(state),
+            context,
+            control_tokens,
+            max_context,
+            prompt: vec![],
+            seed: None,
+            memory_params: WhisperMemoryParams {
+                decoders: 1,
+                samples: 0,
+            },
+        })
+    }
+
+    /// The tokens the last run leaves as text context for the next one: the tail of its prompt,
+    /// followed by the text and timestamp tokens it generated.
+    fn carried_context(&self) -> Vec<i32> {
+        let mut tokens = self.prompt.clone();
+
+        unsafe {
+            for segment in 0..whisper_full_n_segments_from_state(self.0) {
+                for token in 0..whisper_full_n_tokens_from_state(self.0, segment) {
+                    let id = whisper_full_get_token_id_from_state(self.0, segment, token);
+
+                    if !self.control_tokens.contains(&id) {
+                        tokens.push(id);
+                    }
+                }
+            }
+        }
+
+        tokens.drain(..tokens.len().saturating_sub(self.max_context));
+        tokens
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum WhisperSessionError {
-    #[error("failed to initialize whisper context state")]
-    Initialization,
-    #[error("failed to process audio samples")]
-    Internal,
+    #[error("failed to initialize whisper context state ({0})")]
+    Initialization(WhisperErrorLog),
+    #[error("failed to tokenize the initial prompt ({0})")]
+    Tokenization(WhisperErrorLog),
+    #[error("segment {segment} is out of range, the last run has {count} segments")]
+    SegmentOutOfRange { segment: u32, count: u32 },
+    #[error("token {token} of segment {segment} is out of range, the segment has {count} tokens")]
+    TokenOutOfRange {
+        segment: u32,
+        token: u32,
+        count: u32,
+    },
+    #[error("failed to compute the log mel spectrogram (code {code}; {log})")]
+    MelSpectrogram { code: c_int, log: WhisperErrorLog },
+    #[error("the spectrogram has {found} mel bands, but the model takes {expected}")]
+    MelBands { expected: usize, found: usize },
+    #[error("the model only knows English, it cannot translate")]
+    NotMultilingual,
+    #[error("failed to auto-detect the language (code {code}; {log})")]
+    LanguageDetection { code: c_int, log: WhisperErrorLog },
+    #[error("failed to initialize the decoders (code {code}; {log})")]
+    DecoderInitialization { code: c_int, log: WhisperErrorLog },
+    #[error("audio_ctx is larger than the model's audio context (code {code}; {log})")]
+    AudioContext { code: c_int, log: WhisperErrorLog },
+    #[error("failed to encode the audio (code {code}; {log})")]
+    Encode { code: c_int, log: WhisperErrorLog },
+    #[error("failed to decode the audio (code {code}; {log})")]
+    Decode { code: c_int, log: WhisperErrorLog },
+    #[error("whisper_full_with_state failed (code {code}; {log})")]
+    Full { code: c_int, log: WhisperErrorLog },
     #[error("failed to convert  WhisperParams into whisper_full_params: {0}")]
     Params(#[from] WhisperParamsError),
     #[error("string retrieved from whisper.ccp is invalid: {0}")]
     CStr(#[from] std::str::Utf8Error),
+    #[error("the blocking transcription task failed: {0}")]
+    Task(#[from] tokio::task::JoinError),
+}
+
+/// The input of a run, see [`WhisperSession::full`].
+#[derive(Clone, Copy)]
+enum Audio<'a> {
+    Samples(&'a [f32]),
+    Mel(&'a WhisperMel),
+}
+
+impl Audio<'_> {
+    /// Number of samples of the audio, as counted by [`WhisperMemoryParams::samples`].
+    fn samples(&self) -> usize {
+        match self {
+            Self::Samples(samples) => samples.len(),
+            Self::Mel(mel) => mel.frames() * mel::HOP_LENGTH,
+        }
+    }
+}
+
+impl WhisperSessionError {
+    /// Maps a non-zero return code of [`whisper_full_with_state`] to its error.
+    fn from_full_code(code: c_int, log: WhisperErrorLog) -> Self {
+        match code {
+            -1 | -2 => Self::MelSpectrogram { code, log },
+            -3 => Self::LanguageDetection { code, log },
+            -4 => Self::DecoderInitialization { code, log },
+            -5 => Self::AudioContext { code, log },
+            -6 => Self::Encode { code, log },
+            -7 | -8 => Self::Decode { code, log },
+            _ => Self::Full { code, log },
+        }
+    }
 }
 
 // Due to using the with state variant of each function, we can use sessions across multiple
 // threads, even if the sessions have the same context.
 pub struct WhisperSession {
-    context: Arc<RwLock<WhisperContext>>,
-    state: WhisperState,
+    /// The state is shared with the blocking task spawned by [`WhisperSession::advance`], which
+    /// keeps running to completion even if the future driving it is dropped.
+    state: Arc<Mutex<SessionState>>,
+
+    /// The current context of the model, which the state is recreated for when it changes, see
+    /// [`WhisperModel::reload`].
+    context: Arc<RwLock<Arc<WhisperContext>>>,
+
+    /// The span every message logged while running this session is attributed to.
+    span: Span,
 }
 
 impl WhisperSession {
     #[doc(alias = "whisper_init_state")]
-    async fn new(context: Arc<RwLock<WhisperContext>>) -> Result<Self, WhisperSessionError> {
-        let state;
-        {
-            let locked = context.read().await;
-            unsafe { state = whisper_init_state(locked.0) };
-        }
+    async fn new(
+        context: Arc<RwLock<Arc<WhisperContext>>>,
+        span: Span,
+    ) -> Result<Self, WhisperSessionError> {
+        let state = {
+            let current = context.read().await.clone();
+            span.in_scope(|| SessionState::new(current))?
+        };
 
-        if state.is_null() {
-            return Err(WhisperSessionError::Initialization);
-        }
+        Ok(Self {
+            state: Arc::new(Mutex::new(state)),
+            context,
+            span,
+        })
+    }
+
+    #[doc(alias = "whisper_init_state")]
+    fn new_blocking(
+        context: Arc<RwLock<Arc<WhisperContext>>>,
+        span: Span,
+    ) -> Result<Self, WhisperSessionError> {
+        let state = {
+            let current = context.blocking_read().clone();
+            span.in_scope(|| SessionState::new(current))?
+        };
 
         Ok(Self {
+            state: Arc::new(Mutex::new(state)),
             context,
-            state: WhisperState(state),
+            span,
         })
     }
 
+    /// Discards the results and text context of previous runs, so the next
+    /// [`WhisperSession::advance`] behaves as if this session was newly created.
+    ///
+    /// Until then, the session has no segments, and the functions reading them return errors, even
+    /// if that run fails.
+    pub fn reset(&mut self) {
+        self.state().seed = Some(vec![]);
+    }
+
+    /// Returns the tokens the next [`WhisperSession::advance`] will be given as text context,
+    /// unless [`WhisperParams::no_context`] is set.
+    ///
+    /// These can be handed to [`WhisperSession::set_context_tokens`] of another session, possibly
+    /// of another process, to resume the transcription of a stream there.
+    pub fn context_tokens(&self) -> Vec<i32> {
+        let state = self.state();
+        match &state.seed {
+            Some(seed) => seed.clone(),
+            None => state.carried_context(),
+        }
+    }
+
+    /// Replaces the text context of this session with `tokens`, as returned by
+    /// [`WhisperSession::context_tokens`], discarding the results of previous runs.
+    ///
+    /// The tokens are dropped if the session moves to a reloaded model before its next run, as they
+    /// belong to the vocabulary of the model it last ran on, see [`WhisperModel::reload`].
+    pub fn set_context_tokens(&mut self, tokens: Vec<i32>) {
+        self.state().seed = Some(tokens);
+    }
+
+    /// Moves this session to the current model if its model was reloaded since its last run,
+    /// freeing its state of the previous model now instead of on its next run.
+    ///
+    /// As when a run moves the session, its results and text context are discarded. The state is
+    /// created on [`tokio`]'s blocking thread pool.
+    #[doc(alias = "whisper_init_state")]
+    pub async fn refresh(&mut self) -> Result<(), WhisperSessionError> {
+        let context = self.context.read().await.clone();
+        let state = self.state.clone();
+        let span = self.span.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+            span.in_scope(|| Self::switch_context(context, &mut state))
+        })
+        .await??;
+
+        Ok(())
+    }
+
+    /// Synchronous version of [`WhisperSession::refresh`], creating the state on the calling
+    /// thread. This does not require an async runtime.
+    ///
+    /// ## Panic
+    /// Panics if called from within an asynchronous execution context.
+    #[doc(alias = "whisper_init_state")]
+    pub fn refresh_blocking(&mut self) -> Result<(), WhisperSessionError> {
+        let context = self.context.blocking_read().clone();
+        self.span
+            .in_scope(|| Self::switch_context(context, &mut self.state()))?;
+
+        Ok(())
+    }
+
+    /// Replaces `state` with a new one if `context` is not the one it was created for, which drops
+    /// its results, text context and seed. `state` is left as is if creating the new one fails.
+    fn switch_context(
+        context: Arc<WhisperContext>,
+        state: &mut SessionState,
+    ) -> Result<(), WhisperSessionError> {
+        if !Arc::ptr_eq(&state.context, &context) {
+            *state = SessionState::new(context)?;
+        }
+
+        Ok(())
+    }
+
+    /// The span of this session, which every message logged while running it is attributed to.
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    /// The estimated memory used by this session, not counting its model, given the most decoders
+    /// and samples of its runs so far.
+    ///
+    /// Use [`WhisperMemoryUsage::session`] to know how much a session will use once it runs with
+    /// other parameters.
+    pub fn memory_usage(&self) -> WhisperMemoryUsage {
+        let state = self.state();
+        WhisperMemoryUsage::session(&state.context.hparams, state.memory_params)
+    }
+
+    /// Locks the [`WhisperState`] of this session.
+    ///
+    /// This only blocks if a previous [`WhisperSession::advance`] was cancelled while its
+    /// transcription was still running, in which case it waits for that transcription to end.
+    fn state(&self) -> MutexGuard<'_, SessionState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
     /// Convert RAW PCM audio to log mel spectrogram.
     /// The resulting spectrogram is stored inside the default state of the provided whisper context.
     /// Returns 0 on success
@@ -193,15 +753,6 @@ impl WhisperSession {
         todo!()
     }
 
-    /// This can be used to set a custom log mel spectrogram inside the default state of the provided whisper context.
-    /// Use this instead of whisper_pcm_to_mel() if you want to provide your own log mel spectrogram.
-    /// n_mel must be 80
-    /// Returns 0 on success
-    #[doc(alias = "whisper_set_mel_with_state")]
-    pub fn set_mel(&self) {
-        todo!()
-    }
-
     /// Run the Whisper encoder on the log mel spectrogram stored inside the default state in the provided whisper context.
     /// Make sure to call whisper_pcm_to_mel() or whisper_set_mel() first.
     /// offset can be used to specify the offset of the first frame in the spectrogram.
@@ -238,51 +789,348 @@ impl WhisperSession {
 
     /// Run the entire model: PCM -> log mel spectrogram -> encoder -> decoder -> text.
     /// Uses the specified decoding strategy to obtain the text.
+    ///
+    /// Unless [`WhisperParams::no_context`] is set, the text of the previous run is given as
+    /// context, see [`WhisperSession::context_tokens`].
+    ///
+    /// The samples are copied and the computation is moved to [`tokio`]'s blocking thread pool, so
+    /// the calling runtime worker is free while the transcription runs. If the returned future is
+    /// dropped before completion, the transcription still runs to completion in the background.
     #[doc(alias = "whisper_full_with_state")]
     pub async fn advance(
         &mut self,
         params: WhisperParams,
         samples: &[f32],
     ) -> Result<(), WhisperSessionError> {
-        let locked = self.context.read().await;
-        let res = unsafe {
-            let (_vec, c_params) = params.c_params()?;
+        let context = self.context.read().await.clone();
+        let state = self.state.clone();
+        let samples = samples.to_vec();
+        let span = self.span.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+            let audio = Audio::Samples(&samples);
+            span.in_scope(|| Self::full(context, &mut state, params, audio))
+        })
+        .await?
+    }
+
+    /// Synchronous version of [`WhisperSession::advance`], running the computation on the
+    /// calling thread. This does not require an async runtime.
+    ///
+    /// ## Panic
+    /// Panics if called from within an asynchronous execution context.
+    #[doc(alias = "whisper_full_with_state")]
+    pub fn advance_blocking(
+        &mut self,
+        params: WhisperParams,
+        samples: &[f32],
+    ) -> Result<(), WhisperSessionError> {
+        let context = self.context.blocking_read().clone();
+        let audio = Audio::Samples(samples);
+        self.span
+            .in_scope(|| Self::full(context, &mut self.state(), params, audio))
+    }
+
+    /// Same as [`WhisperSession::advance`], but runs the model on a spectrogram computed
+    /// beforehand, such as with [`WhisperMel::new`], instead of audio samples.
+    ///
+    /// The frames of audio of the spectrogram are transcribed, as counted by
+    /// [`WhisperMel::frames`], leaving out its padding.
+    #[doc(alias = "whisper_set_mel_with_state")]
+    pub async fn advance_mel(
+        &mut self,
+        params: WhisperParams,
+        mel: &WhisperMel,
+    ) -> Result<(), WhisperSessionError> {
+        let context = self.context.read().await.clone();
+        let state = self.state.clone();
+        let mel = mel.clone();
+        let span = self.span.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+            let audio = Audio::Mel(&mel);
+            span.in_scope(|| Self::full(context, &mut state, params, audio))
+        })
+        .await?
+    }
+
+    /// Synchronous version of [`WhisperSession::advance_mel`], running the computation on the
+    /// calling thread. This does not require an async runtime.
+    ///
+    /// ## Panic
+    /// Panics if called from within an asynchronous execution context.
+    #[doc(alias = "whisper_set_mel_with_state")]
+    pub fn advance_mel_blocking(
+        &mut self,
+        params: WhisperParams,
+        mel: &WhisperMel,
+    ) -> Result<(), WhisperSessionError> {
+        let context = self.context.blocking_read().clone();
+        let audio = Audio::Mel(mel);
+        self.span
+            .in_scope(|| Self::full(context, &mut self.state(), params, audio))
+    }
+
+    /// Transcribes `samples`, then translates them to English, returning the two texts aligned by
+    /// time into bilingual cues, see [`align_bilingual`].
+    ///
+    /// [`WhisperParams::translate`] is ignored, and the translation runs without text context.
+    /// Afterwards the session carries over the text context of the transcription, as if only it
+    /// had run, and the results of both runs are discarded.
+    ///
+    /// Fails with [`WhisperSessionError::NotMultilingual`] if the model only knows English.
+    pub async fn advance_bilingual(
+        &mut self,
+        params: WhisperParams,
+        samples: &[f32],
+    ) -> Result<Vec<WhisperBilingualCue>, WhisperSessionError> {
+        if !self.context.read().await.is_multilingual() {
+            return Err(WhisperSessionError::NotMultilingual);
+        }
+        let (transcribe, translate) = Self::bilingual_params(params);
+
+        self.advance(transcribe, samples).await?;
+        let original = self.segments()?;
+        let context = self.context_tokens();
+
+        self.advance(translate, samples).await?;
+        let translation = self.segments()?;
+        self.set_context_tokens(context);
+
+        Ok(align_bilingual(&original, &translation))
+    }
+
+    /// Synchronous version of [`WhisperSession::advance_bilingual`], running the computation on
+    /// the calling thread. This does not require an async runtime.
+    ///
+    /// ## Panic
+    /// Panics if called from within an asynchronous execution context.
+    pub fn advance_bilingual_blocking(
+        &mut self,
+        params: WhisperParams,
+        samples: &[f32],
+    ) -> Result<Vec<WhisperBilingualCue>, WhisperSessionError> {
+        if !self.context.blocking_read().is_multilingual() {
+            return Err(WhisperSessionError::NotMultilingual);
+        }
+        let (transcribe, translate) = Self::bilingual_params(params);
+
+        self.advance_blocking(transcribe, samples)?;
+        let original = self.segments()?;
+        let context = self.context_tokens();
+
+        self.advance_blocking(translate, samples)?;
+        let translation = self.segments()?;
+        self.set_context_tokens(context);
+
+        Ok(align_bilingual(&original, &translation))
+    }
+
+    /// Splits `params` into the parameters of the transcription and of the translation run of
+    /// [`WhisperSession::advance_bilingual`].
+    fn bilingual_params(mut params: WhisperParams) -> (WhisperParams, WhisperParams) {
+        params.translate = false;
+
+        let mut translate = params.clone();
+        translate.translate = true;
+        translate.no_context = true;
+
+        (params, translate)
+    }
+
+    /// Runs the model, starting from the text context in the seed of `state` if set, or else from
+    /// the one left by the previous run.
+    ///
+    /// The text context is always passed explicitly as prompt tokens, so it is known to
+    /// [`WhisperSession::context_tokens`] and never taken from the internal state of whisper.cpp.
+    ///
+    /// If `context` is not the one `state` was created for, the model was reloaded and the state is
+    /// replaced by a new one, dropping the text context left by the previous model, as well as the
+    /// seed, whose tokens belong to the vocabulary of the previous model.
+    ///
+    /// Until whisper.cpp starts running, failures leave the results, text context and seed of
+    /// `state` untouched.
+    #[doc(alias = "whisper_full_with_state")]
+    fn full(
+        context: Arc<WhisperContext>,
+        state: &mut SessionState,
+        mut params: WhisperParams,
+        audio: Audio,
+    ) -> Result<(), WhisperSessionError> {
+        if let Audio::Mel(mel) = audio {
+            let expected = context.hparams.n_mels.max(0) as usize;
+            if mel.n_mel() != expected {
+                return Err(WhisperSessionError::MelBands {
+                    expected,
+                    found: mel.n_mel(),
+                });
+            }
+        }
+
+        Self::switch_context(context.clone(), state)?;
+
+        if params.translate && !context.is_multilingual() {
+            tracing::warn!("the model only knows English, the audio is transcribed instead");
+        }
+
+        let carried = match &state.seed {
+            _ if params.no_context => vec![],
+            Some(seed) => seed.clone(),
+            None => state.carried_context(),
+        };
+
+        // whisper.cpp ignores the initial prompt when given prompt tokens
+        if params.prompt_tokens.is_empty() && !params.initial_prompt.is_empty() {
+            params.prompt_tokens = Self::tokenize(&context, &params.initial_prompt)?;
+        }
+
+        params.prompt_tokens.extend(carried);
+        params.no_context = true;
+
+        let memory_params = &mut state.memory_params;
+        memory_params.decoders = memory_params
+            .decoders
+            .max(memory::decoders(&params.strategy));
+        memory_params.samples = memory_params.samples.max(audio.samples());
+
+        let samples = match audio {
+            Audio::Samples(samples) => samples,
+            Audio::Mel(mel) => {
+                // whisper.cpp only decodes the frames it computed itself, unless given a duration
+                if params.duration_ms == 0 {
+                    params.duration_ms =
+                        (mel.frames() as u32 * 10).saturating_sub(params.offset_ms);
+                }
+
+                let (res, log) = capture_errors(|| unsafe {
+                    whisper_set_mel_with_state(
+                        context.raw,
+                        state.0,
+                        mel.data().as_ptr(),
+                        mel.n_len() as c_int,
+                        mel.n_mel() as c_int,
+                    )
+                });
+
+                if res != 0 {
+                    return Err(WhisperSessionError::MelSpectrogram { code: res, log });
+                }
+
+                // No samples make whisper.cpp run on the spectrogram set above
+                &[]
+            }
+        };
+
+        let (_storage, c_params) = unsafe { params.c_params()? };
+
+        // whisper.cpp discards the results of the previous run from here on, even if it fails
+        state.seed = None;
+        state.prompt = params.prompt_tokens.clone();
+
+        let (res, log) = capture_errors(|| unsafe {
             whisper_full_with_state(
-                locked.0,
-                self.state.0,
+                context.raw,
+                state.0,
                 c_params,
                 samples.as_ptr(),
                 samples.len() as c_int,
             )
-        };
+        });
 
         if res != 0 {
-            return Err(WhisperSessionError::Internal);
+            return Err(WhisperSessionError::from_full_code(res, log));
         }
 
         Ok(())
     }
 
+    #[doc(alias = "whisper_tokenize")]
+    fn tokenize(context: &WhisperContext, text: &str) -> Result<Vec<i32>, WhisperSessionError> {
+        let text = CString::new(text).map_err(WhisperParamsError::from)?;
+
+        // Every token is at least one byte long
+        let mut tokens = vec![0; text.as_bytes().len()];
+        let (res, log) = capture_errors(|| unsafe {
+            whisper_tokenize(
+                context.raw,
+                text.as_ptr(),
+                tokens.as_mut_ptr(),
+                tokens.len() as c_int,
+            )
+        });
+
+        if res < 0 {
+            return Err(WhisperSessionError::Tokenization(log));
+        }
+
+        tokens.truncate(res as usize);
+        Ok(tokens)
+    }
+
     /// Number of generated text segments.
     /// A segment can be a few words, a sentence, or even a paragraph.
     #[doc(alias = "whisper_full_n_segments_from_state")]
     pub fn segment_count(&self) -> u32 {
-        let res = unsafe { whisper_full_n_segments_from_state(self.state.0) };
+        let state = self.state();
+        if state.seed.is_some() {
+            return 0;
+        }
+
+        let res = unsafe { whisper_full_n_segments_from_state(state.0) };
 
         res as u32
     }
 
-    /// Get the language id associated with the [`WhisperSession`].
+    /// Get the language id associated with the [`WhisperSession`], which is the detected one if
+    /// the language was set to `"auto"`.
+    ///
+    /// See [`language_code`] and [`language_name`].
     #[doc(alias = "whisper_full_lang_id_from_state")]
-    pub fn lang_id(&self) {
-        todo!()
+    pub fn lang_id(&self) -> i32 {
+        unsafe { whisper_full_lang_id_from_state(self.state().0) }
     }
 
-    /// Get the start and end time of the specified segment.
+    /// Returns an error unless `segment` is less than [`WhisperSession::segment_count`].
+    ///
+    /// whisper.cpp does not check the indices given to the functions reading the results, so
+    /// these must all be checked first.
+    fn check_segment(&self, segment: u32) -> Result<(), WhisperSessionError> {
+        let count = self.segment_count();
+        if segment >= count {
+            return Err(WhisperSessionError::SegmentOutOfRange { segment, count });
+        }
+
+        Ok(())
+    }
+
+    /// Returns an error unless `token` is less than the [`WhisperSession::token_count`] of
+    /// `segment`.
+    fn check_token(&self, segment: u32, token: u32) -> Result<(), WhisperSessionError> {
+        let count = self.token_count(segment)?;
+        if token >= count {
+            return Err(WhisperSessionError::TokenOutOfRange {
+                segment,
+                token,
+                count,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Get the start and end time of the specified segment, in centiseconds.
     #[doc(alias = "whisper_full_get_segment_t0_from_state")]
     #[doc(alias = "whisper_full_get_segment_t1_from_state")]
-    pub fn segment_time(&self) {
-        todo!()
+    pub fn segment_time(&self, segment: u32) -> Result<Range<i64>, WhisperSessionError> {
+        self.check_segment(segment)?;
+        let state = self.state();
+
+        Ok(unsafe {
+            whisper_full_get_segment_t0_from_state(state.0, segment as c_int)
+                ..whisper_full_get_segment_t1_from_state(state.0, segment as c_int)
+        })
     }
 
     /// Get whether the next segment is predicted as a speaker turn.
@@ -294,11 +1142,17 @@ impl WhisperSession {
     /// Get the text of the specified segment.
     #[doc(alias = "whisper_full_get_segment_text_from_state")]
     pub fn segment_text(&self, segment: u32) -> Result<String, WhisperSessionError> {
+        self.check_segment(segment)?;
+
+        let state = self.state();
         let text = unsafe {
-            let res = whisper_full_get_segment_text_from_state(self.state.0, segment as c_int);
+            let res = whisper_full_get_segment_text_from_state(state.0, segment as c_int);
 
             if res.is_null() {
-                return Err(WhisperSessionError::Internal);
+                return Err(WhisperSessionError::SegmentOutOfRange {
+                    segment,
+                    count: whisper_full_n_segments_from_state(state.0) as u32,
+                });
             }
 
             CStr::from_ptr(res.cast_mut())
@@ -309,10 +1163,11 @@ impl WhisperSession {
 
     /// Get number of tokens in the specified segment.
     #[doc(alias = "whisper_full_n_tokens_from_state")]
-    pub fn token_count(&self, segment: u32) -> u32 {
-        let res = unsafe { whisper_full_n_tokens_from_state(self.state.0, segment as c_int) };
+    pub fn token_count(&self, segment: u32) -> Result<u32, WhisperSessionError> {
+        self.check_segment(segment)?;
+        let res = unsafe { whisper_full_n_tokens_from_state(self.state().0, segment as c_int) };
 
-        res as u32
+        Ok(res as u32)
     }
 
     /// Get the token text of the specified token in the specified segment.
@@ -323,23 +1178,55 @@ impl WhisperSession {
 
     /// Get the token id of the specified token in the specified segment.
     #[doc(alias = "whisper_full_get_token_id_from_state")]
-    pub fn token_id(&self, segment: u32, token: u32) -> i32 {
-        unsafe {
-            whisper_full_get_token_id_from_state(self.state.0, segment as c_int, token as c_int)
-        }
+    pub fn token_id(&self, segment: u32, token: u32) -> Result<i32, WhisperSessionError> {
+        self.check_token(segment, token)?;
+
+        Ok(unsafe {
+            whisper_full_get_token_id_from_state(self.state().0, segment as c_int, token as c_int)
+        })
     }
 
     /// Get token data for the specified token in the specified segment.
     /// This contains probabilities, timestamps, etc.
     #[doc(alias = "whisper_full_get_token_data_from_state")]
-    pub fn token_data(&self) {
-        todo!()
+    pub fn token_data(
+        &self,
+        segment: u32,
+        token: u32,
+    ) -> Result<WhisperTokenData, WhisperSessionError> {
+        self.check_token(segment, token)?;
+        let data = unsafe {
+            whisper_full_get_token_data_from_state(self.state().0, segment as c_int, token as c_int)
+        };
+
+        Ok(data.into())
     }
 
     /// Get the probability of the specified token in the specified segment.
     #[doc(alias = "whisper_full_get_token_p_from_state")]
-    pub fn token_probability(&self) {
-        todo!()
+    pub fn token_probability(&self, segment: u32, token: u32) -> Result<f32, WhisperSessionError> {
+        self.check_token(segment, token)?;
+
+        Ok(unsafe {
+            whisper_full_get_token_p_from_state(self.state().0, segment as c_int, token as c_int)
+        })
+    }
+
+    /// Get the quality metrics of the specified segment, computed from the data of its tokens.
+    pub fn segment_metrics(
+        &self,
+        segment: u32,
+    ) -> Result<WhisperSegmentMetrics, WhisperSessionError> {
+        let text = self.segment_text(segment)?;
+        let tokens = (0..self.token_count(segment)?)
+            .map(|token| self.token_data(segment, token))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(WhisperSegmentMetrics::new(
+            &text,
+            &tokens,
+            self.state().control_tokens.clone(),
+        ))
     }
 
     /// Returns the decoded text of the last segment encoding.
@@ -352,15 +1239,171 @@ impl WhisperSession {
 
         Ok(res)
     }
+
+    /// Returns the text and times of every segment of the last run.
+    pub fn segments(&self) -> Result<Vec<WhisperSegment>, WhisperSessionError> {
+        (0..self.segment_count())
+            .map(|segment| {
+                let time = self.segment_time(segment)?;
+
+                Ok(WhisperSegment {
+                    text: self.segment_text(segment)?,
+                    start: time.start,
+                    end: time.end,
+                })
+            })
+            .collect()
+    }
+}
+
+/// A segment of text generated by a [`WhisperSession`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WhisperSegment {
+    /// The text of the segment.
+    pub text: String,
+    /// The start time of the segment, in centiseconds.
+    pub start: i64,
+    /// The end time of the segment, in centiseconds.
+    pub end: i64,
+}
+
+/// The data of a token generated by a [`WhisperSession`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WhisperTokenData {
+    /// The id of the token.
+    pub id: i32,
+    /// The id of the most likely timestamp token at this position.
+    pub timestamp_id: i32,
+    /// The probability of the token.
+    pub probability: f32,
+    /// The log probability of the token.
+    pub log_probability: f32,
+    /// The probability of the most likely timestamp token.
+    pub timestamp_probability: f32,
+    /// The sum of the probabilities of every timestamp token.
+    pub timestamp_probability_sum: f32,
+    /// The start time of the token, in centiseconds, if token timestamps were enabled.
+    pub start: i64,
+    /// The end time of the token, in centiseconds, if token timestamps were enabled.
+    pub end: i64,
+    /// The voice length of the token.
+    pub voice_length: f32,
+}
+
+impl From<whisper_token_data> for WhisperTokenData {
+    fn from(value: whisper_token_data) -> Self {
+        Self {
+            id: value.id,
+            timestamp_id: value.tid,
+            probability: value.p,
+            log_probability: value.plog,
+            timestamp_probability: value.pt,
+            timestamp_probability_sum: value.ptsum,
+            start: value.t0,
+            end: value.t1,
+            voice_length: value.vlen,
+        }
+    }
 }
 
 #[derive(Debug, Error)]
 pub enum WhisperParamsError {
     #[error("failed to convert String to CString: {0}")]
     SessionInitialization(#[from] std::ffi::NulError),
+    #[error("unknown sampling strategy: {0}")]
+    UnknownSamplingStrategy(whisper_sampling_strategy),
+    #[error("the initial prompt is not valid UTF-8: {0}")]
+    InitialPrompt(std::str::Utf8Error),
+    #[error("the language is not valid UTF-8: {0}")]
+    Language(std::str::Utf8Error),
+    #[error("invalid prompt tokens, {0} tokens at a null pointer")]
+    PromptTokens(c_int),
+    #[error("invalid grammar rules, {0} rules at a null pointer")]
+    GrammarRules(usize),
+    #[error("invalid grammar rule {0}, at a null pointer")]
+    GrammarRule(usize),
+    #[error("unknown grammar element type: {0}")]
+    UnknownGrammarElement(whisper_gretype),
 }
 
-#[derive(Debug)]
+/// An element of a grammar rule, see [`whisper_grammar_element`].
+///
+/// Rules are terminated implicitly, so there is no equivalent to `WHISPER_GRETYPE_END`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum WhisperGrammarElement {
+    /// Starts a new alternate definition of the rule.
+    Alt,
+
+    /// References the rule at the specified index.
+    RuleRef(u32),
+
+    /// Matches the specified character, or starts a character range or set.
+    Char(u32),
+
+    /// Matches anything but the specified character, or starts an inverse range or set.
+    CharNot(u32),
+
+    /// Turns the preceding [`WhisperGrammarElement::Char`] or [`WhisperGrammarElement::CharAlt`]
+    /// into an inclusive range ending at the specified character.
+    CharRangeUpper(u32),
+
+    /// Adds the specified character to the preceding [`WhisperGrammarElement::Char`] or
+    /// [`WhisperGrammarElement::CharRangeUpper`].
+    CharAlt(u32),
+}
+
+impl WhisperGrammarElement {
+    fn c_element(&self) -> whisper_grammar_element {
+        let (type_, value) = match *self {
+            Self::Alt => (whisper_gretype_WHISPER_GRETYPE_ALT, 0),
+            Self::RuleRef(value) => (whisper_gretype_WHISPER_GRETYPE_RULE_REF, value),
+            Self::Char(value) => (whisper_gretype_WHISPER_GRETYPE_CHAR, value),
+            Self::CharNot(value) => (whisper_gretype_WHISPER_GRETYPE_CHAR_NOT, value),
+            Self::CharRangeUpper(value) => (whisper_gretype_WHISPER_GRETYPE_CHAR_RNG_UPPER, value),
+            Self::CharAlt(value) => (whisper_gretype_WHISPER_GRETYPE_CHAR_ALT, value),
+        };
+
+        whisper_grammar_element { type_, value }
+    }
+
+    /// Reads the elements of a rule up to its `WHISPER_GRETYPE_END` element.
+    ///
+    /// SAFETY: `rule` must point to an array of [`whisper_grammar_element`]s terminated by a
+    /// `WHISPER_GRETYPE_END` element.
+    unsafe fn read_rule(
+        mut rule: *const whisper_grammar_element,
+    ) -> Result<Vec<Self>, WhisperParamsError> {
+        let mut rv = vec![];
+
+        loop {
+            let element = *rule;
+
+            #[allow(non_upper_case_globals)]
+            rv.push(match element.type_ {
+                whisper_gretype_WHISPER_GRETYPE_END => return Ok(rv),
+                whisper_gretype_WHISPER_GRETYPE_ALT => Self::Alt,
+                whisper_gretype_WHISPER_GRETYPE_RULE_REF => Self::RuleRef(element.value),
+                whisper_gretype_WHISPER_GRETYPE_CHAR => Self::Char(element.value),
+                whisper_gretype_WHISPER_GRETYPE_CHAR_NOT => Self::CharNot(element.value),
+                whisper_gretype_WHISPER_GRETYPE_CHAR_RNG_UPPER => {
+                    Self::CharRangeUpper(element.value)
+                }
+                whisper_gretype_WHISPER_GRETYPE_CHAR_ALT => Self::CharAlt(element.value),
+                other => return Err(WhisperParamsError::UnknownGrammarElement(other)),
+            });
+
+            rule = rule.add(1);
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", rename_all = "snake_case"))]
 pub enum WhisperSampling {
     Greedy {
         /// ref: https://github.com/openai/whisper/blob/f82bc59f5ea234d4b97fb2860842ed38519f7e65/whisper/transcribe.py#L264
@@ -388,7 +1431,8 @@ impl WhisperSampling {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct WhisperParams {
     /// The sampling strategy to be used.
     strategy: WhisperSampling,
@@ -405,8 +1449,12 @@ pub struct WhisperParams {
     /// Audio duration in milliseconds.
     duration_ms: u32,
 
-    /// Translate the audio to ??? (TODO)
-    translate: bool,
+    /// Translate the audio to English instead of transcribing it.
+    ///
+    /// English-only models cannot translate, they ignore this and log a warning.
+    ///
+    /// See [`WhisperSession::advance_bilingual`] to get both the transcription and the translation.
+    pub translate: bool,
 
     /// Do not use past transcription (if any) as initial prompt for the decoder.
     pub no_context: bool,
@@ -456,6 +1504,8 @@ pub struct WhisperParams {
     debug_mode: bool,
 
     /// Overwrite the audio context size (0 = use default).
+    ///
+    /// This does not lower the memory used by a session, see [`WhisperMemoryParams`].
     audio_ctx: u32,
 
     /// Enable *tinydiarize* speaker turn detection.
@@ -495,40 +1545,64 @@ pub struct WhisperParams {
     /// Similar to OpenAI's "compression_ratio_threshold".
     entropy_thold: f32,
 
-    ///
+    /// ref: https://github.com/openai/whisper/blob/f82bc59f5ea234d4b97fb2860842ed38519f7e65/whisper/transcribe.py#L275
     logprob_thold: f32,
 
     /// Not implemented in whisper.cpp
     no_speech_thold: f32,
 
     /// Called for every newly generated text segment.
+    #[cfg_attr(feature = "serde", serde(skip))]
     _new_segment_callback: (),
+    #[cfg_attr(feature = "serde", serde(skip))]
     _new_segment_callback_user_data: (),
 
     /// Called on each progress update.
+    #[cfg_attr(feature = "serde", serde(skip))]
     _progress_callback: (),
+    #[cfg_attr(feature = "serde", serde(skip))]
     _progress_callback_user_data: (),
 
     /// Called each time before the encoder starts.
+    #[cfg_attr(feature = "serde", serde(skip))]
     _encoder_begin_callback: (),
+    #[cfg_attr(feature = "serde", serde(skip))]
     _encoder_begin_callback_user_data: (),
 
     /// Called each time before ggml computation starts.
+    #[cfg_attr(feature = "serde", serde(skip))]
     _abort_callback: (),
+    #[cfg_attr(feature = "serde", serde(skip))]
     _abort_callback_user_data: (),
 
     /// Called by each decoder to filter obtained logits.
+    #[cfg_attr(feature = "serde", serde(skip))]
     _logits_filter_callback: (),
+    #[cfg_attr(feature = "serde", serde(skip))]
     _logits_filter_callback_user_data: (),
 
-    ///
-    _grammar_rules: (),
-    grammar_rule_count: usize,
+    /// Rules of the grammar constraining the decoded text, if any.
+    grammar_rules: Vec<Vec<WhisperGrammarElement>>,
+
+    /// Index of the grammar rule the text must match.
     i_start_rule: usize,
+
+    /// Penalty applied to the logits of tokens not matching the grammar.
     grammar_penalty: f32,
 }
 
+/// Owned data pointed to by the [`whisper_full_params`] returned from [`WhisperParams::c_params`].
+#[derive(Default)]
+struct CParamsStorage {
+    strings: Vec<CString>,
+    grammar_rules: Vec<Vec<whisper_grammar_element>>,
+    grammar_rule_ptrs: Vec<*const whisper_grammar_element>,
+}
+
 impl WhisperParams {
+    /// *whisper.cpp*'s default parameters, sampling with `sampling_strategy`. Its values left at
+    /// zero, as in [`WhisperSampling::default_greedy`] and [`WhisperSampling::default_beam`], are
+    /// replaced with the defaults of *whisper.cpp*.
     pub fn new(sampling_strategy: WhisperSampling) -> Self {
         let c_strategy = match sampling_strategy {
             WhisperSampling::Greedy { .. } => whisper_sampling_strategy_WHISPER_SAMPLING_GREEDY,
@@ -539,16 +1613,49 @@ impl WhisperParams {
 
         let c_params = unsafe { whisper_full_default_params(c_strategy) };
 
-        c_params.into()
+        let mut params: Self = c_params
+            .try_into()
+            .expect("whisper.cpp's default parameters are always valid");
+        params.thread_count = std::thread::available_parallelism()
+            .unwrap_or(unsafe { NonZeroUsize::new_unchecked(1) })
+            .get() as u32;
+
+        match (&mut params.strategy, sampling_strategy) {
+            (WhisperSampling::Greedy { best_of }, WhisperSampling::Greedy { best_of: value })
+                if value != 0 =>
+            {
+                *best_of = value;
+            }
+            (
+                WhisperSampling::BeamSearch {
+                    beam_size,
+                    patience,
+                },
+                WhisperSampling::BeamSearch {
+                    beam_size: size,
+                    patience: value,
+                },
+            ) => {
+                if size != 0 {
+                    *beam_size = size;
+                }
+                if value != 0.0 {
+                    *patience = value;
+                }
+            }
+            _ => {}
+        }
+
+        params
     }
 
     /// Returns a [`whisper_full_params`] equivalent to this [`WhisperParams`].
     ///
     /// SAFETY: The returned [`whisper_full_params`] object must not live longer than the
-    /// accompanying [`Vec`] and this [`WhisperParams`], as it contains pointers to the vector's
-    /// elements and members of this object instance.
-    unsafe fn c_params(&self) -> Result<(Vec<CString>, whisper_full_params), WhisperParamsError> {
-        let mut v = vec![];
+    /// accompanying [`CParamsStorage`] and this [`WhisperParams`], as it contains pointers to the
+    /// storage's elements and members of this object instance.
+    unsafe fn c_params(&self) -> Result<(CParamsStorage, whisper_full_params), WhisperParamsError> {
+        let mut storage = CParamsStorage::default();
 
         fn push_str(
             storage: &mut Vec<CString>,
@@ -592,7 +1699,7 @@ impl WhisperParams {
             debug_mode: self.debug_mode,
             audio_ctx: self.audio_ctx as c_int,
             tdrz_enable: self.tdrz_enable,
-            initial_prompt: push_str(&mut v, &self.initial_prompt)?,
+            initial_prompt: push_str(&mut storage.strings, &self.initial_prompt)?,
             prompt_tokens: {
                 if self.prompt_tokens.is_empty() {
                     null_mut()
@@ -601,7 +1708,7 @@ impl WhisperParams {
                 }
             },
             prompt_n_tokens: self.prompt_tokens.len() as c_int,
-            language: push_str(&mut v, &self.language)?,
+            language: push_str(&mut storage.strings, &self.language)?,
             detect_language: self.detect_language,
             suppress_blank: self.suppress_blank,
             suppress_non_speech_tokens: self.suppress_non_speech_tokens,
@@ -648,19 +1755,39 @@ impl WhisperParams {
             abort_callback_user_data: null_mut(),
             logits_filter_callback: None,
             logits_filter_callback_user_data: null_mut(),
-            grammar_rules: null_mut(),
-            n_grammar_rules: self.grammar_rule_count,
+            grammar_rules: {
+                if self.grammar_rules.is_empty() {
+                    null_mut()
+                } else {
+                    for rule in &self.grammar_rules {
+                        let mut c_rule: Vec<_> = rule.iter().map(|e| e.c_element()).collect();
+                        c_rule.push(whisper_grammar_element {
+                            type_: whisper_gretype_WHISPER_GRETYPE_END,
+                            value: 0,
+                        });
+                        storage.grammar_rule_ptrs.push(c_rule.as_ptr());
+                        storage.grammar_rules.push(c_rule);
+                    }
+                    storage.grammar_rule_ptrs.as_mut_ptr()
+                }
+            },
+            n_grammar_rules: self.grammar_rules.len(),
             i_start_rule: self.i_start_rule,
             grammar_penalty: self.grammar_penalty,
         };
 
-        Ok((v, c_params))
+        Ok((storage, c_params))
     }
 }
 
-impl From<whisper_full_params> for WhisperParams {
-    fn from(value: whisper_full_params) -> Self {
-        Self {
+impl TryFrom<whisper_full_params> for WhisperParams {
+    type Error = WhisperParamsError;
+
+    /// Converts a [`whisper_full_params`] into [`WhisperParams`].
+    ///
+    /// Callbacks are not carried over, as [`WhisperParams`] does not support them yet.
+    fn try_from(value: whisper_full_params) -> Result<Self, Self::Error> {
+        Ok(Self {
             strategy: {
                 match value.strategy {
                     #[allow(non_upper_case_globals)]
@@ -674,14 +1801,10 @@ impl From<whisper_full_params> for WhisperParams {
                             patience: value.beam_search.patience,
                         }
                     }
-                    _ => {
-                        unimplemented!("Sampling strategy not implemented!")
-                    }
+                    other => return Err(WhisperParamsError::UnknownSamplingStrategy(other)),
                 }
             },
-            thread_count: std::thread::available_parallelism()
-                .unwrap_or(unsafe { NonZeroUsize::new_unchecked(1) })
-                .get() as u32,
+            thread_count: value.n_threads as u32,
             max_text_ctx: value.n_max_text_ctx as u32,
             offset_ms: value.offset_ms as u32,
             duration_ms: value.duration_ms as u32,
@@ -708,12 +1831,17 @@ impl From<whisper_full_params> for WhisperParams {
                     "".to_string()
                 } else {
                     let c_str = unsafe { CStr::from_ptr(value.initial_prompt.cast_mut()) };
-                    c_str.to_str().unwrap_or("").to_string()
+                    c_str
+                        .to_str()
+                        .map_err(WhisperParamsError::InitialPrompt)?
+                        .to_string()
                 }
             },
             prompt_tokens: {
-                if value.prompt_tokens.is_null() {
+                if value.prompt_n_tokens <= 0 {
                     vec![]
+                } else if value.prompt_tokens.is_null() {
+                    return Err(WhisperParamsError::PromptTokens(value.prompt_n_tokens));
                 } else {
                     let slice = unsafe {
                         slice::from_raw_parts(value.prompt_tokens, value.prompt_n_tokens as usize)
@@ -726,7 +1854,10 @@ impl From<whisper_full_params> for WhisperParams {
                     "".to_string()
                 } else {
                     let c_str = unsafe { CStr::from_ptr(value.language.cast_mut()) };
-                    c_str.to_str().unwrap_or("").to_string()
+                    c_str
+                        .to_str()
+                        .map_err(WhisperParamsError::Language)?
+                        .to_string()
                 }
             },
             detect_language: value.detect_language,
@@ -749,11 +1880,31 @@ impl From<whisper_full_params> for WhisperParams {
             _abort_callback_user_data: (),
             _logits_filter_callback: (),
             _logits_filter_callback_user_data: (),
-            _grammar_rules: (),
-            grammar_rule_count: value.n_grammar_rules,
+            grammar_rules: {
+                if value.n_grammar_rules == 0 {
+                    vec![]
+                } else if value.grammar_rules.is_null() {
+                    return Err(WhisperParamsError::GrammarRules(value.n_grammar_rules));
+                } else {
+                    let rules = unsafe {
+                        slice::from_raw_parts(value.grammar_rules, value.n_grammar_rules)
+                    };
+                    rules
+                        .iter()
+                        .enumerate()
+                        .map(|(index, rule)| {
+                            if rule.is_null() {
+                                Err(WhisperParamsError::GrammarRule(index))
+                            } else {
+                                unsafe { WhisperGrammarElement::read_rule(*rule) }
+                            }
+                        })
+                        .collect::<Result<_, _>>()?
+                }
+            },
             i_start_rule: value.i_start_rule,
             grammar_penalty: value.grammar_penalty,
-        }
+        })
     }
 }
 
@@ -764,13 +1915,14 @@ mod internal {
 
     use core::ffi::{c_char, c_void, CStr};
 
-    use tracing::{error, info, trace, warn};
-
     use whisper_cpp_sys::{
         ggml_log_level, ggml_log_level_GGML_LOG_LEVEL_ERROR, ggml_log_level_GGML_LOG_LEVEL_INFO,
         ggml_log_level_GGML_LOG_LEVEL_WARN,
     };
 
+    use crate::logging::log;
+    use crate::WhisperLogLevel;
+
     #[no_mangle]
     pub(crate) unsafe extern "C" fn whisper_log_callback(
         level: ggml_log_level,
@@ -789,11 +1941,1101 @@ mod internal {
             text.as_ref()
         };
 
-        match level {
-            ggml_log_level_GGML_LOG_LEVEL_ERROR => error!("ggml: {text}"),
-            ggml_log_level_GGML_LOG_LEVEL_INFO => info!("ggml: {text}"),
-            ggml_log_level_GGML_LOG_LEVEL_WARN => warn!("ggml: {text}"),
-            _ => trace!("ggml: {text}"),
+        let level = match level {
+            ggml_log_level_GGML_LOG_LEVEL_ERROR => WhisperLogLevel::Error,
+            ggml_log_level_GGML_LOG_LEVEL_INFO => WhisperLogLevel::Info,
+            ggml_log_level_GGML_LOG_LEVEL_WARN => WhisperLogLevel::Warn,
+            _ => WhisperLogLevel::Debug,
+        };
+
+        log(level, text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    //! None of these tests call into whisper.cpp, so they can also run under Miri with
+    //! `cargo +nightly miri test -p whisper_cpp --lib`.
+
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+    use static_assertions::{assert_impl_all, assert_not_impl_any};
+
+    use super::*;
+
+    assert_impl_all!(WhisperContext: Send, Sync);
+    assert_not_impl_any!(WhisperContext: Clone);
+    assert_impl_all!(WhisperState: Send);
+    assert_not_impl_any!(WhisperState: Clone, Sync);
+    assert_impl_all!(WhisperModel: Send, Sync, Clone);
+    assert_impl_all!(WhisperSession: Send, Sync);
+    assert_impl_all!(SessionPool: Send, Sync, Clone);
+    assert_impl_all!(PooledSession: Send, Sync);
+    assert_impl_all!(BatchTranscriber: Send, Sync);
+
+    #[test]
+    fn c_params_pointers_are_valid() {
+        let mut params = WhisperParams::try_from(unsafe {
+            // SAFETY: every field of `whisper_full_params` is valid when zeroed
+            std::mem::zeroed::<whisper_full_params>()
+        })
+        .unwrap();
+        params.initial_prompt = "a prompt".to_string();
+        params.language = "en".to_string();
+        params.prompt_tokens = vec![1, 2, 3];
+
+        let (storage, c_params) = unsafe { params.c_params() }.unwrap();
+
+        unsafe {
+            assert_eq!(
+                CStr::from_ptr(c_params.initial_prompt).to_str(),
+                Ok("a prompt")
+            );
+            assert_eq!(CStr::from_ptr(c_params.language).to_str(), Ok("en"));
+            assert_eq!(
+                slice::from_raw_parts(c_params.prompt_tokens, c_params.prompt_n_tokens as usize),
+                [1, 2, 3]
+            );
+        }
+
+        drop(storage);
+    }
+
+    #[test]
+    fn full_error_codes() {
+        let error = |code| WhisperSessionError::from_full_code(code, WhisperErrorLog::default());
+
+        for code in [-1, -2] {
+            assert!(
+                matches!(error(code), WhisperSessionError::MelSpectrogram { code: c, .. } if c == code)
+            );
+        }
+        assert!(matches!(
+            error(-3),
+            WhisperSessionError::LanguageDetection { code: -3, .. }
+        ));
+        assert!(matches!(
+            error(-4),
+            WhisperSessionError::DecoderInitialization { code: -4, .. }
+        ));
+        assert!(matches!(
+            error(-5),
+            WhisperSessionError::AudioContext { code: -5, .. }
+        ));
+        assert!(matches!(
+            error(-6),
+            WhisperSessionError::Encode { code: -6, .. }
+        ));
+        for code in [-7, -8] {
+            assert!(
+                matches!(error(code), WhisperSessionError::Decode { code: c, .. } if c == code)
+            );
+        }
+        for code in [-9, 1, i32::MIN] {
+            assert!(matches!(error(code), WhisperSessionError::Full { code: c, .. } if c == code));
+        }
+
+        let log = WhisperErrorLog(vec!["failed to encode".to_string()]);
+        assert_eq!(
+            WhisperSessionError::from_full_code(-6, log).to_string(),
+            "failed to encode the audio (code -6; failed to encode)"
+        );
+    }
+
+    #[test]
+    fn errors_are_captured_per_thread() {
+        logging::log(WhisperLogLevel::Error, "before the capture");
+
+        let (_, log) = capture_errors(|| {
+            logging::log(WhisperLogLevel::Info, "not an error");
+            logging::log(WhisperLogLevel::Error, "first");
+            std::thread::spawn(|| logging::log(WhisperLogLevel::Error, "another thread"))
+                .join()
+                .unwrap();
+            logging::log(WhisperLogLevel::Error, "second");
+        });
+
+        assert_eq!(log.0, ["first", "second"]);
+        assert_eq!(log.last(), Some("second"));
+
+        logging::log(WhisperLogLevel::Error, "after the capture");
+
+        let (_, log) = capture_errors(|| ());
+        assert_eq!(log, WhisperErrorLog::default());
+    }
+
+    #[test]
+    fn logger_routing() {
+        use std::sync::Mutex;
+
+        use tracing::{Event, Level, Subscriber};
+        use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+
+        let records = Arc::new(Mutex::new(vec![]));
+        let logger = {
+            let records = records.clone();
+            WhisperLogger::custom(move |record| {
+                let span = record.span.metadata().map(|metadata| metadata.name());
+                let message = record.message.to_string();
+                records.lock().unwrap().push((record.level, message, span));
+            })
+            .with_min_level(WhisperLogLevel::Warn)
+        };
+
+        logger.log(WhisperLogLevel::Info, "filtered out");
+        logger.log(WhisperLogLevel::Warn, "outside of a span");
+        tracing::subscriber::with_default(tracing_subscriber::registry(), || {
+            info_span!("whisper_session").in_scope(|| logger.log(WhisperLogLevel::Error, "inside"));
+        });
+        // Spans are only recorded by a subscriber
+        info_span!("whisper_session").in_scope(|| logger.log(WhisperLogLevel::Error, "untracked"));
+
+        assert_eq!(
+            *records.lock().unwrap(),
+            [
+                (WhisperLogLevel::Warn, "outside of a span".to_string(), None),
+                (
+                    WhisperLogLevel::Error,
+                    "inside".to_string(),
+                    Some("whisper_session")
+                ),
+                (WhisperLogLevel::Error, "untracked".to_string(), None),
+            ]
+        );
+
+        /// Collects the level and target of every event.
+        struct Events(Arc<Mutex<Vec<(Level, String)>>>);
+
+        impl<S: Subscriber> Layer<S> for Events {
+            fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+                let metadata = event.metadata();
+                let event = (*metadata.level(), metadata.target().to_string());
+                self.0.lock().unwrap().push(event);
+            }
+        }
+
+        let events = Arc::new(Mutex::new(vec![]));
+        let subscriber = tracing_subscriber::registry().with(Events(events.clone()));
+        tracing::subscriber::with_default(subscriber, || {
+            let levels = [
+                WhisperLogLevel::Debug,
+                WhisperLogLevel::Info,
+                WhisperLogLevel::Warn,
+                WhisperLogLevel::Error,
+            ];
+            for level in levels {
+                WhisperLogger::default().log(level, "message");
+                WhisperLogger::silent().log(level, "message");
+            }
+        });
+
+        let target = LOG_TARGET.to_string();
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                (Level::TRACE, target.clone()),
+                (Level::INFO, target.clone()),
+                (Level::WARN, target.clone()),
+                (Level::ERROR, target),
+            ]
+        );
+    }
+
+    #[test]
+    fn segment_metrics() {
+        let token = |id, probability: f32| WhisperTokenData {
+            id,
+            timestamp_id: 0,
+            probability,
+            log_probability: probability.ln(),
+            timestamp_probability: 0.0,
+            timestamp_probability_sum: 0.0,
+            start: 0,
+            end: 0,
+            voice_length: 0.0,
+        };
+
+        // A timestamp, two text tokens, and the end of transcript control token
+        let tokens = [
+            token(110, 0.5),
+            token(1, 0.25),
+            token(2, 1.0),
+            token(100, 0.01),
+        ];
+        let metrics = WhisperSegmentMetrics::new(" Hello.", &tokens, 100..110);
+
+        assert_eq!(metrics.avg_logprob, (0.5f32.ln() + 0.25f32.ln()) / 3.0);
+        assert_eq!(metrics.min_token_probability, 0.25);
+        assert_eq!(metrics.no_speech_probability, 0.5);
+        assert!(metrics.compression_ratio < 1.0);
+        assert!(!metrics.is_low_quality(&WhisperQualityThresholds::default()));
+        assert!(metrics.is_low_quality(&WhisperQualityThresholds {
+            min_token_probability: 0.3,
+            ..Default::default()
+        }));
+        assert!(!metrics.is_no_speech(&WhisperQualityThresholds::default()));
+
+        let empty = WhisperSegmentMetrics::new("", &[], 100..110);
+        assert_eq!(empty.avg_logprob, 0.0);
+        assert_eq!(empty.compression_ratio, 0.0);
+        assert_eq!(empty.min_token_probability, 1.0);
+
+        let repeated = " I'm sorry.".repeat(20);
+        assert!(compression_ratio(&repeated) > 2.4);
+    }
+
+    #[test]
+    fn bilingual_alignment() {
+        let segment = |text: &str, start, end| WhisperSegment {
+            text: text.to_string(),
+            start,
+            end,
+        };
+        let cue = |start, end, original: &str, translation: &str| WhisperBilingualCue {
+            start,
+            end,
+            original: original.to_string(),
+            translation: translation.to_string(),
+        };
+
+        let original = [
+            segment(" Bonjour.", 0, 100),
+            segment(" Comment allez-vous ?", 100, 250),
+            segment(" Très bien,", 300, 400),
+            segment(" merci.", 400, 480),
+            segment(" Au revoir.", 1000, 1100),
+        ];
+        let translation = [
+            segment(" Hello.", 0, 90),
+            segment(" How are you?", 110, 260),
+            segment(" Very well, thank you.", 300, 480),
+            segment(" Bye.", 2000, 2050),
+        ];
+
+        assert_eq!(
+            align_bilingual(&original, &translation),
+            [
+                cue(0, 100, "Bonjour.", "Hello."),
+                cue(100, 260, "Comment allez-vous ?", "How are you?"),
+                cue(300, 480, "Très bien, merci.", "Very well, thank you."),
+                cue(1000, 1100, "Au revoir.", ""),
+                cue(2000, 2050, "", "Bye."),
+            ]
+        );
+        assert_eq!(align_bilingual(&[], &[]), []);
+
+        // Segments straddling the boundaries of the other list do not chain cues together
+        let original = [
+            segment(" A", 0, 100),
+            segment(" B", 100, 200),
+            segment(" C", 200, 300),
+        ];
+        let translation = [
+            segment(" X", 50, 150),
+            segment(" Y", 150, 250),
+            segment(" Z", 250, 300),
+        ];
+        assert_eq!(
+            align_bilingual(&original, &translation),
+            [cue(0, 200, "A B", "X"), cue(150, 300, "C", "Y Z")]
+        );
+    }
+
+    #[test]
+    fn subtitle_formats() {
+        let segments = [
+            WhisperSegment {
+                text: " Hello.".to_string(),
+                start: 0,
+                end: 150,
+            },
+            WhisperSegment {
+                text: " An hour later.".to_string(),
+                start: 360_150,
+                end: 360_275,
+            },
+        ];
+
+        assert_eq!(format_timestamp(-5, ','), "00:00:00,000");
+        assert_eq!(format_text(&segments), "Hello.\nAn hour later.\n");
+        assert_eq!(
+            format_srt(&segments),
+            "1\n00:00:00,000 --> 00:00:01,500\nHello.\n\n\
+             2\n01:00:01,500 --> 01:00:02,750\nAn hour later.\n\n"
+        );
+        assert_eq!(
+            format_vtt(&segments),
+            "WEBVTT\n\n\
+             00:00:00.000 --> 00:00:01.500\nHello.\n\n\
+             01:00:01.500 --> 01:00:02.750\nAn hour later.\n\n"
+        );
+    }
+
+    #[test]
+    fn segment_filter() {
+        let segment = |text: &str, start, end| WhisperSegment {
+            text: text.to_string(),
+            start,
+            end,
+        };
+
+        let segments = vec![
+            segment(" Hello, how are you?", 0, 150),
+            segment(" No, no, no.", 150, 250),
+            segment(" Thank you. Thank you. Thank you. Thank you.", 250, 500),
+            segment(" Let's go.", 500, 600),
+            segment(" Let's go.", 600, 700),
+            segment(" Let's go.", 700, 800),
+            segment(" Thanks for watching!", 800, 5000),
+            segment(
+                " This cannot possibly be said in a tenth of a second.",
+                3000,
+                3010,
+            ),
+        ];
+
+        let filter = WhisperSegmentFilter::default();
+        let (kept, report) = filter.apply(segments.clone());
+
+        assert_eq!(
+            kept,
+            [
+                segments[0].clone(),
+                segments[1].clone(),
+                segments[3].clone()
+            ]
+        );
+        assert_eq!(
+            report.filtered.iter().map(|f| f.index).collect::<Vec<_>>(),
+            [2, 4, 5, 6, 7]
+        );
+        assert_eq!(
+            report.filtered[0].reasons,
+            [WhisperFilterReason::Repetition {
+                phrase: "thank you".to_string(),
+                count: 4
+            }]
+        );
+        assert_eq!(
+            report.filtered[1].reasons,
+            [WhisperFilterReason::RepeatedSegment { count: 3 }]
+        );
+        assert!(matches!(
+            report.filtered[3].reasons[..],
+            [
+                WhisperFilterReason::Hallucination { .. },
+                WhisperFilterReason::CharsPerSecond(_)
+            ]
+        ));
+        assert!(matches!(
+            report.filtered[4].reasons[..],
+            [WhisperFilterReason::CharsPerSecond(cps)] if cps > 500.0
+        ));
+
+        let filter = WhisperSegmentFilter {
+            action: WhisperFilterAction::Mark,
+            ..Default::default()
+        };
+        let (marked, marked_report) = filter.apply(segments.clone());
+
+        assert_eq!(marked, segments);
+        assert_eq!(marked_report, report);
+        assert!(marked_report.is_filtered(2));
+        assert!(!marked_report.is_filtered(3));
+
+        // Short repetitions are measured in characters, and segments without a duration have no rate
+        let segments = vec![
+            segment(" はい、はい、はい", 0, 100),
+            segment(" Yes.", 100, 100),
+            segment(" You", 100, 200),
+        ];
+        let (kept, report) = WhisperSegmentFilter::default().apply(segments.clone());
+        assert_eq!(kept, segments);
+        assert!(report.filtered.is_empty());
+
+        // Fillers are only caught over silence
+        let metrics = |no_speech_probability, avg_logprob| WhisperSegmentMetrics {
+            avg_logprob,
+            compression_ratio: 1.0,
+            min_token_probability: 0.5,
+            no_speech_probability,
+        };
+        let speech = metrics(0.1, -0.2);
+        let silence = metrics(0.9, -1.5);
+
+        let filter = WhisperSegmentFilter::default();
+        let (kept, _) = filter.apply_with_metrics(segments.clone(), &[speech, speech, speech]);
+        assert_eq!(kept, segments);
+
+        let (kept, report) =
+            filter.apply_with_metrics(segments.clone(), &[speech, speech, silence]);
+        assert_eq!(kept, segments[..2]);
+        assert_eq!(
+            report.filtered[0].reasons,
+            [WhisperFilterReason::Hallucination {
+                phrase: "you".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn model_info() {
+        let mut file = vec![];
+        let int = |file: &mut Vec<u8>, value: i32| file.extend(value.to_le_bytes());
+
+        int(&mut file, model_file::GGML_FILE_MAGIC as i32);
+        // tiny.en hparams, quantized to q5_0 with version 2
+        for value in [51864, 1500, 384, 6, 4, 448, 384, 6, 4, 80, 2008] {
+            int(&mut file, value);
+        }
+        // A 2x3 mel filter bank
+        int(&mut file, 2);
+        int(&mut file, 3);
+        for value in [0.0f32, 0.5, 1.0, 1.0, 0.5, 0.0] {
+            file.extend(value.to_le_bytes());
+        }
+        // Two tokens
+        int(&mut file, 2);
+        for token in [&b"!"[..], b" the"] {
+            int(&mut file, token.len() as i32);
+            file.extend(token);
+        }
+        // A 4x2 f32 tensor and a 64 elements q5_0 tensor
+        let tensors = [("a.weight", &[4, 2][..], 0, 32), ("b.weight", &[64], 6, 44)];
+        for (name, shape, ggml_type, size) in tensors {
+            int(&mut file, shape.len() as i32);
+            int(&mut file, name.len() as i32);
+            int(&mut file, ggml_type);
+            for &dim in shape {
+                int(&mut file, dim);
+            }
+            file.extend(name.as_bytes());
+            file.resize(file.len() + size, 0);
+        }
+
+        let path = std::path::Path::new("tiny.bin");
+        let parse = |bytes: &[u8]| {
+            WhisperModelInfo::parse(std::io::Cursor::new(bytes), bytes.len() as u64, path)
+        };
+
+        let info = parse(&file).unwrap();
+        assert_eq!(info.hparams.n_vocab, 51864);
+        assert_eq!(info.hparams.n_mels, 80);
+        assert_eq!(info.hparams.ftype_name(), Some("q5_0"));
+        assert_eq!(info.hparams.model_type(), Some("tiny"));
+        assert!(!info.hparams.is_multilingual());
+        assert_eq!(info.quantization_version, 2);
+        assert_eq!(info.mel_filters.n_fft, 3);
+        assert_eq!(info.mel_filters.data[4], 0.5);
+        assert_eq!(info.vocab, [b"!".to_vec(), b" the".to_vec()]);
+        assert_eq!(
+            info.tensors,
+            [
+                WhisperTensorInfo {
+                    name: "a.weight".to_string(),
+                    shape: vec![4, 2],
+                    ggml_type: GgmlType::F32,
+                    offset: 125,
+                },
+                WhisperTensorInfo {
+                    name: "b.weight".to_string(),
+                    shape: vec![64],
+                    ggml_type: GgmlType::Q5_0,
+                    offset: 181,
+                },
+            ]
+        );
+        assert_eq!(info.tensor_bytes(), 32 + 44);
+
+        assert!(matches!(
+            parse(&file[..file.len() - 1]),
+            Err(WhisperModelFileError::Truncated(_))
+        ));
+        assert!(matches!(
+            parse(&file[..30]),
+            Err(WhisperModelFileError::Truncated(_))
+        ));
+
+        let mut negative = file.clone();
+        negative[4..8].copy_from_slice(&(-1i32).to_le_bytes());
+        assert!(matches!(
+            parse(&negative),
+            Err(WhisperModelFileError::Invalid { .. })
+        ));
+
+        // A tensor whose size overflows must be rejected, not wrap around
+        let mut huge = file.clone();
+        int(&mut huge, 4);
+        int(&mut huge, 1);
+        int(&mut huge, 0);
+        for _ in 0..4 {
+            int(&mut huge, 1 << 24);
+        }
+        huge.push(b'c');
+        assert!(matches!(
+            parse(&huge),
+            Err(WhisperModelFileError::Invalid { reason, .. }) if reason.contains("too large")
+        ));
+
+        let tensor = WhisperTensorInfo {
+            name: "c".to_string(),
+            shape: vec![1 << 24; 4],
+            ggml_type: GgmlType::F32,
+            offset: 0,
+        };
+        assert_eq!(tensor.element_count(), usize::MAX);
+        assert_eq!(tensor.size(), u64::MAX);
+    }
+
+    #[test]
+    fn quantized_tensors() {
+        use quantize::{f16_to_f32, quantizes, ModelWriter};
+
+        assert_eq!(f16_to_f32(0x3c00), 1.0);
+        assert_eq!(f16_to_f32(0xc000), -2.0);
+        assert_eq!(f16_to_f32(0x3555), 0.333_251_95);
+        assert_eq!(f16_to_f32(0x7bff), 65504.0);
+        assert_eq!(f16_to_f32(0x0001), 2f32.powi(-24));
+        assert_eq!(f16_to_f32(0x8000).to_bits(), (-0.0f32).to_bits());
+        assert_eq!(f16_to_f32(0x7c00), f32::INFINITY);
+        assert!(f16_to_f32(0x7e00).is_nan());
+
+        let tensor = |name: &str, shape: &[usize], ggml_type| WhisperTensorInfo {
+            name: name.to_string(),
+            shape: shape.to_vec(),
+            ggml_type,
+            offset: 0,
+        };
+        let q5_0 = WhisperQuantType::Q5_0;
+        assert!(quantizes(
+            &tensor("a.weight", &[64, 2], GgmlType::F16),
+            q5_0
+        ));
+        assert!(!quantizes(
+            &tensor("a.weight", &[64, 2], GgmlType::Q4_0),
+            q5_0
+        ));
+        assert!(!quantizes(&tensor("a.bias", &[64], GgmlType::F32), q5_0));
+        assert!(!quantizes(
+            &tensor("a.weight", &[48, 2], GgmlType::F16),
+            q5_0
+        ));
+        assert!(!quantizes(
+            &tensor("decoder.positional_embedding", &[384, 448], GgmlType::F32),
+            q5_0
+        ));
+
+        let info = WhisperModelInfo {
+            hparams: WhisperHparams {
+                n_vocab: 51864,
+                n_audio_ctx: 1500,
+                n_audio_state: 384,
+                n_audio_head: 6,
+                n_audio_layer: 4,
+                n_text_ctx: 448,
+                n_text_state: 384,
+                n_text_head: 6,
+                n_text_layer: 4,
+                n_mels: 80,
+                ftype: 8,
+            },
+            quantization_version: 2,
+            mel_filters: WhisperMelFilters {
+                n_mel: 1,
+                n_fft: 2,
+                data: vec![0.25, 0.75],
+            },
+            vocab: vec![b"!".to_vec()],
+            tensors: vec![tensor("b.weight", &[64], GgmlType::Q5_0)],
+        };
+        let mut writer = ModelWriter(vec![]);
+        // The stored `ftype` includes the quantization version
+        let hparams = WhisperHparams {
+            ftype: 2008,
+            ..info.hparams
+        };
+        writer.header(&info, &hparams).unwrap();
+        writer
+            .tensor(&info.tensors[0], GgmlType::Q5_0, &[0; 44])
+            .unwrap();
+
+        let file = writer.0;
+        let path = std::path::Path::new("tiny.bin");
+        let mut parsed =
+            WhisperModelInfo::parse(std::io::Cursor::new(&file), file.len() as u64, path).unwrap();
+        assert_eq!(parsed.tensors[0].offset, file.len() as u64 - 44);
+        parsed.tensors[0].offset = 0;
+        assert_eq!(parsed, info);
+    }
+
+    #[test]
+    fn preset_names() {
+        for preset in [
+            WhisperPreset::Default,
+            WhisperPreset::FastGreedy,
+            WhisperPreset::AccurateBeamSearch,
+            WhisperPreset::Streaming,
+            WhisperPreset::Subtitles,
+        ] {
+            assert_eq!(preset.name().parse(), Ok(preset));
+        }
+
+        assert_eq!(
+            "fast_greedy".parse::<WhisperPreset>(),
+            Err(WhisperPresetParseError("fast_greedy".to_string()))
+        );
+    }
+
+    #[test]
+    fn memory_usage() {
+        let hparams = WhisperHparams {
+            n_vocab: 51864,
+            n_audio_ctx: 1500,
+            n_audio_state: 384,
+            n_audio_head: 6,
+            n_audio_layer: 4,
+            n_text_ctx: 448,
+            n_text_state: 384,
+            n_text_head: 6,
+            n_text_layer: 4,
+            n_mels: 80,
+            ftype: 1,
+        };
+        let single = WhisperMemoryParams::default();
+        let session = WhisperMemoryUsage::session(&hparams, single);
+        // The key/value caches whisper.cpp reports for tiny models, 8.26 MB and 9.22 MB
+        assert_eq!(session.kv_cache, 8_257_536 + 9_216_000);
+        assert_eq!(session.weights, 0);
+        assert!(session.compute > 0);
+
+        let mut params = WhisperParams::try_from(zeroed_c_params()).unwrap();
+        params.strategy = WhisperSampling::BeamSearch {
+            beam_size: 5,
+            patience: -1.0,
+        };
+        let beam = WhisperMemoryParams::new(&params, single.samples);
+        assert_eq!(beam.decoders, 5);
+        let beam_session = WhisperMemoryUsage::session(&hparams, beam);
+        assert!(beam_session.buffers > session.buffers);
+        assert_eq!(beam_session.kv_cache, session.kv_cache);
+
+        params.strategy = WhisperSampling::Greedy { best_of: 0 };
+        let hour = WhisperMemoryParams::new(&params, 3600 * 16000);
+        assert_eq!(hour.decoders, 1);
+        let hour_session = WhisperMemoryUsage::session(&hparams, hour);
+        assert!(hour_session.buffers > session.buffers);
+        assert_eq!(hour_session.compute, session.compute);
+
+        let info = WhisperModelInfo {
+            hparams,
+            quantization_version: 0,
+            mel_filters: WhisperMelFilters {
+                n_mel: 80,
+                n_fft: 201,
+                data: vec![],
+            },
+            vocab: vec![],
+            tensors: vec![WhisperTensorInfo {
+                name: "decoder.token_embedding.weight".to_string(),
+                shape: vec![384, 51864],
+                ggml_type: GgmlType::F16,
+                offset: 0,
+            }],
+        };
+        let model = WhisperMemoryUsage::model(&info);
+        assert_eq!(model.weights, 384 * 51864 * 2);
+        assert_eq!(model.total(), model.weights);
+
+        let usage = info.memory_usage(single, 2);
+        assert_eq!(usage, model + session + session);
+        assert_eq!(usage.total(), model.total() + 2 * session.total());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn mel_spectrogram() {
+        // A 1 kHz tone, in the 25th bin of the spectrum, for a tenth of a second
+        let samples: Vec<f32> = (0..1600)
+            .map(|i| (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / 16000.0).sin())
+            .collect();
+        let mut filters = WhisperMelFilters {
+            n_mel: 2,
+            n_fft: 201,
+            data: vec![0.0; 2 * 201],
+        };
+        filters.data[25] = 1.0;
+        filters.data[201 + 100] = 1.0;
+
+        let mel = WhisperMel::new(&samples, &filters);
+        assert_eq!(mel.n_mel(), 2);
+        assert_eq!(mel.frames(), 9);
+        assert_eq!(mel.n_len(), 3010);
+        assert_eq!(mel.data().len(), 2 * 3010);
+
+        let (tone, other) = (mel.band(0), mel.band(1));
+        assert!(tone[4] > other[4]);
+        // The log values are clamped to 8 below the loudest, then divided by 4, so the loudest value
+        // ends up at most 2 above the quietest, and the padding is silent
+        let max = mel.data().iter().copied().fold(f32::MIN, f32::max);
+        let min = mel.data().iter().copied().fold(f32::MAX, f32::min);
+        assert!(max - min <= 2.0 + 1e-6);
+        assert_eq!(tone[3000], min);
+        assert_eq!(other[3000], min);
+
+        // Audio shorter than a window still has its padding
+        let short = WhisperMel::new(&samples[..100], &filters);
+        assert_eq!(short.frames(), 1);
+        assert_eq!(short.n_len(), 3000);
+
+        assert!(WhisperMel::from_data(2, 9, mel.data().to_vec()).is_some());
+        assert!(WhisperMel::from_data(3, 9, mel.data().to_vec()).is_none());
+        assert!(WhisperMel::from_data(2, 3011, mel.data().to_vec()).is_none());
+        assert!(WhisperMel::from_data(0, 0, vec![]).is_none());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn mel_fft() {
+        let input: Vec<f32> = (0..400)
+            .map(|i| ((i * 37 % 101) as f32 / 50.0) - 1.0)
+            .collect();
+        let output = mel::Fft::new().transform(&input);
+
+        for k in [0, 1, 25, 199, 200, 399] {
+            let (mut re, mut im) = (0.0f64, 0.0f64);
+            for (i, &value) in input.iter().enumerate() {
+                let angle = 2.0 * std::f64::consts::PI * (k * i) as f64 / 400.0;
+                re += value as f64 * angle.cos();
+                im -= value as f64 * angle.sin();
+            }
+            assert!((output[2 * k] as f64 - re).abs() < 1e-3, "bin {k}");
+            assert!((output[2 * k + 1] as f64 - im).abs() < 1e-3, "bin {k}");
+        }
+    }
+
+    fn sine(frequency: f32, amplitude: f32, samples: usize) -> Vec<f32> {
+        (0..samples)
+            .map(|i| {
+                amplitude * (2.0 * std::f32::consts::PI * frequency * i as f32 / 16000.0).sin()
+            })
+            .collect()
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn preprocessing_filters() {
+        let rms = |samples: &[f32]| WhisperAudioStats::measure(&samples[8000..]).rms_dbfs;
+
+        let mut samples: Vec<f32> = sine(1000.0, 0.5, 16000).iter().map(|s| s + 0.2).collect();
+        assert!((WhisperAudioStats::measure(&samples).dc_offset - 0.2).abs() < 1e-3);
+        let report = WhisperPreprocessor::new(vec![WhisperPreprocessStep::RemoveDc])
+            .apply(&mut samples)
+            .unwrap();
+        assert!((report.input.dc_offset - 0.2).abs() < 1e-3);
+        assert!(report.output.dc_offset.abs() < 1e-3);
+        assert_eq!(report.gain_db, 0.0);
+
+        let high_pass =
+            WhisperPreprocessor::new(vec![WhisperPreprocessStep::HighPass { cutoff_hz: 80.0 }]);
+        let mut rumble = sine(20.0, 0.5, 16000);
+        let before = rms(&rumble);
+        high_pass.apply(&mut rumble).unwrap();
+        assert!(rms(&rumble) < before - 20.0);
+        let mut speech = sine(1000.0, 0.5, 16000);
+        high_pass.apply(&mut speech).unwrap();
+        assert!((rms(&speech) - before).abs() < 0.1);
+
+        let notch = WhisperPreprocessor::new(vec![WhisperPreprocessStep::Notch {
+            frequency_hz: 50.0,
+            q: 10.0,
+        }]);
+        let mut hum = sine(50.0, 0.5, 16000);
+        notch.apply(&mut hum).unwrap();
+        assert!(rms(&hum) < before - 30.0);
+        let mut speech = sine(1000.0, 0.5, 16000);
+        notch.apply(&mut speech).unwrap();
+        assert!((rms(&speech) - before).abs() < 0.1);
+
+        // Invalid filters are caught before anything runs
+        let mut samples = sine(1000.0, 0.5, 1600);
+        let invalid = WhisperPreprocessor::new(vec![
+            WhisperPreprocessStep::RemoveDc,
+            WhisperPreprocessStep::HighPass { cutoff_hz: 8000.0 },
+        ]);
+        assert!(matches!(
+            invalid.apply(&mut samples),
+            Err(WhisperPreprocessError::Frequency { .. })
+        ));
+        assert_eq!(samples, sine(1000.0, 0.5, 1600));
+        let invalid = WhisperPreprocessor::new(vec![WhisperPreprocessStep::Notch {
+            frequency_hz: 60.0,
+            q: 0.0,
+        }]);
+        assert!(matches!(
+            invalid.apply(&mut samples),
+            Err(WhisperPreprocessError::Quality { .. })
+        ));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn preprocessing_levels() {
+        // A 1 kHz sine at -20 dBFS is at -23 LUFS
+        let samples = sine(1000.0, 0.1, 16000);
+        let stats = WhisperAudioStats::measure(&samples);
+        assert!((stats.peak_dbfs + 20.0).abs() < 0.01);
+        assert!((stats.rms_dbfs + 23.01).abs() < 0.01);
+        assert!((stats.loudness_lufs.unwrap() + 23.0).abs() < 0.1);
+        assert!(!stats.is_clipped());
+        assert_eq!(
+            WhisperAudioStats::measure(&samples[..6000]).loudness_lufs,
+            None
+        );
+
+        let mut samples = sine(1000.0, 0.01, 16000);
+        let report = WhisperPreprocessor::default().apply(&mut samples).unwrap();
+        assert!((report.gain_db - 20.0).abs() < 0.1);
+        assert!((report.output.loudness_lufs.unwrap() + 23.0).abs() < 0.1);
+
+        // The gain is limited by the peak
+        let mut samples = sine(1000.0, 0.01, 16000);
+        let report = WhisperPreprocessor::new(vec![WhisperPreprocessStep::LoudnessNormalize {
+            target_lufs: -5.0,
+            max_peak_dbfs: -6.0,
+        }])
+        .apply(&mut samples)
+        .unwrap();
+        assert!((report.output.peak_dbfs + 6.0).abs() < 0.01);
+
+        let mut samples = sine(1000.0, 0.01, 800);
+        let report = WhisperPreprocessor::new(vec![WhisperPreprocessStep::PeakNormalize {
+            target_dbfs: -1.0,
+        }])
+        .apply(&mut samples)
+        .unwrap();
+        assert!((report.output.peak_dbfs + 1.0).abs() < 0.01);
+
+        let mut silence = vec![0.0; 16000];
+        let report = WhisperPreprocessor::default().apply(&mut silence).unwrap();
+        assert_eq!(report.output.peak_dbfs, f32::NEG_INFINITY);
+        assert_eq!(report.output.loudness_lufs, None);
+        assert_eq!(report.gain_db, 0.0);
+        assert!(silence.iter().all(|&sample| sample == 0.0));
+
+        // Overdriven audio flattens at full scale
+        let clipped: Vec<f32> = sine(100.0, 1.5, 1600)
+            .into_iter()
+            .map(|sample| sample.clamp(-1.0, 1.0))
+            .collect();
+        let stats = WhisperAudioStats::measure(&clipped);
+        assert!(stats.is_clipped());
+        assert!(stats.clipped_samples > 100);
+        assert_eq!(
+            WhisperAudioStats::measure(&sine(1000.0, 1.0, 1600)).clipped_samples,
+            0
+        );
+    }
+
+    fn zeroed_c_params() -> whisper_full_params {
+        unsafe {
+            // SAFETY: every field of `whisper_full_params` is valid when zeroed
+            std::mem::zeroed()
+        }
+    }
+
+    #[test]
+    fn invalid_c_params() {
+        let c_params = whisper_full_params {
+            strategy: 42,
+            ..zeroed_c_params()
+        };
+        assert!(matches!(
+            WhisperParams::try_from(c_params),
+            Err(WhisperParamsError::UnknownSamplingStrategy(42))
+        ));
+
+        let language = c"\xff";
+        let c_params = whisper_full_params {
+            language: language.as_ptr(),
+            ..zeroed_c_params()
+        };
+        assert!(matches!(
+            WhisperParams::try_from(c_params),
+            Err(WhisperParamsError::Language(_))
+        ));
+
+        let c_params = whisper_full_params {
+            prompt_n_tokens: 3,
+            ..zeroed_c_params()
+        };
+        assert!(matches!(
+            WhisperParams::try_from(c_params),
+            Err(WhisperParamsError::PromptTokens(3))
+        ));
+
+        let rule = [whisper_grammar_element {
+            type_: 42,
+            value: 0,
+        }];
+        let mut rules = [rule.as_ptr()];
+        let c_params = whisper_full_params {
+            grammar_rules: rules.as_mut_ptr(),
+            n_grammar_rules: 1,
+            ..zeroed_c_params()
+        };
+        assert!(matches!(
+            WhisperParams::try_from(c_params),
+            Err(WhisperParamsError::UnknownGrammarElement(42))
+        ));
+
+        let initial_prompt = c"\xff";
+        let c_params = whisper_full_params {
+            initial_prompt: initial_prompt.as_ptr(),
+            ..zeroed_c_params()
+        };
+        assert!(matches!(
+            WhisperParams::try_from(c_params),
+            Err(WhisperParamsError::InitialPrompt(_))
+        ));
+
+        let c_params = whisper_full_params {
+            n_grammar_rules: 2,
+            ..zeroed_c_params()
+        };
+        assert!(matches!(
+            WhisperParams::try_from(c_params),
+            Err(WhisperParamsError::GrammarRules(2))
+        ));
+
+        let end = [whisper_grammar_element {
+            type_: whisper_gretype_WHISPER_GRETYPE_END,
+            value: 0,
+        }];
+        let mut rules = [end.as_ptr(), std::ptr::null()];
+        let c_params = whisper_full_params {
+            grammar_rules: rules.as_mut_ptr(),
+            n_grammar_rules: 2,
+            ..zeroed_c_params()
+        };
+        assert!(matches!(
+            WhisperParams::try_from(c_params),
+            Err(WhisperParamsError::GrammarRule(1))
+        ));
+
+        // The only error converting the other way
+        let mut params = WhisperParams::new(WhisperSampling::default_greedy());
+        params.initial_prompt = "a nul \0 byte".to_string();
+        assert!(matches!(
+            unsafe { params.c_params() },
+            Err(WhisperParamsError::SessionInitialization(_))
+        ));
+    }
+
+    fn sampling() -> impl Strategy<Value = WhisperSampling> {
+        prop_oneof![
+            any::<u32>().prop_map(|best_of| WhisperSampling::Greedy { best_of }),
+            (any::<u32>(), -10f32..10.).prop_map(|(beam_size, patience)| {
+                WhisperSampling::BeamSearch {
+                    beam_size,
+                    patience,
+                }
+            }),
+        ]
+    }
+
+    fn grammar_element() -> impl Strategy<Value = WhisperGrammarElement> {
+        prop_oneof![
+            Just(WhisperGrammarElement::Alt),
+            any::<u32>().prop_map(WhisperGrammarElement::RuleRef),
+            any::<u32>().prop_map(WhisperGrammarElement::Char),
+            any::<u32>().prop_map(WhisperGrammarElement::CharNot),
+            any::<u32>().prop_map(WhisperGrammarElement::CharRangeUpper),
+            any::<u32>().prop_map(WhisperGrammarElement::CharAlt),
+        ]
+    }
+
+    prop_compose! {
+        fn params()(
+            strategy in sampling(),
+            bools in vec(any::<bool>(), 16),
+            ints in vec(any::<u32>(), 7),
+            floats in vec(-1000f32..1000., 10),
+            initial_prompt in "[^\0]*",
+            language in "[^\0]*",
+            prompt_tokens in vec(any::<i32>(), 0..16),
+            grammar_rules in vec(vec(grammar_element(), 0..8), 0..4),
+            i_start_rule in any::<usize>(),
+        ) -> WhisperParams {
+            let mut bools = bools.into_iter();
+            let mut bool = || bools.next().unwrap();
+            let mut ints = ints.into_iter();
+            let mut int = || ints.next().unwrap();
+            let mut floats = floats.into_iter();
+            let mut float = || floats.next().unwrap();
+
+            WhisperParams {
+                strategy,
+                thread_count: int(),
+                max_text_ctx: int(),
+                offset_ms: int(),
+                duration_ms: int(),
+                translate: bool(),
+                no_context: bool(),
+                no_timestamps: bool(),
+                single_segment: bool(),
+                print_special: bool(),
+                print_progress: bool(),
+                print_realtime: bool(),
+                print_timestamps: bool(),
+                token_timestamps: bool(),
+                thold_pt: float(),
+                thold_ptsum: float(),
+                max_len: int(),
+                split_on_word: bool(),
+                max_tokens: int(),
+                speed_up: bool(),
+                debug_mode: bool(),
+                audio_ctx: int(),
+                tdrz_enable: bool(),
+                initial_prompt,
+                prompt_tokens,
+                language,
+                detect_language: bool(),
+                suppress_blank: bool(),
+                suppress_non_speech_tokens: bool(),
+                temperature: float(),
+                max_initial_ts: float(),
+                length_penalty: float(),
+                temperature_inc: float(),
+                entropy_thold: float(),
+                logprob_thold: float(),
+                no_speech_thold: float(),
+                _new_segment_callback: (),
+                _new_segment_callback_user_data: (),
+                _progress_callback: (),
+                _progress_callback_user_data: (),
+                _encoder_begin_callback: (),
+                _encoder_begin_callback_user_data: (),
+                _abort_callback: (),
+                _abort_callback_user_data: (),
+                _logits_filter_callback: (),
+                _logits_filter_callback_user_data: (),
+                grammar_rules,
+                i_start_rule,
+                grammar_penalty: float(),
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        #[cfg_attr(miri, ignore)]
+        fn c_params_round_trip(params in params()) {
+            let (storage, c_params) = unsafe { params.c_params() }.unwrap();
+            let converted = WhisperParams::try_from(c_params).unwrap();
+            drop(storage);
+
+            prop_assert_eq!(params, converted);
         }
     }
 }
@@ -0,0 +1,242 @@
+use std::cell::RefCell;
+use std::fmt::{Display, Formatter};
+use std::sync::{PoisonError, RwLock};
+
+use tracing::{debug, error, info, trace, warn, Level, Span};
+
+use crate::set_log;
+
+/// The [`tracing`] target of the events emitted by [`WhisperLogger::tracing`].
+pub const LOG_TARGET: &str = "whisper_cpp::ggml";
+
+/// The [`WhisperLogger`] set by [`set_logger`], if [`None`] the default [`WhisperLogger`] is used.
+static LOGGER: RwLock<Option<WhisperLogger>> = RwLock::new(None);
+
+thread_local! {
+    /// Error messages logged on this thread while inside [`capture_errors`].
+    static CAPTURED_ERRORS: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+/// Sets the logger receiving all messages from [`whisper.cpp`][whisper.cpp] and *ggml*, replacing
+/// the previous one.
+///
+/// Until this is called, messages are forwarded to [`tracing`] as set by
+/// [`WhisperLogger::default`].
+///
+/// [whisper.cpp]: https://github.com/ggerganov/whisper.cpp/
+pub fn set_logger(logger: WhisperLogger) {
+    set_log();
+
+    *LOGGER.write().unwrap_or_else(PoisonError::into_inner) = Some(logger);
+}
+
+/// Dispatches a message from [`whisper.cpp`][whisper.cpp] to the current [`WhisperLogger`].
+///
+/// [whisper.cpp]: https://github.com/ggerganov/whisper.cpp/
+pub(crate) fn log(level: WhisperLogLevel, message: &str) {
+    if level == WhisperLogLevel::Error {
+        CAPTURED_ERRORS.with(|captured| {
+            if let Some(captured) = captured.borrow_mut().as_mut() {
+                captured.push(message.to_string());
+            }
+        });
+    }
+
+    let logger = LOGGER.read().unwrap_or_else(PoisonError::into_inner);
+
+    match logger.as_ref() {
+        Some(logger) => logger.log(level, message),
+        None => WhisperLogger::default().log(level, message),
+    }
+}
+
+/// Runs `f`, collecting every error message [`whisper.cpp`][whisper.cpp] logs on this thread in
+/// the meantime, regardless of the current [`WhisperLogger`].
+///
+/// [whisper.cpp]: https://github.com/ggerganov/whisper.cpp/
+pub(crate) fn capture_errors<T>(f: impl FnOnce() -> T) -> (T, WhisperErrorLog) {
+    CAPTURED_ERRORS.with(|captured| *captured.borrow_mut() = Some(vec![]));
+    let res = f();
+    let messages = CAPTURED_ERRORS.with(|captured| captured.borrow_mut().take());
+
+    (res, WhisperErrorLog(messages.unwrap_or_default()))
+}
+
+/// The error messages logged by [`whisper.cpp`][whisper.cpp] during a failed operation, in the
+/// order they were logged.
+///
+/// [whisper.cpp]: https://github.com/ggerganov/whisper.cpp/
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WhisperErrorLog(pub Vec<String>);
+
+impl WhisperErrorLog {
+    /// The last error message logged, usually the least specific one.
+    pub fn last(&self) -> Option<&str> {
+        self.0.last().map(String::as_str)
+    }
+}
+
+impl Display for WhisperErrorLog {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.0.is_empty() {
+            write!(f, "no error was logged")
+        } else {
+            write!(f, "{}", self.0.join("; "))
+        }
+    }
+}
+
+/// The severity of a message logged by [`whisper.cpp`][whisper.cpp] or *ggml*.
+///
+/// [whisper.cpp]: https://github.com/ggerganov/whisper.cpp/
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum WhisperLogLevel {
+    /// Any level not known by this crate.
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+/// A single message logged by [`whisper.cpp`][whisper.cpp] or *ggml*.
+///
+/// [whisper.cpp]: https://github.com/ggerganov/whisper.cpp/
+#[derive(Debug)]
+pub struct WhisperLogRecord<'a> {
+    /// The severity of the message.
+    pub level: WhisperLogLevel,
+
+    /// The message, without a trailing newline.
+    pub message: &'a str,
+
+    /// The span of the [`WhisperModel`][crate::WhisperModel] or
+    /// [`WhisperSession`][crate::WhisperSession] that produced the message, disabled if the message
+    /// did not come from either.
+    ///
+    /// This is [`Span::current`] on the thread running [`whisper.cpp`][whisper.cpp], so it is also
+    /// disabled when no [`tracing`] subscriber enables the spans of this crate, as then they are
+    /// not recorded at all.
+    ///
+    /// [whisper.cpp]: https://github.com/ggerganov/whisper.cpp/
+    pub span: &'a Span,
+}
+
+/// The [`tracing`] [`Level`] each [`WhisperLogLevel`] is emitted with.
+#[derive(Clone, Copy, Debug)]
+pub struct TracingLevels {
+    pub error: Level,
+    pub warn: Level,
+    pub info: Level,
+    pub debug: Level,
+}
+
+impl TracingLevels {
+    fn get(&self, level: WhisperLogLevel) -> Level {
+        match level {
+            WhisperLogLevel::Error => self.error,
+            WhisperLogLevel::Warn => self.warn,
+            WhisperLogLevel::Info => self.info,
+            WhisperLogLevel::Debug => self.debug,
+        }
+    }
+}
+
+impl Default for TracingLevels {
+    fn default() -> Self {
+        Self {
+            error: Level::ERROR,
+            warn: Level::WARN,
+            info: Level::INFO,
+            debug: Level::TRACE,
+        }
+    }
+}
+
+/// Where the messages received by a [`WhisperLogger`] go.
+enum WhisperLogSink {
+    Tracing(TracingLevels),
+    Custom(Box<dyn Fn(&WhisperLogRecord<'_>) + Send + Sync>),
+    Silent,
+}
+
+/// Routes the messages logged by [`whisper.cpp`][whisper.cpp] and *ggml*, see [`set_logger`].
+///
+/// [whisper.cpp]: https://github.com/ggerganov/whisper.cpp/
+pub struct WhisperLogger {
+    sink: WhisperLogSink,
+    min_level: WhisperLogLevel,
+}
+
+impl WhisperLogger {
+    /// Forwards messages to [`tracing`] under the [`LOG_TARGET`] target, using the provided
+    /// [`TracingLevels`].
+    ///
+    /// Events are emitted inside the span of the [`WhisperModel`][crate::WhisperModel] or
+    /// [`WhisperSession`][crate::WhisperSession] that produced them.
+    pub fn tracing(levels: TracingLevels) -> Self {
+        Self {
+            sink: WhisperLogSink::Tracing(levels),
+            min_level: WhisperLogLevel::Debug,
+        }
+    }
+
+    /// Calls the provided closure for every message.
+    ///
+    /// The closure is called from whichever thread is running [`whisper.cpp`][whisper.cpp], and
+    /// must not call [`set_logger`]. Telling which model or session logged a message requires a
+    /// [`tracing`] subscriber, see [`WhisperLogRecord::span`].
+    ///
+    /// [whisper.cpp]: https://github.com/ggerganov/whisper.cpp/
+    pub fn custom<F>(callback: F) -> Self
+    where
+        F: Fn(&WhisperLogRecord<'_>) + Send + Sync + 'static,
+    {
+        Self {
+            sink: WhisperLogSink::Custom(Box::new(callback)),
+            min_level: WhisperLogLevel::Debug,
+        }
+    }
+
+    /// Discards every message.
+    pub fn silent() -> Self {
+        Self {
+            sink: WhisperLogSink::Silent,
+            min_level: WhisperLogLevel::Debug,
+        }
+    }
+
+    /// Discards messages less severe than `level`.
+    pub fn with_min_level(mut self, level: WhisperLogLevel) -> Self {
+        self.min_level = level;
+        self
+    }
+
+    pub(crate) fn log(&self, level: WhisperLogLevel, message: &str) {
+        if level < self.min_level {
+            return;
+        }
+
+        match &self.sink {
+            WhisperLogSink::Tracing(levels) => match levels.get(level) {
+                Level::ERROR => error!(target: LOG_TARGET, "{message}"),
+                Level::WARN => warn!(target: LOG_TARGET, "{message}"),
+                Level::INFO => info!(target: LOG_TARGET, "{message}"),
+                Level::DEBUG => debug!(target: LOG_TARGET, "{message}"),
+                _ => trace!(target: LOG_TARGET, "{message}"),
+            },
+            WhisperLogSink::Custom(callback) => callback(&WhisperLogRecord {
+                level,
+                message,
+                span: &Span::current(),
+            }),
+            WhisperLogSink::Silent => {}
+        }
+    }
+}
+
+impl Default for WhisperLogger {
+    /// Forwards every message to [`tracing`] using the default [`TracingLevels`].
+    fn default() -> Self {
+        Self::tracing(TracingLevels::default())
+    }
+}
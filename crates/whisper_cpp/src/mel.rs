@@ -0,0 +1,250 @@
+use std::f64::consts::PI;
+
+use crate::WhisperMelFilters;
+
+/// The sample rate of the audio *whisper* models take.
+pub const SAMPLE_RATE: usize = 16000;
+
+/// Samples per frame of the spectrogram.
+pub(crate) const HOP_LENGTH: usize = 160;
+
+/// Samples per window of the short-time Fourier transform.
+const N_FFT: usize = 400;
+
+/// The silence appended to the audio, so that its last 30 seconds window is complete.
+const PADDING: usize = 30 * SAMPLE_RATE;
+
+/// The smallest power before taking the logarithm.
+const MIN_POWER: f64 = 1e-10;
+
+/// The log-mel spectrogram of some audio, as taken by *whisper* models.
+///
+/// [`WhisperMel::new`] computes it the way [whisper.cpp] does, so that giving it to
+/// [`WhisperSession::advance_mel`](crate::WhisperSession::advance_mel) transcribes the same as
+/// giving the samples to [`WhisperSession::advance`](crate::WhisperSession::advance). It can also
+/// be cached, or changed in between.
+///
+/// The values are normalized log powers, one band after the other, and include the frames of the
+/// 30 seconds of silence padding the audio.
+///
+/// [whisper.cpp]: https://github.com/ggerganov/whisper.cpp/
+#[derive(Clone, Debug, PartialEq)]
+pub struct WhisperMel {
+    n_mel: usize,
+
+    /// Frames of the audio, without the padding.
+    frames: usize,
+
+    data: Vec<f32>,
+}
+
+impl WhisperMel {
+    /// Computes the spectrogram of `samples`, 16 kHz mono audio, through the mel filters of a
+    /// model, see [`WhisperModel::mel_filters`](crate::WhisperModel::mel_filters).
+    #[doc(alias = "whisper_pcm_to_mel")]
+    pub fn new(samples: &[f32], filters: &WhisperMelFilters) -> Self {
+        let padded = pad(samples);
+        let n_len = (padded.len() - N_FFT) / HOP_LENGTH;
+        let frames = (1
+            + (samples.len() as i64 + N_FFT as i64 / 2 - N_FFT as i64) / HOP_LENGTH as i64)
+            .max(0) as usize;
+
+        let fft = Fft::new();
+        let window = hann_window();
+        let bins = filters.n_fft.min(N_FFT / 2 + 1);
+        let n_mel = filters.n_mel;
+        let mut data = vec![0.0; n_mel * n_len];
+        let mut windowed = [0.0; N_FFT];
+        let mut power = [0.0; N_FFT / 2 + 1];
+
+        for frame in 0..n_len {
+            let samples = &padded[frame * HOP_LENGTH..][..N_FFT];
+
+            // Frames of silence, such as the padding, have no power
+            if samples.iter().all(|&sample| sample == 0.0) {
+                power.fill(0.0);
+            } else {
+                for (windowed, (sample, weight)) in
+                    windowed.iter_mut().zip(samples.iter().zip(window))
+                {
+                    *windowed = sample * weight;
+                }
+                let spectrum = fft.transform(&windowed);
+                for (power, bin) in power.iter_mut().zip(spectrum.chunks_exact(2)) {
+                    *power = bin[0] * bin[0] + bin[1] * bin[1];
+                }
+            }
+
+            for (band, filter) in filters.data.chunks_exact(filters.n_fft.max(1)).enumerate() {
+                let sum: f64 = power[..bins]
+                    .iter()
+                    .zip(filter)
+                    .map(|(power, weight)| (power * weight) as f64)
+                    .sum();
+                data[band * n_len + frame] = sum.max(MIN_POWER).log10() as f32;
+            }
+        }
+
+        // Clamps the values to 8 below the loudest, before scaling them to about [-1, 1]
+        let max = data
+            .iter()
+            .fold(-1e20f64, |max, &value| max.max(value as f64))
+            - 8.0;
+        for value in &mut data {
+            *value = ((*value as f64).max(max) + 4.0) as f32 / 4.0;
+        }
+
+        Self {
+            n_mel,
+            frames,
+            data,
+        }
+    }
+
+    /// Makes a spectrogram from `data`, laid out as in [`WhisperMel::data`], of which `frames`
+    /// frames are audio.
+    ///
+    /// Returns [`None`] if `data` cannot be split in `n_mel` bands of at least `frames` frames.
+    pub fn from_data(n_mel: usize, frames: usize, data: Vec<f32>) -> Option<Self> {
+        if n_mel == 0 || !data.len().is_multiple_of(n_mel) || data.len() / n_mel < frames {
+            return None;
+        }
+
+        Some(Self {
+            n_mel,
+            frames,
+            data,
+        })
+    }
+
+    /// Number of mel bands.
+    pub fn n_mel(&self) -> usize {
+        self.n_mel
+    }
+
+    /// Number of frames of audio, 100 per second, not counting the padding.
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Number of frames, counting the padding.
+    pub fn n_len(&self) -> usize {
+        self.data.len() / self.n_mel
+    }
+
+    /// The values of the band `band`, one per frame.
+    ///
+    /// ## Panic
+    /// Panics if `band` is not less than [`WhisperMel::n_mel`].
+    pub fn band(&self, band: usize) -> &[f32] {
+        let n_len = self.n_len();
+        &self.data[band * n_len..][..n_len]
+    }
+
+    /// Mutable version of [`WhisperMel::band`].
+    ///
+    /// ## Panic
+    /// Panics if `band` is not less than [`WhisperMel::n_mel`].
+    pub fn band_mut(&mut self, band: usize) -> &mut [f32] {
+        let n_len = self.n_len();
+        &mut self.data[band * n_len..][..n_len]
+    }
+
+    /// Every value, one band after the other.
+    pub fn data(&self) -> &[f32] {
+        &self.data
+    }
+
+    /// Mutable version of [`WhisperMel::data`].
+    pub fn data_mut(&mut self) -> &mut [f32] {
+        &mut self.data
+    }
+
+    pub fn into_data(self) -> Vec<f32> {
+        self.data
+    }
+}
+
+/// Centers the windows on the samples by reflecting the start of the audio, and appends
+/// [`PADDING`] samples of silence, followed by half a window.
+fn pad(samples: &[f32]) -> Vec<f32> {
+    let half = N_FFT / 2;
+    let mut padded = vec![0.0; half + samples.len() + PADDING + half];
+
+    for (padded, sample) in padded[..half].iter_mut().rev().zip(samples.iter().skip(1)) {
+        *padded = *sample;
+    }
+    padded[half..][..samples.len()].copy_from_slice(samples);
+
+    padded
+}
+
+/// The periodic Hann window.
+fn hann_window() -> [f32; N_FFT] {
+    std::array::from_fn(|i| (0.5 * (1.0 - (2.0 * PI * i as f64 / N_FFT as f64).cos())) as f32)
+}
+
+/// The fast Fourier transform of [`N_FFT`] real values, splitting them in even and odd halves
+/// down to odd lengths, which are transformed directly.
+pub(crate) struct Fft {
+    cos: [f32; N_FFT],
+    sin: [f32; N_FFT],
+}
+
+impl Fft {
+    pub(crate) fn new() -> Self {
+        let angle = |i: usize| 2.0 * PI * i as f64 / N_FFT as f64;
+
+        Self {
+            cos: std::array::from_fn(|i| angle(i).cos() as f32),
+            sin: std::array::from_fn(|i| angle(i).sin() as f32),
+        }
+    }
+
+    /// Returns the complex spectrum of `input`, as interleaved real and imaginary parts.
+    pub(crate) fn transform(&self, input: &[f32]) -> Vec<f32> {
+        let n = input.len();
+        let mut output = vec![0.0; 2 * n];
+
+        if n == 1 {
+            output[0] = input[0];
+            return output;
+        }
+
+        // Angles are multiples of 2π / n, found in the tables every `step` entries
+        let step = N_FFT / n;
+
+        if !n.is_multiple_of(2) {
+            for k in 0..n {
+                let (mut re, mut im) = (0.0, 0.0);
+                for (i, value) in input.iter().enumerate() {
+                    let index = (k * i * step) % N_FFT;
+                    re += value * self.cos[index];
+                    im -= value * self.sin[index];
+                }
+                output[2 * k] = re;
+                output[2 * k + 1] = im;
+            }
+
+            return output;
+        }
+
+        let even: Vec<_> = input.iter().step_by(2).copied().collect();
+        let odd: Vec<_> = input.iter().skip(1).step_by(2).copied().collect();
+        let even = self.transform(&even);
+        let odd = self.transform(&odd);
+
+        for k in 0..n / 2 {
+            let (re, im) = (self.cos[k * step], -self.sin[k * step]);
+            let (re_odd, im_odd) = (odd[2 * k], odd[2 * k + 1]);
+            let (twiddled_re, twiddled_im) = (re * re_odd - im * im_odd, re * im_odd + im * re_odd);
+
+            output[2 * k] = even[2 * k] + twiddled_re;
+            output[2 * k + 1] = even[2 * k + 1] + twiddled_im;
+            output[2 * (k + n / 2)] = even[2 * k] - twiddled_re;
+            output[2 * (k + n / 2) + 1] = even[2 * k + 1] - twiddled_im;
+        }
+
+        output
+    }
+}
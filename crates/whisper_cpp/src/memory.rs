@@ -0,0 +1,158 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::mel::{HOP_LENGTH, SAMPLE_RATE};
+use crate::{WhisperHparams, WhisperModelInfo, WhisperParams, WhisperSampling};
+
+/// *whisper.cpp* allocates room for 3 times the text context in the self-attention cache, to fit
+/// the tokens of every decoder.
+const KV_SELF_FACTOR: usize = 3;
+
+/// Bytes of an element of the key/value caches, which are `f16`.
+const KV_ELEMENT: usize = 2;
+
+/// Bytes of an element of the intermediate results, which are `f32`.
+const F32: usize = 4;
+
+/// What the memory used by a session depends on, besides its model.
+///
+/// The audio context (`audio_ctx`) is not part of these: *whisper.cpp* sizes the buffers of a
+/// session for the full audio context of its model when the session is created, whatever the audio
+/// context of its runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WhisperMemoryParams {
+    /// Number of decoders run side by side: the beam size, or `best_of` with greedy sampling.
+    pub decoders: usize,
+
+    /// Number of samples of the longest audio transcribed in a single run.
+    pub samples: usize,
+}
+
+impl WhisperMemoryParams {
+    /// The memory parameters of runs with `params` over `samples` samples.
+    pub fn new(params: &WhisperParams, samples: usize) -> Self {
+        Self {
+            decoders: decoders(&params.strategy),
+            samples,
+        }
+    }
+}
+
+impl Default for WhisperMemoryParams {
+    /// A single decoder over 30 seconds of audio, the window of *whisper* models.
+    fn default() -> Self {
+        Self {
+            decoders: 1,
+            samples: 30 * SAMPLE_RATE,
+        }
+    }
+}
+
+/// The number of decoders *whisper.cpp* runs for `strategy`.
+pub(crate) fn decoders(strategy: &WhisperSampling) -> usize {
+    match *strategy {
+        WhisperSampling::Greedy { best_of } => best_of.max(1) as usize,
+        WhisperSampling::BeamSearch { beam_size, .. } => beam_size.max(1) as usize,
+    }
+}
+
+/// Memory used by a model or its sessions, in bytes, counting host and GPU memory alike.
+///
+/// Apart from the weights, these are estimates from the sizes of the buffers *whisper.cpp*
+/// allocates, rounded up where they depend on the way it evaluates the model.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, derive_more::Add, derive_more::AddAssign)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WhisperMemoryUsage {
+    /// The tensors of the model.
+    pub weights: u64,
+
+    /// The self-attention and cross-attention key/value caches.
+    pub kv_cache: u64,
+
+    /// The intermediate results of the convolutions, the encoder and the decoder.
+    pub compute: u64,
+
+    /// The audio, its mel spectrogram, and the logits of every decoder.
+    pub buffers: u64,
+}
+
+impl WhisperMemoryUsage {
+    pub fn total(&self) -> u64 {
+        self.weights + self.kv_cache + self.compute + self.buffers
+    }
+
+    /// Memory used by a loaded model, without sessions.
+    pub fn model(info: &WhisperModelInfo) -> Self {
+        Self {
+            weights: info.tensor_bytes(),
+            ..Default::default()
+        }
+    }
+
+    /// Memory used by a session of a model with `hparams`.
+    pub fn session(hparams: &WhisperHparams, params: WhisperMemoryParams) -> Self {
+        let [n_vocab, audio_ctx, audio_state, audio_head, n_text_ctx, text_state, text_head, text_layer, n_mels] =
+            [
+                hparams.n_vocab,
+                hparams.n_audio_ctx,
+                hparams.n_audio_state,
+                hparams.n_audio_head,
+                hparams.n_text_ctx,
+                hparams.n_text_state,
+                hparams.n_text_head,
+                hparams.n_text_layer,
+                hparams.n_mels,
+            ]
+            .map(|value| value.max(0) as usize);
+
+        let kv_self = 2 * text_layer * text_state * KV_SELF_FACTOR * n_text_ctx * KV_ELEMENT;
+        let kv_cross = 2 * text_layer * text_state * audio_ctx * KV_ELEMENT;
+
+        // The convolutions run over twice as many mel frames as the audio context
+        let frames = 2 * audio_ctx;
+        let conv = n_mels * frames * F32 + 3 * n_mels * frames * 2 + 2 * audio_state * frames * F32;
+        // The attention scores and their softmax, and the activations of the MLP
+        let encode =
+            2 * audio_head * audio_ctx * audio_ctx * F32 + 12 * audio_ctx * audio_state * F32;
+        let cross = 2 * audio_ctx * text_state * F32;
+        // The logits of a whole text context, and the attention scores over the text context and
+        // the audio
+        let decode = n_text_ctx * n_vocab * F32
+            + 2 * text_head * n_text_ctx * (KV_SELF_FACTOR * n_text_ctx + audio_ctx) * F32
+            + 12 * n_text_ctx * text_state * F32;
+
+        // The samples are copied once by the session and once when padded for the spectrogram,
+        // which is padded with 30 seconds of silence
+        let audio = 2 * params.samples * F32;
+        let mel = n_mels * (params.samples / HOP_LENGTH + 3000) * F32;
+        // The logits of a whole text context, and the logits, probabilities and log probabilities
+        // of each decoder
+        let logits = n_text_ctx * n_vocab * F32 + 3 * params.decoders.max(1) * n_vocab * F32;
+
+        Self {
+            weights: 0,
+            kv_cache: (kv_self + kv_cross) as u64,
+            compute: (conv + encode + cross + decode) as u64,
+            buffers: (audio + mel + logits) as u64,
+        }
+    }
+}
+
+impl WhisperModelInfo {
+    /// Estimates the memory used by this model once loaded, along with `sessions` sessions running
+    /// with `params`, without loading it.
+    ///
+    /// There is no audio context to pass: sessions use as much memory whatever the `audio_ctx` of
+    /// their runs, see [`WhisperMemoryParams`].
+    pub fn memory_usage(&self, params: WhisperMemoryParams, sessions: usize) -> WhisperMemoryUsage {
+        let session = WhisperMemoryUsage::session(&self.hparams, params);
+        let mut usage = WhisperMemoryUsage::model(self);
+
+        for _ in 0..sessions {
+            usage += session;
+        }
+
+        usage
+    }
+}
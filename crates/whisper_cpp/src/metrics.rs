@@ -0,0 +1,139 @@
+use std::io::Write;
+use std::ops::Range;
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::WhisperTokenData;
+
+/// Quality measures of a transcribed segment, to decide whether it can be trusted.
+///
+/// See [`WhisperSession::segment_metrics`](crate::WhisperSession::segment_metrics).
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WhisperSegmentMetrics {
+    /// The average log probability of the text and timestamp tokens of the segment.
+    ///
+    /// Values below `-1.0` usually mean the model was guessing.
+    pub avg_logprob: f32,
+
+    /// The ratio between the size of the segment text and the size of its zlib compressed form,
+    /// as computed by OpenAI's implementation.
+    ///
+    /// Values above `2.4` usually mean the text is repetitive, a common failure mode.
+    pub compression_ratio: f32,
+
+    /// The lowest probability of a text token of the segment, or `1.0` if it has none.
+    pub min_token_probability: f32,
+
+    /// A heuristic for whether the segment contains no speech, which is *not* the no speech
+    /// probability of OpenAI's implementation.
+    ///
+    /// [whisper.cpp] does not expose the probability of its no speech token, so this is the
+    /// complement of the probability of the first token of the segment, how unsure the model was
+    /// of the way it starts. It is high over silence, but also over hesitant or noisy speech, so it
+    /// should only be trusted along with another signal, as [`Self::is_no_speech`] does.
+    ///
+    /// [whisper.cpp]: https://github.com/ggerganov/whisper.cpp/
+    pub no_speech_probability: f32,
+}
+
+impl WhisperSegmentMetrics {
+    /// Computes the metrics of a segment from its text and tokens, ignoring the tokens with an id
+    /// in `control_tokens`.
+    pub(crate) fn new(text: &str, tokens: &[WhisperTokenData], control_tokens: Range<i32>) -> Self {
+        let sampled = tokens
+            .iter()
+            .filter(|token| !control_tokens.contains(&token.id));
+
+        let (logprob_sum, sampled_count) = sampled.clone().fold((0.0, 0), |(sum, count), token| {
+            (sum + token.log_probability, count + 1)
+        });
+
+        let min_token_probability = sampled
+            .filter(|token| token.id < control_tokens.start)
+            .map(|token| token.probability)
+            .fold(1.0, f32::min);
+
+        Self {
+            avg_logprob: if sampled_count == 0 {
+                0.0
+            } else {
+                logprob_sum / sampled_count as f32
+            },
+            compression_ratio: compression_ratio(text),
+            min_token_probability,
+            no_speech_probability: tokens.first().map_or(1.0, |token| 1.0 - token.probability),
+        }
+    }
+
+    /// Whether the segment is likely to be wrong, because its text is too repetitive, or the model
+    /// was not confident enough in it.
+    pub fn is_low_quality(&self, thresholds: &WhisperQualityThresholds) -> bool {
+        self.compression_ratio > thresholds.compression_ratio
+            || self.avg_logprob < thresholds.avg_logprob
+            || self.min_token_probability < thresholds.min_token_probability
+    }
+
+    /// Whether the segment is likely to contain no speech at all, in which case its text should be
+    /// dropped.
+    ///
+    /// As in OpenAI's implementation, this requires the model to also not be confident in the text,
+    /// although [`Self::no_speech_probability`] is only a heuristic.
+    pub fn is_no_speech(&self, thresholds: &WhisperQualityThresholds) -> bool {
+        self.no_speech_probability > thresholds.no_speech_probability
+            && self.avg_logprob < thresholds.avg_logprob
+    }
+}
+
+/// The limits past which [`WhisperSegmentMetrics::is_low_quality`] and
+/// [`WhisperSegmentMetrics::is_no_speech`] report a segment.
+///
+/// The defaults are the thresholds of OpenAI's implementation, with no limit on the probability
+/// of single tokens.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct WhisperQualityThresholds {
+    /// The highest accepted [`WhisperSegmentMetrics::compression_ratio`].
+    pub compression_ratio: f32,
+
+    /// The lowest accepted [`WhisperSegmentMetrics::avg_logprob`].
+    pub avg_logprob: f32,
+
+    /// The lowest accepted [`WhisperSegmentMetrics::min_token_probability`].
+    pub min_token_probability: f32,
+
+    /// The highest accepted [`WhisperSegmentMetrics::no_speech_probability`], OpenAI's threshold
+    /// for its actual no speech probability by default.
+    pub no_speech_probability: f32,
+}
+
+impl Default for WhisperQualityThresholds {
+    fn default() -> Self {
+        Self {
+            compression_ratio: 2.4,
+            avg_logprob: -1.0,
+            min_token_probability: 0.0,
+            no_speech_probability: 0.6,
+        }
+    }
+}
+
+/// The ratio between the size of `text` and the size of its zlib compressed form.
+///
+/// Returns `0.0` for an empty text.
+pub fn compression_ratio(text: &str) -> f32 {
+    if text.is_empty() {
+        return 0.0;
+    }
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    // Writing to a `Vec` cannot fail
+    encoder.write_all(text.as_bytes()).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    text.len() as f32 / compressed.len() as f32
+}
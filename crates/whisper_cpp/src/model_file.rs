@@ -0,0 +1,95 @@
+use std::ffi::CString;
+use std::fs::File;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::WhisperModelInfo;
+
+/// The magic number at the start of every *ggml* *whisper* model file.
+pub(crate) const GGML_FILE_MAGIC: u32 = 0x67676d6c;
+
+#[derive(Debug, Error)]
+pub enum WhisperModelFileError {
+    #[error("model file does not exist: {0}")]
+    NotFound(PathBuf),
+    #[error("permission denied while opening model file: {0}")]
+    PermissionDenied(PathBuf),
+    #[error("model path is a directory: {0}")]
+    Directory(PathBuf),
+    #[error("failed to read model file {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("{path} is not a ggml whisper model, found magic {magic:#010x}")]
+    InvalidMagic { path: PathBuf, magic: u32 },
+    #[error("{0} is too short to be a ggml whisper model")]
+    Truncated(PathBuf),
+    #[error("{path} is not a valid ggml whisper model: {reason}")]
+    Invalid { path: PathBuf, reason: String },
+    #[error("model path contains a NUL byte: {0}")]
+    Nul(PathBuf),
+    #[error("model path is not valid UTF-8: {0}")]
+    NotUnicode(PathBuf),
+}
+
+/// Checks that the file at `path` is a complete *ggml* *whisper* model, returning the path as a
+/// [`CString`] that can be handed to [`whisper.cpp`][whisper.cpp], along with the header of the
+/// model.
+///
+/// [whisper.cpp]: https://github.com/ggerganov/whisper.cpp/
+pub(crate) fn validate(path: &Path) -> Result<(CString, WhisperModelInfo), WhisperModelFileError> {
+    let c_path = c_path(path)?;
+
+    let file = open(path)?;
+    let info = WhisperModelInfo::from_file(file, path)?;
+
+    Ok((c_path, info))
+}
+
+/// Opens the file at `path` for reading, checking that it is not a directory.
+pub(crate) fn open(path: &Path) -> Result<File, WhisperModelFileError> {
+    let file = File::open(path).map_err(|e| io_error(path, e))?;
+
+    // On Unix, opening a directory for reading succeeds, so this has to be checked explicitly
+    if file.metadata().map_err(|e| io_error(path, e))?.is_dir() {
+        return Err(WhisperModelFileError::Directory(path.to_path_buf()));
+    }
+
+    Ok(file)
+}
+
+pub(crate) fn io_error(path: &Path, source: std::io::Error) -> WhisperModelFileError {
+    match source.kind() {
+        ErrorKind::NotFound => WhisperModelFileError::NotFound(path.to_path_buf()),
+        ErrorKind::PermissionDenied => WhisperModelFileError::PermissionDenied(path.to_path_buf()),
+        _ => WhisperModelFileError::Io {
+            path: path.to_path_buf(),
+            source,
+        },
+    }
+}
+
+/// Converts `path` into a [`CString`] without any lossy conversion.
+///
+/// On Unix any path without NUL bytes is accepted, as *whisper.cpp* passes it untouched to the
+/// operating system. Elsewhere, paths must be valid UTF-8.
+fn c_path(path: &Path) -> Result<CString, WhisperModelFileError> {
+    #[cfg(unix)]
+    let bytes = {
+        use std::os::unix::ffi::OsStrExt;
+        path.as_os_str().as_bytes().to_vec()
+    };
+
+    #[cfg(not(unix))]
+    let bytes = path
+        .to_str()
+        .ok_or_else(|| WhisperModelFileError::NotUnicode(path.to_path_buf()))?
+        .as_bytes()
+        .to_vec();
+
+    CString::new(bytes).map_err(|_| WhisperModelFileError::Nul(path.to_path_buf()))
+}
@@ -0,0 +1,487 @@
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read, Seek, SeekFrom};
+use std::path::Path;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::model_file::{self, WhisperModelFileError, GGML_FILE_MAGIC};
+
+/// `ftype` is stored as `quantization_version * GGML_QNT_VERSION_FACTOR + ftype`.
+const GGML_QNT_VERSION_FACTOR: i32 = 1000;
+
+/// The contents of a *ggml* *whisper* model file, read without [whisper.cpp], apart from the
+/// tensor data.
+///
+/// [whisper.cpp]: https://github.com/ggerganov/whisper.cpp/
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WhisperModelInfo {
+    pub hparams: WhisperHparams,
+
+    /// The version of the quantization formats of the tensors, 0 for unquantized models.
+    pub quantization_version: i32,
+
+    pub mel_filters: WhisperMelFilters,
+
+    /// The bytes of each token of the vocabulary stored in the file, indexed by id.
+    ///
+    /// *whisper.cpp* adds the special tokens following them, up to [`WhisperHparams::n_vocab`].
+    pub vocab: Vec<Vec<u8>>,
+
+    /// Every tensor, in file order.
+    pub tensors: Vec<WhisperTensorInfo>,
+}
+
+/// The hyperparameters of a model.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WhisperHparams {
+    pub n_vocab: i32,
+    pub n_audio_ctx: i32,
+    pub n_audio_state: i32,
+    pub n_audio_head: i32,
+    pub n_audio_layer: i32,
+    pub n_text_ctx: i32,
+    pub n_text_state: i32,
+    pub n_text_head: i32,
+    pub n_text_layer: i32,
+    pub n_mels: i32,
+
+    /// The type most tensors are stored as, see [`WhisperHparams::ftype_name`].
+    pub ftype: i32,
+}
+
+impl WhisperHparams {
+    /// Whether the model was trained on many languages, or only on English.
+    pub fn is_multilingual(&self) -> bool {
+        self.n_vocab >= 51865
+    }
+
+    /// The size of the model, as named by OpenAI, given its number of audio layers.
+    pub fn model_type(&self) -> Option<&'static str> {
+        match self.n_audio_layer {
+            4 => Some("tiny"),
+            6 => Some("base"),
+            12 => Some("small"),
+            24 => Some("medium"),
+            32 => Some("large"),
+            _ => None,
+        }
+    }
+
+    /// The name of [`WhisperHparams::ftype`], such as `"q5_0"`.
+    pub fn ftype_name(&self) -> Option<&'static str> {
+        match self.ftype {
+            0 => Some("f32"),
+            1 => Some("f16"),
+            2 => Some("q4_0"),
+            3 => Some("q4_1"),
+            4 => Some("q4_1_some_f16"),
+            7 => Some("q8_0"),
+            8 => Some("q5_0"),
+            9 => Some("q5_1"),
+            10 => Some("q2_k"),
+            11 => Some("q3_k"),
+            12 => Some("q4_k"),
+            13 => Some("q5_k"),
+            14 => Some("q6_k"),
+            _ => None,
+        }
+    }
+}
+
+/// The mel filter bank turning the spectrum of the audio into the input of the model.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WhisperMelFilters {
+    /// Number of mel bands.
+    pub n_mel: usize,
+
+    /// Number of frequency bins of the spectrum.
+    pub n_fft: usize,
+
+    /// The weight of each frequency bin for each band, band after band.
+    pub data: Vec<f32>,
+}
+
+/// A tensor of a model file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WhisperTensorInfo {
+    pub name: String,
+
+    /// The number of elements along each dimension, innermost first, as in *ggml*.
+    pub shape: Vec<usize>,
+
+    pub ggml_type: GgmlType,
+
+    /// The position of the data of the tensor in the file.
+    pub offset: u64,
+}
+
+impl WhisperTensorInfo {
+    /// The number of elements of the tensor, saturating at [`usize::MAX`] for shapes too large to
+    /// be in a model file, which [`WhisperModelInfo::read`] rejects.
+    pub fn element_count(&self) -> usize {
+        self.checked_element_count().unwrap_or(usize::MAX)
+    }
+
+    /// The size of the data of the tensor, in bytes, saturating at [`u64::MAX`] as
+    /// [`WhisperTensorInfo::element_count`] does.
+    pub fn size(&self) -> u64 {
+        self.checked_size().unwrap_or(u64::MAX)
+    }
+
+    fn checked_element_count(&self) -> Option<usize> {
+        self.shape
+            .iter()
+            .try_fold(1usize, |count, &dim| count.checked_mul(dim))
+    }
+
+    fn checked_size(&self) -> Option<u64> {
+        let (block_size, block_bytes) = self.ggml_type.block();
+        let size = (self.checked_element_count()? / block_size).checked_mul(block_bytes)?;
+        u64::try_from(size).ok()
+    }
+}
+
+/// The type of the elements of a *ggml* tensor.
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum GgmlType {
+    F32,
+    F16,
+    Q4_0,
+    Q4_1,
+    Q5_0,
+    Q5_1,
+    Q8_0,
+    Q8_1,
+    Q2_K,
+    Q3_K,
+    Q4_K,
+    Q5_K,
+    Q6_K,
+    Q8_K,
+}
+
+impl GgmlType {
+    /// Returns the type with the given `ggml_type` value.
+    pub fn from_id(id: u32) -> Option<Self> {
+        Some(match id {
+            0 => Self::F32,
+            1 => Self::F16,
+            2 => Self::Q4_0,
+            3 => Self::Q4_1,
+            6 => Self::Q5_0,
+            7 => Self::Q5_1,
+            8 => Self::Q8_0,
+            9 => Self::Q8_1,
+            10 => Self::Q2_K,
+            11 => Self::Q3_K,
+            12 => Self::Q4_K,
+            13 => Self::Q5_K,
+            14 => Self::Q6_K,
+            15 => Self::Q8_K,
+            _ => return None,
+        })
+    }
+
+    /// The `ggml_type` value of the type.
+    pub fn id(self) -> u32 {
+        match self {
+            Self::F32 => 0,
+            Self::F16 => 1,
+            Self::Q4_0 => 2,
+            Self::Q4_1 => 3,
+            Self::Q5_0 => 6,
+            Self::Q5_1 => 7,
+            Self::Q8_0 => 8,
+            Self::Q8_1 => 9,
+            Self::Q2_K => 10,
+            Self::Q3_K => 11,
+            Self::Q4_K => 12,
+            Self::Q5_K => 13,
+            Self::Q6_K => 14,
+            Self::Q8_K => 15,
+        }
+    }
+
+    /// The name of the type, such as `"q5_0"`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::F32 => "f32",
+            Self::F16 => "f16",
+            Self::Q4_0 => "q4_0",
+            Self::Q4_1 => "q4_1",
+            Self::Q5_0 => "q5_0",
+            Self::Q5_1 => "q5_1",
+            Self::Q8_0 => "q8_0",
+            Self::Q8_1 => "q8_1",
+            Self::Q2_K => "q2_k",
+            Self::Q3_K => "q3_k",
+            Self::Q4_K => "q4_k",
+            Self::Q5_K => "q5_k",
+            Self::Q6_K => "q6_k",
+            Self::Q8_K => "q8_k",
+        }
+    }
+
+    /// The number of elements stored together, and the size of their block in bytes.
+    pub(crate) fn block(self) -> (usize, usize) {
+        match self {
+            Self::F32 => (1, 4),
+            Self::F16 => (1, 2),
+            Self::Q4_0 => (32, 18),
+            Self::Q4_1 => (32, 20),
+            Self::Q5_0 => (32, 22),
+            Self::Q5_1 => (32, 24),
+            Self::Q8_0 => (32, 34),
+            Self::Q8_1 => (32, 40),
+            Self::Q2_K => (256, 84),
+            Self::Q3_K => (256, 110),
+            Self::Q4_K => (256, 144),
+            Self::Q5_K => (256, 176),
+            Self::Q6_K => (256, 210),
+            Self::Q8_K => (256, 292),
+        }
+    }
+}
+
+/// The largest vocabulary, mel filter bank or token accepted, so that corrupted lengths fail
+/// instead of allocating huge buffers.
+const MAX_COUNT: usize = 1 << 24;
+
+/// The longest tensor name accepted.
+const MAX_NAME_LEN: usize = 1024;
+
+impl WhisperModelInfo {
+    /// Reads the model file at `path`, checking that it is complete, without reading the tensor
+    /// data.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, WhisperModelFileError> {
+        let path = path.as_ref();
+        let file = model_file::open(path)?;
+        Self::from_file(file, path)
+    }
+
+    pub(crate) fn from_file(file: File, path: &Path) -> Result<Self, WhisperModelFileError> {
+        let len = file
+            .metadata()
+            .map_err(|e| model_file::io_error(path, e))?
+            .len();
+        Self::parse(BufReader::new(file), len, path)
+    }
+
+    /// Parses the `len` bytes long model file read by `reader`, attributing errors to `path`.
+    pub(crate) fn parse<R>(reader: R, len: u64, path: &Path) -> Result<Self, WhisperModelFileError>
+    where
+        R: Read + Seek,
+    {
+        let mut reader = ModelReader {
+            inner: reader,
+            path,
+        };
+
+        let magic = reader.u32()?;
+        if magic != GGML_FILE_MAGIC {
+            return Err(WhisperModelFileError::InvalidMagic {
+                path: path.to_path_buf(),
+                magic,
+            });
+        }
+
+        let mut hparams = WhisperHparams {
+            n_vocab: reader.positive("n_vocab")?,
+            n_audio_ctx: reader.positive("n_audio_ctx")?,
+            n_audio_state: reader.positive("n_audio_state")?,
+            n_audio_head: reader.positive("n_audio_head")?,
+            n_audio_layer: reader.positive("n_audio_layer")?,
+            n_text_ctx: reader.positive("n_text_ctx")?,
+            n_text_state: reader.positive("n_text_state")?,
+            n_text_head: reader.positive("n_text_head")?,
+            n_text_layer: reader.positive("n_text_layer")?,
+            n_mels: reader.positive("n_mels")?,
+            ftype: reader.i32()?,
+        };
+        let quantization_version = hparams.ftype / GGML_QNT_VERSION_FACTOR;
+        hparams.ftype %= GGML_QNT_VERSION_FACTOR;
+
+        let n_mel = reader.count("mel filter bands")?;
+        let n_fft = reader.count("mel filter bins")?;
+        let data = reader.f32s(reader.checked(n_mel.checked_mul(n_fft), "mel filters")?)?;
+        let mel_filters = WhisperMelFilters { n_mel, n_fft, data };
+
+        let n_vocab = reader.count("vocabulary")?;
+        let vocab = (0..n_vocab)
+            .map(|_| {
+                let len = reader.count("token")?;
+                reader.bytes(len)
+            })
+            .collect::<Result<_, _>>()?;
+
+        let mut tensors = vec![];
+        while let Some(n_dims) = reader.tensor_start()? {
+            let name_len = reader.i32()?;
+            let type_id = reader.u32()?;
+
+            if !(1..=4).contains(&n_dims) {
+                return Err(reader.invalid(format!("tensor has {n_dims} dimensions")));
+            }
+            if !(1..=MAX_NAME_LEN as i32).contains(&name_len) {
+                return Err(reader.invalid(format!("tensor name is {name_len} bytes long")));
+            }
+
+            let shape = (0..n_dims)
+                .map(|_| reader.count("tensor dimension"))
+                .collect::<Result<Vec<_>, _>>()?;
+            let name = String::from_utf8_lossy(&reader.bytes(name_len as usize)?).into_owned();
+            let ggml_type = GgmlType::from_id(type_id).ok_or_else(|| {
+                reader.invalid(format!("tensor {name} has unknown type {type_id}"))
+            })?;
+
+            let (block_size, _) = ggml_type.block();
+            if !shape[0].is_multiple_of(block_size) {
+                return Err(reader.invalid(format!(
+                    "tensor {name} has {} columns, not a multiple of the {block_size} of {}",
+                    shape[0],
+                    ggml_type.name()
+                )));
+            }
+
+            let offset = reader.position()?;
+            let tensor = WhisperTensorInfo {
+                name,
+                shape,
+                ggml_type,
+                offset,
+            };
+            let end = tensor
+                .checked_size()
+                .and_then(|size| offset.checked_add(size))
+                .ok_or_else(|| reader.invalid(format!("tensor {} is too large", tensor.name)))?;
+            if end > len {
+                return Err(WhisperModelFileError::Truncated(path.to_path_buf()));
+            }
+
+            reader.seek(end)?;
+            tensors.push(tensor);
+        }
+
+        Ok(Self {
+            hparams,
+            quantization_version,
+            mel_filters,
+            vocab,
+            tensors,
+        })
+    }
+
+    /// The size of the data of every tensor, in bytes.
+    pub fn tensor_bytes(&self) -> u64 {
+        self.tensors.iter().map(WhisperTensorInfo::size).sum()
+    }
+}
+
+struct ModelReader<'a, R> {
+    inner: R,
+    path: &'a Path,
+}
+
+impl<R: Read + Seek> ModelReader<'_, R> {
+    fn read<const N: usize>(&mut self) -> Result<[u8; N], WhisperModelFileError> {
+        let mut bytes = [0; N];
+        self.inner
+            .read_exact(&mut bytes)
+            .map_err(|e| self.error(e))?;
+        Ok(bytes)
+    }
+
+    fn i32(&mut self) -> Result<i32, WhisperModelFileError> {
+        self.read().map(i32::from_le_bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, WhisperModelFileError> {
+        self.read().map(u32::from_le_bytes)
+    }
+
+    fn positive(&mut self, name: &str) -> Result<i32, WhisperModelFileError> {
+        match self.i32()? {
+            value if value > 0 => Ok(value),
+            value => Err(self.invalid(format!("{name} is {value}"))),
+        }
+    }
+
+    /// Reads a length, which must not be negative or larger than [`MAX_COUNT`].
+    fn count(&mut self, name: &str) -> Result<usize, WhisperModelFileError> {
+        let value = self.i32()?;
+        self.checked(usize::try_from(value).ok(), name)
+    }
+
+    fn checked(&self, count: Option<usize>, name: &str) -> Result<usize, WhisperModelFileError> {
+        match count {
+            Some(count) if count <= MAX_COUNT => Ok(count),
+            _ => Err(self.invalid(format!("invalid {name} length"))),
+        }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<Vec<u8>, WhisperModelFileError> {
+        let mut bytes = vec![0; len];
+        self.inner
+            .read_exact(&mut bytes)
+            .map_err(|e| self.error(e))?;
+        Ok(bytes)
+    }
+
+    fn f32s(&mut self, len: usize) -> Result<Vec<f32>, WhisperModelFileError> {
+        let bytes = self.bytes(len * 4)?;
+        Ok(bytes
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect())
+    }
+
+    /// Reads the number of dimensions of the next tensor, or [`None`] at the end of the file.
+    fn tensor_start(&mut self) -> Result<Option<i32>, WhisperModelFileError> {
+        let mut bytes = [0; 4];
+        let mut read = 0;
+        while read < bytes.len() {
+            match self.inner.read(&mut bytes[read..]) {
+                Ok(0) if read == 0 => return Ok(None),
+                Ok(0) => return Err(WhisperModelFileError::Truncated(self.path.to_path_buf())),
+                Ok(n) => read += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(self.error(e)),
+            }
+        }
+
+        Ok(Some(i32::from_le_bytes(bytes)))
+    }
+
+    fn position(&mut self) -> Result<u64, WhisperModelFileError> {
+        self.inner.stream_position().map_err(|e| self.error(e))
+    }
+
+    fn seek(&mut self, position: u64) -> Result<(), WhisperModelFileError> {
+        self.inner
+            .seek(SeekFrom::Start(position))
+            .map_err(|e| self.error(e))?;
+        Ok(())
+    }
+
+    fn error(&self, e: std::io::Error) -> WhisperModelFileError {
+        match e.kind() {
+            ErrorKind::UnexpectedEof => WhisperModelFileError::Truncated(self.path.to_path_buf()),
+            _ => model_file::io_error(self.path, e),
+        }
+    }
+
+    fn invalid(&self, reason: String) -> WhisperModelFileError {
+        WhisperModelFileError::Invalid {
+            path: self.path.to_path_buf(),
+            reason,
+        }
+    }
+}
@@ -0,0 +1,711 @@
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, PoisonError, RwLock};
+
+use regex::Regex;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// How transcripts are normalized before being compared, so that differences of case,
+/// punctuation or spelling do not count as errors.
+///
+/// These are ports of the normalizers OpenAI evaluates *whisper* with, in `whisper/normalizers`,
+/// with the differences listed for [`WhisperTextNormalizer::English`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum WhisperTextNormalizer {
+    /// Leaves the text as is, only collapsing its whitespace.
+    Verbatim,
+
+    /// OpenAI's `BasicTextNormalizer`, for any language: lowercases the text, removes what is
+    /// between brackets or parentheses, and replaces symbols, punctuation and marks with spaces.
+    /// With `remove_diacritics`, the diacritics are removed from letters instead of being
+    /// replaced.
+    Basic { remove_diacritics: bool },
+
+    /// OpenAI's `EnglishTextNormalizer`, which also removes filler words, expands contractions
+    /// and titles, writes spelled out numbers, percentages and amounts of money in digits, and
+    /// uses American spellings.
+    ///
+    /// Unlike OpenAI's number normalizer, signs ("minus"), plurals ("the sixties"), repeated
+    /// digits ("double five"), numbers read digit by digit and successive multipliers ("a
+    /// thousand million") are left spelled out.
+    ///
+    /// Only the most common British spellings are built in. For error rates comparable to
+    /// OpenAI's, load its `english.json` with [`set_english_spellings`].
+    #[default]
+    English,
+}
+
+impl WhisperTextNormalizer {
+    pub fn normalize(&self, text: &str) -> String {
+        let patterns = patterns();
+
+        let text = match *self {
+            Self::Verbatim => text.to_string(),
+            Self::Basic { remove_diacritics } => {
+                let text = text.to_lowercase();
+                let text = patterns.brackets.replace_all(&text, "");
+                let text = patterns.parentheses.replace_all(&text, "");
+
+                match remove_diacritics {
+                    true => remove_symbols_and_diacritics(&text, ""),
+                    false => remove_symbols(&text),
+                }
+                .to_lowercase()
+            }
+            Self::English => {
+                let text = text.to_lowercase();
+                let text = patterns.brackets.replace_all(&text, "");
+                let text = patterns.parentheses.replace_all(&text, "");
+                let text = patterns.fillers.replace_all(&text, "");
+                let mut text = patterns.apostrophes.replace_all(&text, "'").into_owned();
+
+                for (pattern, replacement) in &patterns.replacements {
+                    text = pattern.replace_all(&text, *replacement).into_owned();
+                }
+
+                let text = patterns.digit_commas.replace_all(&text, "${1}${2}");
+                let text = patterns.periods.replace_all(&text, " ${1}");
+                // Symbols of numbers are kept until the numbers are written in digits
+                let text = remove_symbols_and_diacritics(&text, ".%$¢€£");
+                let text = standardize_numbers(&text);
+                let text = standardize_spellings(&text);
+                let text = patterns.prefix_symbols.replace_all(&text, " ${1}");
+                patterns.percents.replace_all(&text, "${1} ").into_owned()
+            }
+        };
+
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+}
+
+struct Patterns {
+    /// Words between brackets, such as `[MUSIC]` or `<laughs>`.
+    brackets: Regex,
+
+    /// Words between parentheses, such as `(applause)`.
+    parentheses: Regex,
+
+    fillers: Regex,
+
+    /// Whitespace before an apostrophe.
+    apostrophes: Regex,
+
+    /// Contractions and abbreviations, with their expansion.
+    replacements: Vec<(Regex, &'static str)>,
+
+    /// Commas between digits.
+    digit_commas: Regex,
+
+    /// Periods not followed by a digit.
+    periods: Regex,
+
+    /// Symbols not followed by a digit.
+    prefix_symbols: Regex,
+
+    /// Percent signs not preceded by a digit.
+    percents: Regex,
+}
+
+fn patterns() -> &'static Patterns {
+    static PATTERNS: OnceLock<Patterns> = OnceLock::new();
+
+    PATTERNS.get_or_init(|| {
+        let regex = |pattern: &str| Regex::new(pattern).expect("the pattern is valid");
+
+        Patterns {
+            brackets: regex(r"[<\[][^>\]]*[>\]]"),
+            parentheses: regex(r"\(([^)]+?)\)"),
+            fillers: regex(r"\b(hmm|mm|mhm|mmm|uh|um)\b"),
+            apostrophes: regex(r"\s+'"),
+            replacements: REPLACEMENTS
+                .iter()
+                .map(|&(pattern, replacement)| (regex(pattern), replacement))
+                .collect(),
+            digit_commas: regex(r"(\d),(\d)"),
+            periods: regex(r"\.([^0-9]|$)"),
+            prefix_symbols: regex(r"[.$¢€£]([^0-9])"),
+            percents: regex(r"([^0-9])%"),
+        }
+    })
+}
+
+/// Replaced in order, so the specific contractions come before the general ones.
+const REPLACEMENTS: &[(&str, &str)] = &[
+    // Common contractions
+    (r"\bwon't\b", "will not"),
+    (r"\bcan't\b", "can not"),
+    (r"\blet's\b", "let us"),
+    (r"\bain't\b", "aint"),
+    (r"\by'all\b", "you all"),
+    (r"\bwanna\b", "want to"),
+    (r"\bgotta\b", "got to"),
+    (r"\bgonna\b", "going to"),
+    (r"\bi'ma\b", "i am going to"),
+    (r"\bimma\b", "i am going to"),
+    (r"\bwoulda\b", "would have"),
+    (r"\bcoulda\b", "could have"),
+    (r"\bshoulda\b", "should have"),
+    (r"\bma'am\b", "madam"),
+    // Titles and prefixes
+    (r"\bmr\b", "mister "),
+    (r"\bmrs\b", "missus "),
+    (r"\bst\b", "saint "),
+    (r"\bdr\b", "doctor "),
+    (r"\bprof\b", "professor "),
+    (r"\bcapt\b", "captain "),
+    (r"\bgov\b", "governor "),
+    (r"\bald\b", "alderman "),
+    (r"\bgen\b", "general "),
+    (r"\bsen\b", "senator "),
+    (r"\brep\b", "representative "),
+    (r"\bpres\b", "president "),
+    (r"\brev\b", "reverend "),
+    (r"\bhon\b", "honorable "),
+    (r"\basst\b", "assistant "),
+    (r"\bassoc\b", "associate "),
+    (r"\blt\b", "lieutenant "),
+    (r"\bcol\b", "colonel "),
+    (r"\bjr\b", "junior "),
+    (r"\bsr\b", "senior "),
+    (r"\besq\b", "esquire "),
+    // Perfect tenses
+    (r"'d been\b", " had been"),
+    (r"'s been\b", " has been"),
+    (r"'d gone\b", " had gone"),
+    (r"'s gone\b", " has gone"),
+    (r"'d done\b", " had done"),
+    (r"'s got\b", " has got"),
+    // General contractions
+    (r"n't\b", " not"),
+    (r"'re\b", " are"),
+    (r"'s\b", " is"),
+    (r"'d\b", " would"),
+    (r"'ll\b", " will"),
+    (r"'t\b", " not"),
+    (r"'ve\b", " have"),
+    (r"'m\b", " am"),
+];
+
+/// Letters which do not decompose into a letter and diacritics, with their replacement.
+fn additional_diacritic(c: char) -> Option<&'static str> {
+    Some(match c {
+        'œ' => "oe",
+        'Œ' => "OE",
+        'ø' => "o",
+        'Ø' => "O",
+        'æ' => "ae",
+        'Æ' => "AE",
+        'ß' => "ss",
+        'ẞ' => "SS",
+        'đ' | 'ð' => "d",
+        'Đ' | 'Ð' => "D",
+        'þ' | 'Þ' => "th",
+        'ł' => "l",
+        'Ł' => "L",
+        _ => return None,
+    })
+}
+
+fn is_symbol_or_punctuation(c: char) -> bool {
+    !(c.is_alphanumeric() || c.is_whitespace() || c.is_control())
+}
+
+/// Replaces the marks, symbols and punctuation of `text` with spaces.
+fn remove_symbols(text: &str) -> String {
+    text.nfkc()
+        .map(
+            |c| match is_combining_mark(c) || is_symbol_or_punctuation(c) {
+                true => ' ',
+                false => c,
+            },
+        )
+        .collect()
+}
+
+/// Removes the diacritics of `text`, and replaces its symbols and punctuation with spaces, except
+/// for the characters of `keep`.
+fn remove_symbols_and_diacritics(text: &str, keep: &str) -> String {
+    let mut removed = String::with_capacity(text.len());
+
+    for c in text.nfkd() {
+        if keep.contains(c) {
+            removed.push(c);
+        } else if let Some(replacement) = additional_diacritic(c) {
+            removed.push_str(replacement);
+        } else if is_combining_mark(c) {
+            continue;
+        } else if is_symbol_or_punctuation(c) {
+            removed.push(' ');
+        } else {
+            removed.push(c);
+        }
+    }
+
+    removed
+}
+
+/// British spellings, sorted, with their American spelling.
+const SPELLINGS: &[(&str, &str)] = &[
+    ("aeroplane", "airplane"),
+    ("aeroplanes", "airplanes"),
+    ("aluminium", "aluminum"),
+    ("analyse", "analyze"),
+    ("analysed", "analyzed"),
+    ("analysing", "analyzing"),
+    ("apologise", "apologize"),
+    ("apologised", "apologized"),
+    ("armour", "armor"),
+    ("behaviour", "behavior"),
+    ("behaviours", "behaviors"),
+    ("cancelled", "canceled"),
+    ("cancelling", "canceling"),
+    ("catalogue", "catalog"),
+    ("centre", "center"),
+    ("centres", "centers"),
+    ("cheque", "check"),
+    ("civilisation", "civilization"),
+    ("colour", "color"),
+    ("coloured", "colored"),
+    ("colours", "colors"),
+    ("counselling", "counseling"),
+    ("criticise", "criticize"),
+    ("criticised", "criticized"),
+    ("defence", "defense"),
+    ("dialogue", "dialog"),
+    ("emphasise", "emphasize"),
+    ("endeavour", "endeavor"),
+    ("favour", "favor"),
+    ("favourite", "favorite"),
+    ("favourites", "favorites"),
+    ("flavour", "flavor"),
+    ("fulfil", "fulfill"),
+    ("grey", "gray"),
+    ("harbour", "harbor"),
+    ("honour", "honor"),
+    ("honoured", "honored"),
+    ("humour", "humor"),
+    ("jewellery", "jewelry"),
+    ("kilometre", "kilometer"),
+    ("kilometres", "kilometers"),
+    ("labour", "labor"),
+    ("licence", "license"),
+    ("litre", "liter"),
+    ("litres", "liters"),
+    ("manoeuvre", "maneuver"),
+    ("metre", "meter"),
+    ("metres", "meters"),
+    ("mould", "mold"),
+    ("neighbour", "neighbor"),
+    ("neighbourhood", "neighborhood"),
+    ("neighbours", "neighbors"),
+    ("offence", "offense"),
+    ("organisation", "organization"),
+    ("organisations", "organizations"),
+    ("organise", "organize"),
+    ("organised", "organized"),
+    ("practise", "practice"),
+    ("programme", "program"),
+    ("programmes", "programs"),
+    ("realise", "realize"),
+    ("realised", "realized"),
+    ("realising", "realizing"),
+    ("recognise", "recognize"),
+    ("recognised", "recognized"),
+    ("rumour", "rumor"),
+    ("savour", "savor"),
+    ("sceptical", "skeptical"),
+    ("summarise", "summarize"),
+    ("theatre", "theater"),
+    ("theatres", "theaters"),
+    ("travelled", "traveled"),
+    ("traveller", "traveler"),
+    ("travellers", "travelers"),
+    ("travelling", "traveling"),
+    ("tyre", "tire"),
+    ("tyres", "tires"),
+    ("vapour", "vapor"),
+];
+
+/// The spellings set with [`set_english_spellings`], replacing [`SPELLINGS`].
+static ENGLISH_SPELLINGS: RwLock<Option<Arc<HashMap<String, String>>>> = RwLock::new(None);
+
+/// Replaces the British spellings [`WhisperTextNormalizer::English`] writes in American English
+/// with the ones of `json`, an object mapping each British spelling to its American one, such as
+/// OpenAI's `whisper/normalizers/english.json`.
+///
+/// This applies to every normalizer of the process, including those already in use.
+pub fn set_english_spellings(json: &str) -> Result<(), serde_json::Error> {
+    let spellings: HashMap<String, String> = serde_json::from_str(json)?;
+    let mut current = ENGLISH_SPELLINGS
+        .write()
+        .unwrap_or_else(PoisonError::into_inner);
+    *current = Some(Arc::new(spellings));
+
+    Ok(())
+}
+
+fn standardize_spellings(text: &str) -> String {
+    let spellings = ENGLISH_SPELLINGS
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+
+    text.split_whitespace()
+        .map(|word| match &spellings {
+            Some(spellings) => spellings.get(word).map_or(word, String::as_str),
+            None => match SPELLINGS.binary_search_by_key(&word, |&(british, _)| british) {
+                Ok(index) => SPELLINGS[index].1,
+                Err(_) => word,
+            },
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+const ONES: [&str; 10] = [
+    "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine",
+];
+const ONES_ORDINAL: [&str; 10] = [
+    "zeroth", "first", "second", "third", "fourth", "fifth", "sixth", "seventh", "eighth", "ninth",
+];
+const TEENS: [&str; 10] = [
+    "ten",
+    "eleven",
+    "twelve",
+    "thirteen",
+    "fourteen",
+    "fifteen",
+    "sixteen",
+    "seventeen",
+    "eighteen",
+    "nineteen",
+];
+const TEENS_ORDINAL: [&str; 10] = [
+    "tenth",
+    "eleventh",
+    "twelfth",
+    "thirteenth",
+    "fourteenth",
+    "fifteenth",
+    "sixteenth",
+    "seventeenth",
+    "eighteenth",
+    "nineteenth",
+];
+const TENS: [&str; 8] = [
+    "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety",
+];
+const TENS_ORDINAL: [&str; 8] = [
+    "twentieth",
+    "thirtieth",
+    "fortieth",
+    "fiftieth",
+    "sixtieth",
+    "seventieth",
+    "eightieth",
+    "ninetieth",
+];
+const MULTIPLIERS: [(&str, &str, u64); 4] = [
+    ("thousand", "thousandth", 1_000),
+    ("million", "millionth", 1_000_000),
+    ("billion", "billionth", 1_000_000_000),
+    ("trillion", "trillionth", 1_000_000_000_000),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum NumberWord {
+    Ones(u64),
+    Teen(u64),
+    Tens(u64),
+    Hundred,
+    Multiplier(u64),
+}
+
+impl NumberWord {
+    /// Parses a spelled out number, returning whether it is ordinal.
+    fn parse(word: &str) -> Option<(Self, bool)> {
+        let find = |words: &[&str]| words.iter().position(|&w| w == word).map(|i| i as u64);
+
+        if let Some(n) = find(&ONES) {
+            Some((Self::Ones(n), false))
+        } else if let Some(n) = find(&ONES_ORDINAL) {
+            Some((Self::Ones(n), true))
+        } else if let Some(n) = find(&TEENS) {
+            Some((Self::Teen(10 + n), false))
+        } else if let Some(n) = find(&TEENS_ORDINAL) {
+            Some((Self::Teen(10 + n), true))
+        } else if let Some(n) = find(&TENS) {
+            Some((Self::Tens(20 + 10 * n), false))
+        } else if let Some(n) = find(&TENS_ORDINAL) {
+            Some((Self::Tens(20 + 10 * n), true))
+        } else if word == "hundred" || word == "hundredth" {
+            Some((Self::Hundred, word == "hundredth"))
+        } else {
+            MULTIPLIERS
+                .iter()
+                .find(|(cardinal, ordinal, _)| word == *cardinal || word == *ordinal)
+                .map(|&(_, ordinal, value)| (Self::Multiplier(value), word == ordinal))
+        }
+    }
+}
+
+/// The last part of a number being parsed.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Last {
+    Start,
+    Digits,
+    Ones,
+    Teen,
+    Tens,
+    TensOnes,
+    Hundred,
+    Multiplier,
+}
+
+/// The value of a decimal number followed by a multiplier, such as "3.5 million", if it is a
+/// whole number.
+fn scaled_decimal(number: &str, multiplier: Option<&str>) -> Option<u64> {
+    let Some((NumberWord::Multiplier(m), false)) = multiplier.and_then(NumberWord::parse) else {
+        return None;
+    };
+    let (whole, fraction) = number.split_once('.')?;
+    if whole.is_empty()
+        || ![whole, fraction]
+            .iter()
+            .all(|part| part.bytes().all(|b| b.is_ascii_digit()))
+    {
+        return None;
+    }
+
+    let scale = 10u64.checked_pow(fraction.len() as u32)?;
+    if !m.is_multiple_of(scale) {
+        return None;
+    }
+
+    let fraction = match fraction {
+        "" => 0,
+        fraction => fraction.parse::<u64>().ok()?,
+    };
+    whole
+        .parse::<u64>()
+        .ok()?
+        .checked_mul(m)?
+        .checked_add(fraction * (m / scale))
+}
+
+/// Writes the spelled out numbers of `text` in digits.
+fn standardize_numbers(text: &str) -> String {
+    let words: Vec<_> = text.split_whitespace().collect();
+    let mut standardized = Vec::with_capacity(words.len());
+    let mut index = 0;
+
+    while index < words.len() {
+        match parse_number(&words[index..]) {
+            Some((number, used)) => {
+                standardized.push(number);
+                index += used;
+            }
+            None => {
+                standardized.push(words[index].to_string());
+                index += 1;
+            }
+        }
+    }
+
+    standardized.join(" ")
+}
+
+/// Parses the number `words` start with, along with its unit, returning it in digits and the
+/// number of words it spans.
+fn parse_number(words: &[&str]) -> Option<(String, usize)> {
+    let is_number_word = |index: usize, accepted: fn(NumberWord) -> bool| {
+        words
+            .get(index)
+            .and_then(|word| NumberWord::parse(word))
+            .is_some_and(|(word, _)| accepted(word))
+    };
+    let is_scale = |word| matches!(word, NumberWord::Hundred | NumberWord::Multiplier(_));
+    let is_below_hundred = |word| {
+        matches!(
+            word,
+            NumberWord::Ones(_) | NumberWord::Teen(_) | NumberWord::Tens(_)
+        )
+    };
+
+    let (mut total, mut current) = (0u64, 0u64);
+    // The first two digits of a year spelled out in two parts, such as "nineteen ninety"
+    let mut century = None;
+    let mut last = Last::Start;
+    let mut used = 0;
+    let mut ordinal = false;
+
+    let first = *words.first()?;
+    if first == "a" && is_number_word(1, is_scale) {
+        (current, last, used) = (1, Last::Ones, 1);
+    } else if first.bytes().all(|b| b.is_ascii_digit()) {
+        (current, last, used) = (first.parse().ok()?, Last::Digits, 1);
+    } else if let Some(value) = scaled_decimal(first, words.get(1).copied()) {
+        (total, last, used) = (value, Last::Multiplier, 2);
+    }
+
+    while let Some(&word) = words.get(used) {
+        if word == "and"
+            && matches!(last, Last::Hundred | Last::Multiplier)
+            && is_number_word(used + 1, is_below_hundred)
+        {
+            used += 1;
+            continue;
+        }
+
+        let Some((number, is_ordinal)) = NumberWord::parse(word) else {
+            break;
+        };
+        // On its own, "second" is rather the unit of time
+        if word == "second" && last == Last::Start {
+            break;
+        }
+
+        let two_digits = century.is_none()
+            && total == 0
+            && matches!(last, Last::Teen | Last::Tens | Last::TensOnes);
+
+        last = match (number, last) {
+            (NumberWord::Ones(n), Last::Start | Last::Hundred | Last::Multiplier) => {
+                current += n;
+                Last::Ones
+            }
+            (NumberWord::Ones(n), Last::Tens) if n > 0 => {
+                current += n;
+                Last::TensOnes
+            }
+            (NumberWord::Teen(n), Last::Start | Last::Hundred | Last::Multiplier) => {
+                current += n;
+                Last::Teen
+            }
+            (NumberWord::Tens(n), Last::Start | Last::Hundred | Last::Multiplier) => {
+                current += n;
+                Last::Tens
+            }
+            (NumberWord::Teen(n) | NumberWord::Tens(n), _) if two_digits => {
+                century = Some(current);
+                current = n;
+                match number {
+                    NumberWord::Teen(_) => Last::Teen,
+                    _ => Last::Tens,
+                }
+            }
+            (NumberWord::Hundred, Last::Start) => {
+                current = 100;
+                Last::Hundred
+            }
+            (NumberWord::Hundred, Last::Hundred | Last::Multiplier) => break,
+            (NumberWord::Hundred, _) if current < 100 && century.is_none() => {
+                current *= 100;
+                Last::Hundred
+            }
+            (NumberWord::Multiplier(m), _) if last != Last::Multiplier && century.is_none() => {
+                let Some(sum) = current
+                    .max(1)
+                    .checked_mul(m)
+                    .and_then(|value| total.checked_add(value))
+                else {
+                    break;
+                };
+                (total, current) = (sum, 0);
+                Last::Multiplier
+            }
+            _ => break,
+        };
+
+        used += 1;
+        if is_ordinal {
+            ordinal = true;
+            break;
+        }
+    }
+
+    if last == Last::Start {
+        return None;
+    }
+
+    let value = match century {
+        Some(century) => century * 100 + current,
+        None => total + current,
+    };
+    let mut number = value.to_string();
+
+    if ordinal {
+        number.push_str(match value % 100 {
+            11..=13 => "th",
+            _ => match value % 10 {
+                1 => "st",
+                2 => "nd",
+                3 => "rd",
+                _ => "th",
+            },
+        });
+
+        return Some((number, used));
+    }
+
+    if words.get(used) == Some(&"point")
+        && is_number_word(used + 1, |word| matches!(word, NumberWord::Ones(_)))
+    {
+        number.push('.');
+        used += 1;
+
+        while let Some((NumberWord::Ones(digit), false)) =
+            words.get(used).and_then(|word| NumberWord::parse(word))
+        {
+            number.push_str(&digit.to_string());
+            used += 1;
+        }
+    }
+
+    match words.get(used).copied() {
+        Some("percent") => {
+            number.push('%');
+            used += 1;
+        }
+        Some("dollars" | "dollar") => {
+            used += 1;
+
+            // "and twenty cents"
+            if words.get(used) == Some(&"and") {
+                let cents = parse_number(&words[used + 1..]).and_then(|(cents, n)| {
+                    let cents = cents.strip_suffix('¢')?;
+                    (cents.len() <= 2 && cents.bytes().all(|b| b.is_ascii_digit()))
+                        .then(|| (cents.to_string(), n))
+                });
+
+                if let Some((cents, n)) = cents {
+                    number = format!("{number}.{cents:0>2}");
+                    used += 1 + n;
+                }
+            }
+
+            number.insert(0, '$');
+        }
+        Some("cents" | "cent") => {
+            number.push('¢');
+            used += 1;
+        }
+        Some("pounds" | "pound") => {
+            number.insert(0, '£');
+            used += 1;
+        }
+        Some("euros" | "euro") => {
+            number.insert(0, '€');
+            used += 1;
+        }
+        // Numbers already in digits are left as they are
+        _ if last == Last::Digits && used == 1 => return None,
+        _ => {}
+    }
+
+    Some((number, used))
+}
@@ -0,0 +1,58 @@
+use std::fmt::Write;
+
+use crate::WhisperSegment;
+
+/// Formats a time in centiseconds as `HH:MM:SS<separator>mmm`, as used by subtitle formats.
+pub fn format_timestamp(centiseconds: i64, separator: char) -> String {
+    let millis = centiseconds.max(0) * 10;
+    let (hours, millis) = (millis / 3_600_000, millis % 3_600_000);
+    let (minutes, millis) = (millis / 60_000, millis % 60_000);
+    let (seconds, millis) = (millis / 1000, millis % 1000);
+
+    format!("{hours:02}:{minutes:02}:{seconds:02}{separator}{millis:03}")
+}
+
+/// Formats segments as plain text, one segment per line.
+pub fn format_text(segments: &[WhisperSegment]) -> String {
+    segments.iter().fold(String::new(), |mut out, segment| {
+        out.push_str(segment.text.trim());
+        out.push('\n');
+        out
+    })
+}
+
+/// Formats segments as SubRip (`.srt`) subtitles, one cue per segment.
+pub fn format_srt(segments: &[WhisperSegment]) -> String {
+    let mut out = String::new();
+
+    for (i, segment) in segments.iter().enumerate() {
+        // Writing to a `String` cannot fail
+        let _ = write!(
+            out,
+            "{}\n{} --> {}\n{}\n\n",
+            i + 1,
+            format_timestamp(segment.start, ','),
+            format_timestamp(segment.end, ','),
+            segment.text.trim(),
+        );
+    }
+
+    out
+}
+
+/// Formats segments as WebVTT (`.vtt`) subtitles, one cue per segment.
+pub fn format_vtt(segments: &[WhisperSegment]) -> String {
+    let mut out = "WEBVTT\n\n".to_string();
+
+    for segment in segments {
+        let _ = write!(
+            out,
+            "{} --> {}\n{}\n\n",
+            format_timestamp(segment.start, '.'),
+            format_timestamp(segment.end, '.'),
+            segment.text.trim(),
+        );
+    }
+
+    out
+}
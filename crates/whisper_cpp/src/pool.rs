@@ -0,0 +1,166 @@
+use std::num::NonZeroUsize;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::{WhisperError, WhisperModel, WhisperSession, WhisperSessionError};
+
+/// A fixed size pool of [`WhisperSession`]s sharing the same [`WhisperModel`].
+///
+/// Every session holds its own `whisper_state`, which can take hundreds of megabytes for larger
+/// models. The pool allocates all of them upfront and never grows, so when every session is in use
+/// callers wait in [`SessionPool::checkout`] (or get [`None`] from [`SessionPool::try_checkout`])
+/// instead of allocating more memory.
+pub struct SessionPool {
+    inner: Arc<PoolInner>,
+}
+
+struct PoolInner {
+    /// Sessions not currently checked out.
+    idle: Mutex<Vec<WhisperSession>>,
+
+    /// Holds one permit for each idle session.
+    permits: Arc<Semaphore>,
+
+    /// The total amount of sessions owned by the pool.
+    size: usize,
+}
+
+impl SessionPool {
+    /// Creates a new [`SessionPool`], allocating `size` sessions of the provided [`WhisperModel`].
+    pub async fn new(model: &WhisperModel, size: NonZeroUsize) -> Result<Self, WhisperError> {
+        let size = size.get();
+        let mut idle = Vec::with_capacity(size);
+
+        for _ in 0..size {
+            idle.push(model.new_session().await?);
+        }
+
+        Ok(Self {
+            inner: Arc::new(PoolInner {
+                idle: Mutex::new(idle),
+                permits: Arc::new(Semaphore::new(size)),
+                size,
+            }),
+        })
+    }
+
+    /// Takes a session from the pool, waiting for one to be returned if all of them are in use.
+    ///
+    /// Waiters are served in the order they called this function.
+    pub async fn checkout(&self) -> PooledSession {
+        let permit = self
+            .inner
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("the pool semaphore is never closed");
+
+        self.take(permit)
+    }
+
+    /// Takes a session from the pool, returning [`None`] if all of them are in use.
+    pub fn try_checkout(&self) -> Option<PooledSession> {
+        let permit = self.inner.permits.clone().try_acquire_owned().ok()?;
+
+        Some(self.take(permit))
+    }
+
+    /// Moves the idle sessions of the pool to the current model after it was reloaded, freeing
+    /// their states of the previous model, see [`WhisperSession::refresh`].
+    ///
+    /// The sessions checked out meanwhile move on their next run, as any session does.
+    pub async fn refresh(&self) -> Result<(), WhisperSessionError> {
+        let mut sessions = vec![];
+        while let Some(session) = self.try_checkout() {
+            sessions.push(session);
+        }
+
+        for session in &mut sessions {
+            session.refresh().await?;
+        }
+
+        Ok(())
+    }
+
+    /// The total amount of sessions in the pool, including those checked out.
+    pub fn size(&self) -> usize {
+        self.inner.size
+    }
+
+    /// The amount of sessions that can currently be checked out without waiting.
+    pub fn available(&self) -> usize {
+        self.inner.permits.available_permits()
+    }
+
+    fn take(&self, permit: OwnedSemaphorePermit) -> PooledSession {
+        let session = self
+            .inner
+            .idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop()
+            .expect("there is an idle session for each available permit");
+
+        PooledSession {
+            session: Some(session),
+            pool: self.inner.clone(),
+            _permit: permit,
+        }
+    }
+}
+
+impl Clone for SessionPool {
+    /// Makes a clone of the [`SessionPool`].
+    ///
+    /// The clone hands out sessions from the same pool, it does **NOT** allocate new sessions.
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+/// A [`WhisperSession`] checked out from a [`SessionPool`].
+///
+/// The session is reset and returned to the pool when this is dropped.
+pub struct PooledSession {
+    session: Option<WhisperSession>,
+    pool: Arc<PoolInner>,
+
+    /// Released after the session is back in the pool, see [`PooledSession::drop`].
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Deref for PooledSession {
+    type Target = WhisperSession;
+
+    fn deref(&self) -> &Self::Target {
+        self.session
+            .as_ref()
+            .expect("the session is only taken when dropped")
+    }
+}
+
+impl DerefMut for PooledSession {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.session
+            .as_mut()
+            .expect("the session is only taken when dropped")
+    }
+}
+
+impl Drop for PooledSession {
+    fn drop(&mut self) {
+        if let Some(mut session) = self.session.take() {
+            session.reset();
+            self.pool
+                .idle
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(session);
+        }
+    }
+}
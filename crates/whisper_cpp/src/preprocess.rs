@@ -0,0 +1,386 @@
+use std::f64::consts::PI;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::mel::SAMPLE_RATE;
+
+/// Samples at or above this magnitude count as clipped, when part of a run.
+const CLIP_LEVEL: f32 = 0.999;
+
+/// The number of consecutive samples at the clip level from which they are considered clipped, as
+/// single full scale samples also occur in clean recordings.
+const MIN_CLIP_RUN: usize = 3;
+
+/// Length of the blocks the loudness is measured over, in samples: 400 ms.
+const LOUDNESS_BLOCK: usize = SAMPLE_RATE * 2 / 5;
+
+/// Step between loudness blocks, in samples: 100 ms, for blocks overlapping by 75%.
+const LOUDNESS_STEP: usize = SAMPLE_RATE / 10;
+
+/// Blocks quieter than this, in LUFS, are left out of the integrated loudness.
+const ABSOLUTE_GATE: f64 = -70.0;
+
+/// Blocks this many LU quieter than the loudness of the blocks above the absolute gate are left
+/// out of the integrated loudness.
+const RELATIVE_GATE: f64 = -10.0;
+
+/// A step of a [`WhisperPreprocessor`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", rename_all = "snake_case"))]
+pub enum WhisperPreprocessStep {
+    /// Subtracts the mean of the samples, removing their DC offset.
+    RemoveDc,
+
+    /// Attenuates the frequencies below `cutoff_hz` with a second order Butterworth filter, such
+    /// as rumble and handling noise.
+    HighPass { cutoff_hz: f32 },
+
+    /// Removes a narrow band around `frequency_hz`, such as the 50 or 60 Hz mains hum. A higher
+    /// `q` makes the band narrower.
+    Notch { frequency_hz: f32, q: f32 },
+
+    /// Scales the samples so that their peak is at `target_dbfs`.
+    PeakNormalize { target_dbfs: f32 },
+
+    /// Scales the samples so that their integrated loudness, as defined by ITU-R BS.1770 and used
+    /// by EBU R128, is `target_lufs`, lowering the gain if needed to keep their peak at or below
+    /// `max_peak_dbfs`.
+    LoudnessNormalize {
+        target_lufs: f32,
+        max_peak_dbfs: f32,
+    },
+}
+
+#[derive(Debug, Error)]
+pub enum WhisperPreprocessError {
+    #[error("{frequency_hz} Hz is not between 0 Hz and the Nyquist frequency, 8000 Hz")]
+    Frequency { frequency_hz: f32 },
+    #[error("the quality factor of a filter must be positive, not {q}")]
+    Quality { q: f32 },
+}
+
+/// A chain of [`WhisperPreprocessStep`]s run over 16 kHz mono audio before it is transcribed,
+/// in place on the buffers given to [`WhisperSession::advance`](crate::WhisperSession::advance).
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct WhisperPreprocessor {
+    /// The steps, run in order.
+    pub steps: Vec<WhisperPreprocessStep>,
+}
+
+impl Default for WhisperPreprocessor {
+    /// Removes the DC offset and everything below 80 Hz, then normalizes the loudness to the
+    /// -23 LUFS of EBU R128, with peaks at -1 dBFS at most.
+    fn default() -> Self {
+        Self {
+            steps: vec![
+                WhisperPreprocessStep::RemoveDc,
+                WhisperPreprocessStep::HighPass { cutoff_hz: 80.0 },
+                WhisperPreprocessStep::LoudnessNormalize {
+                    target_lufs: -23.0,
+                    max_peak_dbfs: -1.0,
+                },
+            ],
+        }
+    }
+}
+
+impl WhisperPreprocessor {
+    pub fn new(steps: Vec<WhisperPreprocessStep>) -> Self {
+        Self { steps }
+    }
+
+    /// Runs every step over `samples`, returning what the audio was like before and after.
+    ///
+    /// The normalization steps leave silent audio, and audio too short or too quiet to measure
+    /// its loudness, as is. Fails without changing `samples` if a filter has invalid parameters.
+    pub fn apply(
+        &self,
+        samples: &mut [f32],
+    ) -> Result<WhisperPreprocessReport, WhisperPreprocessError> {
+        let filters = self
+            .steps
+            .iter()
+            .map(|step| match *step {
+                WhisperPreprocessStep::HighPass { cutoff_hz } => {
+                    Biquad::high_pass(cutoff_hz).map(Some)
+                }
+                WhisperPreprocessStep::Notch { frequency_hz, q } => {
+                    Biquad::notch(frequency_hz, q).map(Some)
+                }
+                _ => Ok(None),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let input = WhisperAudioStats::measure(samples);
+        let mut gain_db = 0.0;
+
+        for (step, filter) in self.steps.iter().zip(filters) {
+            if let Some(filter) = filter {
+                filter.run(samples);
+                continue;
+            }
+
+            let gain = match *step {
+                WhisperPreprocessStep::RemoveDc => {
+                    let mean = mean(samples) as f32;
+                    samples.iter_mut().for_each(|sample| *sample -= mean);
+                    continue;
+                }
+                WhisperPreprocessStep::PeakNormalize { target_dbfs } => {
+                    let peak = peak_dbfs(samples);
+                    peak.is_finite().then_some(target_dbfs - peak)
+                }
+                WhisperPreprocessStep::LoudnessNormalize {
+                    target_lufs,
+                    max_peak_dbfs,
+                } => loudness_lufs(samples).map(|loudness| {
+                    (target_lufs - loudness).min(max_peak_dbfs - peak_dbfs(samples))
+                }),
+                WhisperPreprocessStep::HighPass { .. } | WhisperPreprocessStep::Notch { .. } => {
+                    unreachable!("filters are run above")
+                }
+            };
+
+            if let Some(gain) = gain {
+                let factor = 10f32.powf(gain / 20.0);
+                samples.iter_mut().for_each(|sample| *sample *= factor);
+                gain_db += gain;
+            }
+        }
+
+        Ok(WhisperPreprocessReport {
+            input,
+            output: WhisperAudioStats::measure(samples),
+            gain_db,
+        })
+    }
+}
+
+/// What [`WhisperPreprocessor::apply`] did to the audio.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WhisperPreprocessReport {
+    /// The audio before preprocessing, which is where clipping is looked for.
+    pub input: WhisperAudioStats,
+
+    /// The audio after preprocessing.
+    pub output: WhisperAudioStats,
+
+    /// The gain applied by the normalization steps, in dB.
+    pub gain_db: f32,
+}
+
+/// Levels of some audio, in decibels relative to full scale, that is to samples of magnitude 1.
+/// Silent audio is at negative infinity.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WhisperAudioStats {
+    /// The level of the loudest sample.
+    pub peak_dbfs: f32,
+
+    /// The level of the root mean square of the samples.
+    pub rms_dbfs: f32,
+
+    /// The integrated loudness, as defined by ITU-R BS.1770, or [`None`] if the audio is shorter
+    /// than 400 ms or quieter than -70 LUFS.
+    pub loudness_lufs: Option<f32>,
+
+    /// The mean of the samples.
+    pub dc_offset: f32,
+
+    /// The number of samples in runs of at least 3 consecutive samples at full scale, which is
+    /// where the recording clipped.
+    pub clipped_samples: usize,
+}
+
+impl WhisperAudioStats {
+    /// Measures `samples`, 16 kHz mono audio.
+    pub fn measure(samples: &[f32]) -> Self {
+        let rms = mean_square(samples).sqrt();
+
+        Self {
+            peak_dbfs: peak_dbfs(samples),
+            rms_dbfs: (20.0 * rms.log10()) as f32,
+            loudness_lufs: loudness_lufs(samples),
+            dc_offset: mean(samples) as f32,
+            clipped_samples: clipped_samples(samples),
+        }
+    }
+
+    /// Whether the recording clipped, which no preprocessing undoes.
+    pub fn is_clipped(&self) -> bool {
+        self.clipped_samples > 0
+    }
+}
+
+fn mean(samples: &[f32]) -> f64 {
+    samples.iter().map(|&sample| sample as f64).sum::<f64>() / samples.len().max(1) as f64
+}
+
+fn mean_square(samples: &[f32]) -> f64 {
+    samples
+        .iter()
+        .map(|&sample| sample as f64 * sample as f64)
+        .sum::<f64>()
+        / samples.len().max(1) as f64
+}
+
+fn peak_dbfs(samples: &[f32]) -> f32 {
+    let peak = samples
+        .iter()
+        .fold(0f32, |peak, sample| peak.max(sample.abs()));
+    20.0 * peak.log10()
+}
+
+fn clipped_samples(samples: &[f32]) -> usize {
+    let mut clipped = 0;
+    let mut run = 0;
+
+    for (index, sample) in samples.iter().enumerate() {
+        let same_side = index > 0 && sample.signum() == samples[index - 1].signum();
+        run = match sample.abs() >= CLIP_LEVEL {
+            true if same_side => run + 1,
+            true => 1,
+            false => 0,
+        };
+
+        // The first samples of a run are counted once it is long enough
+        match run {
+            MIN_CLIP_RUN => clipped += MIN_CLIP_RUN,
+            run if run > MIN_CLIP_RUN => clipped += 1,
+            _ => {}
+        }
+    }
+
+    clipped
+}
+
+/// The gated integrated loudness of ITU-R BS.1770, over 400 ms blocks of the K-weighted samples.
+fn loudness_lufs(samples: &[f32]) -> Option<f32> {
+    if samples.len() < LOUDNESS_BLOCK {
+        return None;
+    }
+
+    let mut weighted = samples.to_vec();
+    for filter in Biquad::k_weighting() {
+        filter.run(&mut weighted);
+    }
+
+    let loudness = |mean_square: f64| -0.691 + 10.0 * mean_square.log10();
+    let blocks: Vec<f64> = (0..=(weighted.len() - LOUDNESS_BLOCK) / LOUDNESS_STEP)
+        .map(|block| mean_square(&weighted[block * LOUDNESS_STEP..][..LOUDNESS_BLOCK]))
+        .filter(|&block| loudness(block) > ABSOLUTE_GATE)
+        .collect();
+
+    if blocks.is_empty() {
+        return None;
+    }
+
+    let gate = loudness(blocks.iter().sum::<f64>() / blocks.len() as f64) + RELATIVE_GATE;
+    let gated: Vec<f64> = blocks
+        .into_iter()
+        .filter(|&block| loudness(block) > gate)
+        .collect();
+
+    Some(loudness(gated.iter().sum::<f64>() / gated.len() as f64) as f32)
+}
+
+/// A second order IIR filter, with the coefficients normalized by `a0`.
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+}
+
+impl Biquad {
+    /// The high-pass filter of the Audio EQ Cookbook, Butterworth with a Q of 1/√2.
+    fn high_pass(cutoff_hz: f32) -> Result<Self, WhisperPreprocessError> {
+        let (cos, alpha) = Self::angle(cutoff_hz, std::f32::consts::FRAC_1_SQRT_2)?;
+
+        Ok(Self::normalized(
+            [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        ))
+    }
+
+    /// The notch filter of the Audio EQ Cookbook.
+    fn notch(frequency_hz: f32, q: f32) -> Result<Self, WhisperPreprocessError> {
+        let (cos, alpha) = Self::angle(frequency_hz, q)?;
+
+        Ok(Self::normalized(
+            [1.0, -2.0 * cos, 1.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        ))
+    }
+
+    /// The high shelf and high-pass filters of ITU-R BS.1770, computed for 16 kHz.
+    fn k_weighting() -> [Self; 2] {
+        let rate = SAMPLE_RATE as f64;
+
+        let (frequency, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+        let k = (PI * frequency / rate).tan();
+        let vh = 10f64.powf(gain / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let shelf = Self::normalized(
+            [
+                vh + vb * k / q + k * k,
+                2.0 * (k * k - vh),
+                vh - vb * k / q + k * k,
+            ],
+            [
+                1.0 + k / q + k * k,
+                2.0 * (k * k - 1.0),
+                1.0 - k / q + k * k,
+            ],
+        );
+
+        let (frequency, q) = (38.13547087602444, 0.5003270373238773);
+        let k = (PI * frequency / rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let high_pass = Self {
+            b: [1.0, -2.0, 1.0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        };
+
+        [shelf, high_pass]
+    }
+
+    /// The cosine of the angular frequency of `frequency_hz`, and the alpha of the Audio EQ
+    /// Cookbook for the quality factor `q`.
+    fn angle(frequency_hz: f32, q: f32) -> Result<(f64, f64), WhisperPreprocessError> {
+        let nyquist = SAMPLE_RATE as f32 / 2.0;
+        if !(frequency_hz > 0.0 && frequency_hz < nyquist) {
+            return Err(WhisperPreprocessError::Frequency { frequency_hz });
+        }
+        if !(q > 0.0 && q.is_finite()) {
+            return Err(WhisperPreprocessError::Quality { q });
+        }
+
+        let omega = 2.0 * PI * frequency_hz as f64 / SAMPLE_RATE as f64;
+        Ok((omega.cos(), omega.sin() / (2.0 * q as f64)))
+    }
+
+    fn normalized(b: [f64; 3], [a0, a1, a2]: [f64; 3]) -> Self {
+        Self {
+            b: b.map(|b| b / a0),
+            a: [a1 / a0, a2 / a0],
+        }
+    }
+
+    /// Filters `samples` in place, starting from silence, in transposed direct form II.
+    fn run(&self, samples: &mut [f32]) {
+        let (mut z1, mut z2) = (0.0, 0.0);
+
+        for sample in samples {
+            let input = *sample as f64;
+            let output = self.b[0] * input + z1;
+            z1 = self.b[1] * input - self.a[0] * output + z2;
+            z2 = self.b[2] * input - self.a[1] * output;
+            *sample = output as f32;
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use std::str::FromStr;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{WhisperGrammarElement, WhisperParams, WhisperSampling};
+
+/// Named sets of [`WhisperParams`] for common use cases.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum WhisperPreset {
+    /// *whisper.cpp*'s defaults, using greedy sampling.
+    #[default]
+    Default,
+
+    /// Greedy sampling of a single candidate, without temperature fallback.
+    FastGreedy,
+
+    /// Beam search with 5 beams and temperature fallback, as in OpenAI's implementation.
+    AccurateBeamSearch,
+
+    /// A single segment per run, without carrying over past text, for short streamed chunks.
+    Streaming,
+
+    /// Segments of at most 42 characters split on word boundaries, to be used as subtitle cues.
+    Subtitles,
+}
+
+impl WhisperPreset {
+    /// The name of this preset, as written in configuration files and parsed by [`FromStr`].
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Default => "default",
+            Self::FastGreedy => "fast-greedy",
+            Self::AccurateBeamSearch => "accurate-beam-search",
+            Self::Streaming => "streaming",
+            Self::Subtitles => "subtitles",
+        }
+    }
+
+    /// Returns the [`WhisperParams`] of this preset.
+    pub fn params(self) -> WhisperParams {
+        match self {
+            Self::Default => WhisperParams::new(WhisperSampling::default_greedy()),
+            Self::FastGreedy => {
+                let mut params = WhisperParams::new(WhisperSampling::Greedy { best_of: 1 });
+                params.temperature_inc = 0.0;
+                params
+            }
+            Self::AccurateBeamSearch => WhisperParams::new(WhisperSampling::BeamSearch {
+                beam_size: 5,
+                patience: -1.0,
+            }),
+            Self::Streaming => {
+                let mut params = WhisperParams::new(WhisperSampling::default_greedy());
+                params.single_segment = true;
+                params.no_context = true;
+                params.print_progress = false;
+                params
+            }
+            Self::Subtitles => {
+                let mut params = WhisperParams::new(WhisperSampling::default_greedy());
+                params.token_timestamps = true;
+                params.max_len = 42;
+                params.split_on_word = true;
+                params
+            }
+        }
+    }
+}
+
+/// The error returned when parsing the name of an unknown [`WhisperPreset`].
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+#[error(
+    "unknown preset `{0}`, expected default, fast-greedy, accurate-beam-search, streaming or \
+     subtitles"
+)]
+pub struct WhisperPresetParseError(pub String);
+
+impl FromStr for WhisperPreset {
+    type Err = WhisperPresetParseError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        [
+            Self::Default,
+            Self::FastGreedy,
+            Self::AccurateBeamSearch,
+            Self::Streaming,
+            Self::Subtitles,
+        ]
+        .into_iter()
+        .find(|preset| preset.name() == name)
+        .ok_or_else(|| WhisperPresetParseError(name.to_string()))
+    }
+}
+
+impl From<WhisperPreset> for WhisperParams {
+    fn from(value: WhisperPreset) -> Self {
+        value.params()
+    }
+}
+
+/// Declares [`WhisperParamsOverrides`], with an optional field for each field of [`WhisperParams`].
+macro_rules! overrides {
+    ($($field:ident: $ty:ty,)*) => {
+        /// Values replacing the fields of a [`WhisperParams`], fields set to [`None`] are left as is.
+        ///
+        /// See [`WhisperParams`] for the meaning of each field.
+        #[derive(Clone, Debug, Default, PartialEq)]
+        #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub struct WhisperParamsOverrides {
+            $(
+                #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+                pub $field: Option<$ty>,
+            )*
+        }
+
+        impl WhisperParamsOverrides {
+            /// Replaces the fields of `params` set in these overrides.
+            pub fn apply(&self, params: &mut WhisperParams) {
+                $(
+                    if let Some(value) = &self.$field {
+                        params.$field = value.clone();
+                    }
+                )*
+            }
+        }
+    };
+}
+
+overrides! {
+    strategy: WhisperSampling,
+    thread_count: u32,
+    max_text_ctx: u32,
+    offset_ms: u32,
+    duration_ms: u32,
+    translate: bool,
+    no_context: bool,
+    no_timestamps: bool,
+    single_segment: bool,
+    print_special: bool,
+    print_progress: bool,
+    print_realtime: bool,
+    print_timestamps: bool,
+    token_timestamps: bool,
+    thold_pt: f32,
+    thold_ptsum: f32,
+    max_len: u32,
+    split_on_word: bool,
+    max_tokens: u32,
+    speed_up: bool,
+    debug_mode: bool,
+    audio_ctx: u32,
+    tdrz_enable: bool,
+    initial_prompt: String,
+    prompt_tokens: Vec<i32>,
+    language: String,
+    detect_language: bool,
+    suppress_blank: bool,
+    suppress_non_speech_tokens: bool,
+    temperature: f32,
+    max_initial_ts: f32,
+    length_penalty: f32,
+    temperature_inc: f32,
+    entropy_thold: f32,
+    logprob_thold: f32,
+    no_speech_thold: f32,
+    grammar_rules: Vec<Vec<WhisperGrammarElement>>,
+    i_start_rule: usize,
+    grammar_penalty: f32,
+}
+
+/// A [`WhisperPreset`] with some of its fields overridden, as stored in configuration files.
+///
+/// Serialized, the overrides sit next to the preset name:
+/// ```toml
+/// preset = "accurate-beam-search"
+/// language = "de"
+/// thread_count = 4
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WhisperParamsConfig {
+    /// The preset providing the value of every field not overridden.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub preset: WhisperPreset,
+
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub overrides: WhisperParamsOverrides,
+}
+
+impl WhisperParamsConfig {
+    /// Returns the [`WhisperParams`] of the preset, with the overrides applied.
+    pub fn params(&self) -> WhisperParams {
+        let mut params = self.preset.params();
+        self.overrides.apply(&mut params);
+        params
+    }
+}
@@ -0,0 +1,415 @@
+use std::ffi::{c_int, c_void};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use whisper_cpp_sys::{
+    ggml_quantize_q4_0, ggml_quantize_q4_1, ggml_quantize_q5_0, ggml_quantize_q5_1,
+    ggml_quantize_q8_0,
+};
+
+use crate::model_file::GGML_FILE_MAGIC;
+use crate::{GgmlType, WhisperModelFileError, WhisperModelInfo, WhisperTensorInfo};
+
+/// The version of *ggml*'s quantization formats, stored in the `ftype` of quantized models.
+const GGML_QNT_VERSION: i32 = 2;
+
+/// See [`GGML_QNT_VERSION`].
+const GGML_QNT_VERSION_FACTOR: i32 = 1000;
+
+/// The tensors left as they are, as in the `quantize` example of [whisper.cpp].
+///
+/// [whisper.cpp]: https://github.com/ggerganov/whisper.cpp/
+const SKIPPED_TENSORS: [&str; 4] = [
+    "encoder.conv1.bias",
+    "encoder.conv2.bias",
+    "encoder.positional_embedding",
+    "decoder.positional_embedding",
+];
+
+/// The *ggml* quantization formats a model can be converted to.
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum WhisperQuantType {
+    Q4_0,
+    Q4_1,
+    Q5_0,
+    Q5_1,
+    Q8_0,
+}
+
+type QuantizeFn = unsafe extern "C" fn(*const f32, *mut c_void, c_int, c_int, *mut i64) -> usize;
+
+impl WhisperQuantType {
+    /// The type of the quantized tensors.
+    pub fn ggml_type(self) -> GgmlType {
+        match self {
+            Self::Q4_0 => GgmlType::Q4_0,
+            Self::Q4_1 => GgmlType::Q4_1,
+            Self::Q5_0 => GgmlType::Q5_0,
+            Self::Q5_1 => GgmlType::Q5_1,
+            Self::Q8_0 => GgmlType::Q8_0,
+        }
+    }
+
+    /// The `ggml_ftype` value of models quantized to this type.
+    fn ftype(self) -> i32 {
+        match self {
+            Self::Q4_0 => 2,
+            Self::Q4_1 => 3,
+            Self::Q8_0 => 7,
+            Self::Q5_0 => 8,
+            Self::Q5_1 => 9,
+        }
+    }
+
+    fn quantize_fn(self) -> QuantizeFn {
+        match self {
+            Self::Q4_0 => ggml_quantize_q4_0,
+            Self::Q4_1 => ggml_quantize_q4_1,
+            Self::Q5_0 => ggml_quantize_q5_0,
+            Self::Q5_1 => ggml_quantize_q5_1,
+            Self::Q8_0 => ggml_quantize_q8_0,
+        }
+    }
+}
+
+/// Reported after writing each tensor, see [`quantize_with_progress`].
+#[derive(Clone, Copy, Debug)]
+pub struct WhisperQuantizeProgress<'a> {
+    /// The index of the tensor, out of [`WhisperQuantizeProgress::count`].
+    pub index: usize,
+    pub count: usize,
+
+    /// The tensor, as found in the input.
+    pub tensor: &'a WhisperTensorInfo,
+
+    /// The type the tensor was written as, which is its input type if it was not quantized.
+    pub ggml_type: GgmlType,
+
+    /// The size of the written tensor data, in bytes.
+    pub size: u64,
+}
+
+/// The sizes of the tensor data of a quantized model.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WhisperQuantizeStats {
+    /// The size of the tensor data of the input, in bytes.
+    pub input_size: u64,
+
+    /// The size of the tensor data of the output, in bytes.
+    pub output_size: u64,
+
+    /// Number of tensors quantized.
+    pub quantized: usize,
+
+    /// Number of tensors written as they were.
+    pub copied: usize,
+
+    /// How many quantized values fell in each of 16 buckets, as reported by *ggml*.
+    pub histogram: [i64; 16],
+}
+
+impl WhisperQuantizeStats {
+    /// The size of the output tensor data relative to the input.
+    pub fn ratio(&self) -> f64 {
+        self.output_size as f64 / self.input_size as f64
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum WhisperQuantizeError {
+    #[error("invalid input model: {0}")]
+    ModelFile(#[from] WhisperModelFileError),
+    #[error("tensor {tensor} is {}, only f32 and f16 tensors can be quantized", .ggml_type.name())]
+    Unsupported { tensor: String, ggml_type: GgmlType },
+    #[error("failed to read {path}: {source}")]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("failed to write {path}: {source}")]
+    Write {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+/// Writes the *ggml* *whisper* model at `input` to `output`, its matrices quantized to
+/// `quant_type`, as the `quantize` example of [whisper.cpp] does.
+///
+/// Biases and positional embeddings are written as they are. An input with tensors other than
+/// f32 and f16 ones, such as an already quantized model, is rejected before anything is written.
+/// The model is written next to `output` with a `.part` extension appended, then moved
+/// in place once complete, so `output` is never left incomplete and can be the input itself.
+///
+/// [whisper.cpp]: https://github.com/ggerganov/whisper.cpp/
+pub fn quantize<P, Q>(
+    input: P,
+    output: Q,
+    quant_type: WhisperQuantType,
+) -> Result<WhisperQuantizeStats, WhisperQuantizeError>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    quantize_with_progress(input, output, quant_type, |_| {})
+}
+
+/// [`quantize`], calling `progress` after writing each tensor.
+#[doc(alias = "ggml_quantize_chunk")]
+pub fn quantize_with_progress<P, Q, F>(
+    input: P,
+    output: Q,
+    quant_type: WhisperQuantType,
+    mut progress: F,
+) -> Result<WhisperQuantizeStats, WhisperQuantizeError>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    F: FnMut(WhisperQuantizeProgress<'_>),
+{
+    let input = input.as_ref();
+    let output = output.as_ref();
+
+    let info = WhisperModelInfo::read(input)?;
+
+    // Checked before writing anything, so that a quantized input fails without leaving a partial
+    // output behind
+    if let Some(tensor) = info
+        .tensors
+        .iter()
+        .find(|tensor| !matches!(tensor.ggml_type, GgmlType::F32 | GgmlType::F16))
+    {
+        return Err(WhisperQuantizeError::Unsupported {
+            tensor: tensor.name.clone(),
+            ggml_type: tensor.ggml_type,
+        });
+    }
+
+    let mut part = output.as_os_str().to_owned();
+    part.push(".part");
+    let part = PathBuf::from(part);
+
+    match write_quantized(&info, input, &part, quant_type, &mut progress) {
+        Ok(stats) => {
+            std::fs::rename(&part, output).map_err(|source| WhisperQuantizeError::Write {
+                path: output.to_path_buf(),
+                source,
+            })?;
+            Ok(stats)
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&part);
+            Err(e)
+        }
+    }
+}
+
+/// Writes the model of `input`, described by `info`, to `output` with its matrices quantized.
+fn write_quantized<F>(
+    info: &WhisperModelInfo,
+    input: &Path,
+    output: &Path,
+    quant_type: WhisperQuantType,
+    progress: &mut F,
+) -> Result<WhisperQuantizeStats, WhisperQuantizeError>
+where
+    F: FnMut(WhisperQuantizeProgress<'_>),
+{
+    let read_error = |source| WhisperQuantizeError::Read {
+        path: input.to_path_buf(),
+        source,
+    };
+    let write_error = |source| WhisperQuantizeError::Write {
+        path: output.to_path_buf(),
+        source,
+    };
+
+    let mut reader = BufReader::new(File::open(input).map_err(read_error)?);
+    let mut writer = ModelWriter(BufWriter::new(File::create(output).map_err(write_error)?));
+
+    let mut hparams = info.hparams;
+    hparams.ftype = GGML_QNT_VERSION * GGML_QNT_VERSION_FACTOR + quant_type.ftype();
+    writer.header(info, &hparams).map_err(write_error)?;
+
+    let mut stats = WhisperQuantizeStats::default();
+    let count = info.tensors.len();
+
+    for (index, tensor) in info.tensors.iter().enumerate() {
+        let mut data = vec![0; tensor.size() as usize];
+        reader
+            .seek(SeekFrom::Start(tensor.offset))
+            .and_then(|_| reader.read_exact(&mut data))
+            .map_err(read_error)?;
+
+        let ggml_type = if quantizes(tensor, quant_type) {
+            let values = to_f32(tensor, &data);
+            data = quantize_values(&values, tensor.shape[0], quant_type, &mut stats.histogram);
+            stats.quantized += 1;
+            quant_type.ggml_type()
+        } else {
+            stats.copied += 1;
+            tensor.ggml_type
+        };
+
+        writer
+            .tensor(tensor, ggml_type, &data)
+            .map_err(write_error)?;
+
+        stats.input_size += tensor.size();
+        stats.output_size += data.len() as u64;
+
+        progress(WhisperQuantizeProgress {
+            index,
+            count,
+            tensor,
+            ggml_type,
+            size: data.len() as u64,
+        });
+    }
+
+    writer.0.flush().map_err(write_error)?;
+
+    Ok(stats)
+}
+
+/// Whether `tensor` is quantized: every f32 or f16 matrix, except the ones in [`SKIPPED_TENSORS`]
+/// and the ones whose rows cannot be split in blocks of `quant_type`.
+pub(crate) fn quantizes(tensor: &WhisperTensorInfo, quant_type: WhisperQuantType) -> bool {
+    let (block_size, _) = quant_type.ggml_type().block();
+
+    matches!(tensor.ggml_type, GgmlType::F32 | GgmlType::F16)
+        && tensor.shape.len() == 2
+        && tensor.shape[0].is_multiple_of(block_size)
+        && !SKIPPED_TENSORS.contains(&tensor.name.as_str())
+}
+
+/// The values of an f32 or f16 `tensor`.
+fn to_f32(tensor: &WhisperTensorInfo, data: &[u8]) -> Vec<f32> {
+    match tensor.ggml_type {
+        GgmlType::F32 => data
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect(),
+        GgmlType::F16 => data
+            .chunks_exact(2)
+            .map(|bytes| f16_to_f32(u16::from_le_bytes([bytes[0], bytes[1]])))
+            .collect(),
+        ggml_type => unreachable!("{} tensors are not quantized", ggml_type.name()),
+    }
+}
+
+/// Quantizes `values`, made of rows of `row_len` values.
+fn quantize_values(
+    values: &[f32],
+    row_len: usize,
+    quant_type: WhisperQuantType,
+    histogram: &mut [i64; 16],
+) -> Vec<u8> {
+    let (block_size, block_bytes) = quant_type.ggml_type().block();
+    let mut data = vec![0u8; values.len() / block_size * block_bytes];
+
+    // SAFETY: `data` has room for every block of `values`, whose length is a multiple of the
+    // length of a row, itself a multiple of the block size
+    let size = unsafe {
+        quant_type.quantize_fn()(
+            values.as_ptr(),
+            data.as_mut_ptr().cast(),
+            values.len() as c_int,
+            row_len as c_int,
+            histogram.as_mut_ptr(),
+        )
+    };
+    data.truncate(size);
+
+    data
+}
+
+/// Converts a IEEE 754 half-precision float, as stored by *ggml*, to a single-precision one.
+pub(crate) fn f16_to_f32(bits: u16) -> f32 {
+    let sign = (bits as u32 & 0x8000) << 16;
+    let exponent = (bits as u32 >> 10) & 0x1f;
+    let mantissa = bits as u32 & 0x3ff;
+
+    let magnitude = match exponent {
+        // Subnormal numbers are normal in single precision
+        0 => (mantissa as f32 / (1 << 24) as f32).to_bits(),
+        0x1f => 0x7f80_0000 | (mantissa << 13),
+        _ => ((exponent + 127 - 15) << 23) | (mantissa << 13),
+    };
+
+    f32::from_bits(sign | magnitude)
+}
+
+/// Writes the parts of a model file, in the order they are read by
+/// [`WhisperModelInfo::read`].
+pub(crate) struct ModelWriter<W>(pub(crate) W);
+
+impl<W: Write> ModelWriter<W> {
+    fn i32(&mut self, value: i32) -> std::io::Result<()> {
+        self.0.write_all(&value.to_le_bytes())
+    }
+
+    pub(crate) fn header(
+        &mut self,
+        info: &WhisperModelInfo,
+        hparams: &crate::WhisperHparams,
+    ) -> std::io::Result<()> {
+        self.0.write_all(&GGML_FILE_MAGIC.to_le_bytes())?;
+
+        for value in [
+            hparams.n_vocab,
+            hparams.n_audio_ctx,
+            hparams.n_audio_state,
+            hparams.n_audio_head,
+            hparams.n_audio_layer,
+            hparams.n_text_ctx,
+            hparams.n_text_state,
+            hparams.n_text_head,
+            hparams.n_text_layer,
+            hparams.n_mels,
+            hparams.ftype,
+        ] {
+            self.i32(value)?;
+        }
+
+        let filters = &info.mel_filters;
+        self.i32(filters.n_mel as i32)?;
+        self.i32(filters.n_fft as i32)?;
+        for value in &filters.data {
+            self.0.write_all(&value.to_le_bytes())?;
+        }
+
+        self.i32(info.vocab.len() as i32)?;
+        for token in &info.vocab {
+            self.i32(token.len() as i32)?;
+            self.0.write_all(token)?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn tensor(
+        &mut self,
+        tensor: &WhisperTensorInfo,
+        ggml_type: GgmlType,
+        data: &[u8],
+    ) -> std::io::Result<()> {
+        self.i32(tensor.shape.len() as i32)?;
+        self.i32(tensor.name.len() as i32)?;
+        self.i32(ggml_type.id() as i32)?;
+        for &dim in &tensor.shape {
+            self.i32(dim as i32)?;
+        }
+        self.0.write_all(tensor.name.as_bytes())?;
+        self.0.write_all(data)
+    }
+}
@@ -0,0 +1,431 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+
+use crate::{WhisperError, WhisperModel};
+
+/// The models a [`ModelRegistry`] knows about, by name.
+///
+/// Manifests are usually stored as JSON:
+///
+/// ```json
+/// {
+///     "models": {
+///         "base.en": {
+///             "file": "ggml-base.en.bin",
+///             "sha256": "a03779c86df3323075f5e796cb2ce5029f00ec8869eee3fdfb897afe36c6d002"
+///         }
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelManifest {
+    pub models: BTreeMap<String, ModelManifestEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelManifestEntry {
+    /// The name of the file in the cache directory, which is also its path relative to the base
+    /// URL of the registry.
+    pub file: String,
+
+    /// The expected SHA-256 of the file, in hexadecimal.
+    pub sha256: String,
+
+    /// The expected size of the file in bytes, checked before hashing it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+}
+
+#[derive(Debug, Error)]
+pub enum ModelRegistryError {
+    #[error("model {0:?} is not in the manifest")]
+    UnknownModel(String),
+    #[error("invalid manifest entry for {name:?}: {reason}")]
+    InvalidEntry { name: String, reason: &'static str },
+    #[error("failed to read the manifest {}: {reason}", path.display())]
+    Manifest { path: PathBuf, reason: String },
+    #[error("{} is missing and the registry has no base URL", path.display())]
+    Missing { path: PathBuf },
+    #[error("{} is {actual} bytes long, expected {expected}", path.display())]
+    Size {
+        path: PathBuf,
+        expected: u64,
+        actual: u64,
+    },
+    #[error("{} has SHA-256 {actual}, expected {expected}", path.display())]
+    Checksum {
+        path: PathBuf,
+        expected: String,
+        actual: String,
+    },
+    #[error("failed to access {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("failed to download {url}: {source}")]
+    Download {
+        url: String,
+        #[source]
+        source: reqwest::Error,
+    },
+    #[error("failed to load the model: {0}")]
+    Model(#[from] WhisperError),
+}
+
+/// Maps model names to files in a cache directory, checking them against the SHA-256 of a
+/// [`ModelManifest`] before loading them.
+///
+/// With a base URL, missing or corrupted files are downloaded from
+/// `{base_url}/{entry.file}`. Downloads are written next to their destination with a `.part`
+/// extension and only moved in place once their checksum matches, so *whisper.cpp* never sees a
+/// partial file. Concurrent fetches of a file wait for a single download.
+pub struct ModelRegistry {
+    dir: PathBuf,
+    manifest: ModelManifest,
+    base_url: Option<String>,
+    client: reqwest::Client,
+
+    /// Names of the models whose file was verified by this registry, which are not hashed again.
+    verified: Mutex<HashSet<String>>,
+
+    /// A lock per file, held while downloading it so that a single download writes its `.part`
+    /// file.
+    downloads: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl ModelRegistry {
+    /// Creates a new [`ModelRegistry`] keeping the files of `manifest` in `dir`.
+    ///
+    /// Entries whose checksum is not 64 hexadecimal digits, or whose file is not a plain file
+    /// name, are rejected.
+    pub fn new<P>(dir: P, manifest: ModelManifest) -> Result<Self, ModelRegistryError>
+    where
+        P: Into<PathBuf>,
+    {
+        for (name, entry) in &manifest.models {
+            validate_entry(name, entry)?;
+        }
+
+        Ok(Self {
+            dir: dir.into(),
+            manifest,
+            base_url: None,
+            client: reqwest::Client::new(),
+            verified: Mutex::new(HashSet::new()),
+            downloads: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// [`ModelRegistry::new`], reading the manifest from a JSON file.
+    pub fn from_manifest_file<P, Q>(dir: P, manifest_path: Q) -> Result<Self, ModelRegistryError>
+    where
+        P: Into<PathBuf>,
+        Q: AsRef<Path>,
+    {
+        let path = manifest_path.as_ref();
+        let manifest_error = |reason: String| ModelRegistryError::Manifest {
+            path: path.to_path_buf(),
+            reason,
+        };
+
+        let json = std::fs::read_to_string(path).map_err(|e| manifest_error(e.to_string()))?;
+        let manifest = serde_json::from_str(&json).map_err(|e| manifest_error(e.to_string()))?;
+
+        Self::new(dir, manifest)
+    }
+
+    /// Downloads missing or corrupted models from `base_url`.
+    pub fn with_base_url<S>(mut self, base_url: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.base_url = Some(base_url.into().trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Uses `client` for downloads, to set timeouts, proxies or headers.
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    pub fn manifest(&self) -> &ModelManifest {
+        &self.manifest
+    }
+
+    /// The path of the file of the model `name`, which may not exist yet.
+    pub fn path(&self, name: &str) -> Result<PathBuf, ModelRegistryError> {
+        Ok(self.dir.join(&self.entry(name)?.file))
+    }
+
+    /// Checks the file of the model `name` against the manifest, returning its path.
+    ///
+    /// Hashing runs on the blocking thread pool of *tokio*, as it can take seconds for larger
+    /// models.
+    pub async fn verify(&self, name: &str) -> Result<PathBuf, ModelRegistryError> {
+        let entry = self.entry(name)?.clone();
+        let path = self.dir.join(&entry.file);
+
+        if self.is_verified(name) {
+            return Ok(path);
+        }
+
+        let checked = path.clone();
+        tokio::task::spawn_blocking(move || verify_file(&checked, &entry))
+            .await
+            .expect("hashing does not panic")?;
+        self.set_verified(name, true);
+
+        Ok(path)
+    }
+
+    /// Returns the path of the verified file of the model `name`, downloading it if it is missing
+    /// or corrupted and the registry has a base URL.
+    pub async fn fetch(&self, name: &str) -> Result<PathBuf, ModelRegistryError> {
+        let error = match self.verify(name).await {
+            Ok(path) => return Ok(path),
+            Err(e) => e,
+        };
+
+        let corrupted = match &error {
+            ModelRegistryError::Io { source, .. } if source.kind() == ErrorKind::NotFound => false,
+            ModelRegistryError::Size { .. } | ModelRegistryError::Checksum { .. } => true,
+            _ => return Err(error),
+        };
+        let Some(base_url) = &self.base_url else {
+            return Err(if corrupted {
+                error
+            } else {
+                ModelRegistryError::Missing {
+                    path: self.path(name)?,
+                }
+            });
+        };
+
+        let entry = self.entry(name)?;
+        let lock = self.download_lock(&entry.file);
+        let _download = lock.lock().await;
+
+        // Another fetch may have downloaded the file while this one waited for the lock
+        if let Ok(path) = self.verify(name).await {
+            return Ok(path);
+        }
+
+        if corrupted {
+            tracing::warn!("{error}, downloading it again");
+        }
+        self.download(&format!("{base_url}/{}", entry.file), entry)
+            .await?;
+        self.set_verified(name, true);
+
+        Ok(self.dir.join(&entry.file))
+    }
+
+    /// Fetches the model `name` and loads it, see [`ModelRegistry::fetch`] and
+    /// [`WhisperModel::new_from_file`].
+    pub async fn load(
+        &self,
+        name: &str,
+        device: Option<u32>,
+    ) -> Result<WhisperModel, ModelRegistryError> {
+        let path = self.fetch(name).await?;
+
+        tokio::task::spawn_blocking(move || WhisperModel::new_from_file(path, device))
+            .await
+            .expect("loading a model does not panic")
+            .map_err(Into::into)
+    }
+
+    /// Forgets that the file of the model `name` was verified, so that it is hashed again the next
+    /// time it is needed, for instance after it was replaced on disk.
+    pub fn invalidate(&self, name: &str) {
+        self.set_verified(name, false);
+    }
+
+    fn entry(&self, name: &str) -> Result<&ModelManifestEntry, ModelRegistryError> {
+        self.manifest
+            .models
+            .get(name)
+            .ok_or_else(|| ModelRegistryError::UnknownModel(name.to_string()))
+    }
+
+    fn is_verified(&self, name: &str) -> bool {
+        let verified = self.verified.lock().unwrap_or_else(PoisonError::into_inner);
+        verified.contains(name)
+    }
+
+    fn set_verified(&self, name: &str, is_verified: bool) {
+        let mut verified = self.verified.lock().unwrap_or_else(PoisonError::into_inner);
+        if is_verified {
+            verified.insert(name.to_string());
+        } else {
+            verified.remove(name);
+        }
+    }
+
+    /// The lock held while downloading `file`.
+    fn download_lock(&self, file: &str) -> Arc<tokio::sync::Mutex<()>> {
+        let mut downloads = self
+            .downloads
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        downloads.entry(file.to_string()).or_default().clone()
+    }
+
+    /// Downloads `url` to the `.part` file of `entry`, moving it in place once verified.
+    async fn download(
+        &self,
+        url: &str,
+        entry: &ModelManifestEntry,
+    ) -> Result<(), ModelRegistryError> {
+        let path = self.dir.join(&entry.file);
+        let part = self.dir.join(format!("{}.part", entry.file));
+        let io_error = |path: &Path| {
+            let path = path.to_path_buf();
+            move |source| ModelRegistryError::Io { path, source }
+        };
+        let download_error = |source| ModelRegistryError::Download {
+            url: url.to_string(),
+            source,
+        };
+
+        tracing::info!("downloading {url} to {}", path.display());
+
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .map_err(io_error(&self.dir))?;
+
+        let mut response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(download_error)?;
+
+        let mut file = tokio::fs::File::create(&part)
+            .await
+            .map_err(io_error(&part))?;
+        let mut hasher = Sha256::new();
+        let mut size = 0;
+
+        let result = async {
+            while let Some(chunk) = response.chunk().await.map_err(download_error)? {
+                size += chunk.len() as u64;
+                // Stops responses longer than expected before they fill the disk
+                if entry.size.is_some_and(|expected| size > expected) {
+                    check_size(&part, entry, size)?;
+                }
+                hasher.update(&chunk);
+                file.write_all(&chunk).await.map_err(io_error(&part))?;
+            }
+            file.flush().await.map_err(io_error(&part))?;
+            drop(file);
+
+            check(&part, entry, size, hasher)?;
+            tokio::fs::rename(&part, &path)
+                .await
+                .map_err(io_error(&path))
+        }
+        .await;
+
+        if result.is_err() {
+            let _ = tokio::fs::remove_file(&part).await;
+        }
+
+        result
+    }
+}
+
+fn validate_entry(name: &str, entry: &ModelManifestEntry) -> Result<(), ModelRegistryError> {
+    let invalid = |reason| ModelRegistryError::InvalidEntry {
+        name: name.to_string(),
+        reason,
+    };
+
+    if entry.sha256.len() != 64 || !entry.sha256.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(invalid("the checksum must be 64 hexadecimal digits"));
+    }
+
+    let mut components = Path::new(&entry.file).components();
+    if !matches!(
+        (components.next(), components.next()),
+        (Some(std::path::Component::Normal(_)), None)
+    ) {
+        return Err(invalid("the file must be a file name, without directories"));
+    }
+
+    Ok(())
+}
+
+/// Hashes the file at `path`, checking it against `entry`.
+fn verify_file(path: &Path, entry: &ModelManifestEntry) -> Result<(), ModelRegistryError> {
+    let io_error = |source| ModelRegistryError::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    let mut file = std::fs::File::open(path).map_err(io_error)?;
+    let size = file.metadata().map_err(io_error)?.len();
+    check_size(path, entry, size)?;
+
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 1 << 20];
+    loop {
+        match file.read(&mut buffer).map_err(io_error)? {
+            0 => break,
+            read => hasher.update(&buffer[..read]),
+        }
+    }
+
+    check(path, entry, size, hasher)
+}
+
+/// Checks the `size` and hash of the file at `path` against `entry`.
+fn check(
+    path: &Path,
+    entry: &ModelManifestEntry,
+    size: u64,
+    hasher: Sha256,
+) -> Result<(), ModelRegistryError> {
+    check_size(path, entry, size)?;
+
+    let actual = to_hex(&hasher.finalize());
+    if !actual.eq_ignore_ascii_case(&entry.sha256) {
+        return Err(ModelRegistryError::Checksum {
+            path: path.to_path_buf(),
+            expected: entry.sha256.clone(),
+            actual,
+        });
+    }
+
+    Ok(())
+}
+
+fn check_size(
+    path: &Path,
+    entry: &ModelManifestEntry,
+    size: u64,
+) -> Result<(), ModelRegistryError> {
+    match entry.size {
+        Some(expected) if expected != size => Err(ModelRegistryError::Size {
+            path: path.to_path_buf(),
+            expected,
+            actual: size,
+        }),
+        _ => Ok(()),
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
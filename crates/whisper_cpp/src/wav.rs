@@ -0,0 +1,55 @@
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+use hound::{SampleFormat, WavReader};
+use thiserror::Error;
+
+use crate::mel::SAMPLE_RATE;
+
+/// An error reading a wav file with [`read_wav`] or [`decode_wav`].
+#[derive(Debug, Error)]
+pub enum WavError {
+    #[error("failed to read wav file: {0}")]
+    Wav(#[from] hound::Error),
+    #[error("wav file is sampled at {0} Hz, only {SAMPLE_RATE} Hz is supported")]
+    SampleRate(u32),
+}
+
+/// Reads a wav file sampled at 16 kHz, mixing its channels down to mono.
+pub fn read_wav(path: impl AsRef<Path>) -> Result<Vec<f32>, WavError> {
+    samples(WavReader::open(path)?)
+}
+
+/// Decodes the bytes of a wav file sampled at 16 kHz, mixing its channels down to mono.
+pub fn decode_wav(bytes: &[u8]) -> Result<Vec<f32>, WavError> {
+    samples(WavReader::new(Cursor::new(bytes))?)
+}
+
+fn samples<R: Read>(reader: WavReader<R>) -> Result<Vec<f32>, WavError> {
+    let spec = reader.spec();
+
+    if spec.sample_rate as usize != SAMPLE_RATE {
+        return Err(WavError::SampleRate(spec.sample_rate));
+    }
+
+    let interleaved = match spec.sample_format {
+        SampleFormat::Float => reader
+            .into_samples::<f32>()
+            .collect::<Result<Vec<_>, _>>()?,
+        SampleFormat::Int => {
+            let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .into_samples::<i32>()
+                .map(|sample| sample.map(|sample| sample as f32 / scale))
+                .collect::<Result<Vec<_>, _>>()?
+        }
+    };
+
+    let channels = spec.channels.max(1) as usize;
+    let samples = interleaved
+        .chunks_exact(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+
+    Ok(samples)
+}
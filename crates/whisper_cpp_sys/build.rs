@@ -77,8 +77,10 @@ fn main() {
         .parse_callbacks(Box::new(
             bindgen::CargoCallbacks::new().rerun_on_header_files(false),
         ))
+        .parse_callbacks(Box::new(LinkNames))
         .generate_comments(false)
         .allowlist_function("whisper_.*")
+        .allowlist_function("ggml_quantize_q.*")
         .allowlist_type("whisper_.*")
         .allowlist_type("ggml_.*")
         .generate()
@@ -96,6 +98,24 @@ fn main() {
     }
 }
 
+/// Points the bindings of *ggml* functions to their symbols, which are prefixed when the `compat`
+/// feature is enabled, see [`compat::redefine_symbols`].
+#[derive(Debug)]
+struct LinkNames;
+
+impl bindgen::callbacks::ParseCallbacks for LinkNames {
+    fn generated_link_name_override(
+        &self,
+        item_info: bindgen::callbacks::ItemInfo<'_>,
+    ) -> Option<String> {
+        if cfg!(feature = "compat") && item_info.name.starts_with("ggml_") {
+            Some(format!("whisp_{}", item_info.name))
+        } else {
+            None
+        }
+    }
+}
+
 #[cfg(feature = "compat")]
 mod compat {
     use std::collections::HashSet;
@@ -1,6 +1,12 @@
 #[cfg(test)]
 mod tests {
+    use std::num::NonZeroUsize;
+    use std::sync::Arc;
+    use std::time::Duration;
+
     use thiserror::Error;
+    use tokio::task::JoinSet;
+    use tokio::time::timeout;
 
     use whisper_cpp::*;
 
@@ -10,81 +16,1443 @@ mod tests {
         Whisper(#[from] WhisperError),
         #[error("whisper session error")]
         Session(#[from] WhisperSessionError),
+        #[error("invalid model file")]
+        ModelFile(#[from] WhisperModelFileError),
+        #[error("quantization failed")]
+        Quantize(#[from] WhisperQuantizeError),
         #[error("file was not found: {0}")]
         FileNotFound(#[from] std::io::Error),
     }
 
-    #[tokio::test]
-    async fn it_works() -> Result<(), TestError> {
-        let model_paths = {
-            let dir = std::env::var("WHISPER_TEST_MODEL_DIR").unwrap_or_else(|_| {
-                eprintln!(
-                    "WHISPER_TEST_MODEL environment variable not set. \
+    /// Returns the paths of every model in `WHISPER_TEST_MODEL_DIR`, exiting the test if the
+    /// variable is not set.
+    async fn model_paths() -> Vec<String> {
+        let dir = std::env::var("WHISPER_TEST_MODEL_DIR").unwrap_or_else(|_| {
+            eprintln!(
+                "WHISPER_TEST_MODEL environment variable not set. \
                 Please set this to the path to a Whisper GGUF model file for the test to run."
-                );
+            );
 
-                std::process::exit(0)
-            });
+            std::process::exit(0)
+        });
 
-            let dir = std::path::Path::new(&dir);
+        let dir = std::path::Path::new(&dir);
 
-            if !dir.is_dir() {
-                panic!("\"{}\" is not a directory", dir.to_string_lossy());
-            }
+        if !dir.is_dir() {
+            panic!("\"{}\" is not a directory", dir.to_string_lossy());
+        }
 
-            let mut models = tokio::fs::read_dir(dir).await.unwrap();
-            let mut rv = vec![];
+        let mut models = tokio::fs::read_dir(dir).await.unwrap();
+        let mut rv = vec![];
 
-            while let Some(model) = models.next_entry().await.unwrap() {
-                let path = model.path();
+        while let Some(model) = models.next_entry().await.unwrap() {
+            let path = model.path();
 
-                if path.is_file() {
-                    let path = path.to_str().unwrap();
-                    if path.ends_with(".bin") {
-                        rv.push(path.to_string());
-                    }
+            if path.is_file() {
+                let path = path.to_str().unwrap();
+                if path.ends_with(".bin") {
+                    rv.push(path.to_string());
                 }
             }
+        }
 
-            rv
-        };
+        rv
+    }
+
+    /// Reads the first channel of the `WHISPER_TEST_SAMPLE` wav file, exiting the test if the
+    /// variable is not set.
+    fn samples() -> Result<Vec<f32>, TestError> {
+        read_samples(&sample_path())
+    }
 
-        let sample_path_str = std::env::var("WHISPER_TEST_SAMPLE").unwrap_or_else(|_| {
+    /// Returns the `WHISPER_TEST_SAMPLE` path, exiting the test if the variable is not set.
+    fn sample_path() -> String {
+        std::env::var("WHISPER_TEST_SAMPLE").unwrap_or_else(|_| {
             eprintln!(
                 "WHISPER_TEST_SAMPLE environment variable not set. \
                 Please set this to the path to a sample wav file for the test to run."
             );
 
             std::process::exit(0)
-        });
+        })
+    }
+
+    /// Reads the first channel of the wav file at `path`.
+    fn read_samples(path: &str) -> Result<Vec<f32>, TestError> {
+        let mut file = std::fs::File::open(path)?;
+        let (header, data) = wav::read(&mut file)?;
+        let sixteens = data.as_sixteen().unwrap();
+        let samples: Vec<_> = sixteens[..sixteens.len() / header.channel_count as usize]
+            .iter()
+            .map(|v| *v as f32 / 32768.)
+            .collect();
+
+        Ok(samples)
+    }
+
+    fn device() -> Option<u32> {
+        if cfg!(any(feature = "cuda")) {
+            Some(0)
+        } else {
+            None
+        }
+    }
+
+    #[tokio::test]
+    async fn it_works() -> Result<(), TestError> {
+        let model_paths = model_paths().await;
+        let samples = samples()?;
 
         for model_path_str in model_paths {
-            let device = if cfg!(any(feature = "cuda")) {
-                Some(0)
+            let model = WhisperModel::new_from_file(model_path_str, device())?;
+
+            let mut session = model.new_session().await?;
+
+            let params = WhisperParams::new(WhisperSampling::default_greedy());
+
+            session.advance(params, &samples).await?;
+            let result = session.new_context()?;
+
+            println!("\n{result}\n");
+        }
+
+        Ok(())
+    }
+
+    /// Reloads every model in place of the first one while a session is running on it.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn model_reload() -> Result<(), TestError> {
+        let model_paths = model_paths().await;
+        let samples = Arc::new(samples()?);
+        let params = WhisperParams::new(WhisperSampling::default_greedy());
+
+        let model = WhisperModel::new_from_file(&model_paths[0], device())?;
+        let mut session = model.new_session().await?;
+        let mut idle = model.new_session().await?;
+        let pool = SessionPool::new(&model, NonZeroUsize::new(2).unwrap()).await?;
+        let state = whisper_server::ServerState {
+            pool: pool.clone(),
+            params: params.clone(),
+            live: Default::default(),
+        };
+        let wyoming = whisper_server::wyoming::WyomingServer::new(state, &model, "test");
+
+        for model_path_str in model_paths.iter().rev() {
+            let running = {
+                let samples = samples.clone();
+                let params = params.clone();
+                tokio::spawn(async move {
+                    session.advance(params, &samples).await?;
+                    Ok::<_, WhisperSessionError>(session)
+                })
+            };
+            model.reload(model_path_str, device()).await?;
+            session = running.await.unwrap()?;
+            assert!(session.segment_count() > 0);
+
+            // The previous session switches to the reloaded model, as do new ones
+            let reference = WhisperModel::new_from_file(model_path_str, device())?;
+            let mut expected = reference.new_session().await?;
+            expected.advance(params.clone(), &samples).await?;
+
+            session.reset();
+            session.advance(params.clone(), &samples).await?;
+            assert_eq!(session.new_context()?, expected.new_context()?);
+
+            let mut new_session = model.new_session().await?;
+            new_session.advance(params.clone(), &samples).await?;
+            assert_eq!(new_session.new_context()?, expected.new_context()?);
+            assert_eq!(
+                model.is_multilingual().await,
+                reference.is_multilingual().await
+            );
+
+            // Idle sessions move without running, dropping the tokens of the previous vocabulary
+            idle.set_context_tokens(vec![1, 2, 3]);
+            idle.refresh().await?;
+            assert!(idle.context_tokens().is_empty());
+            pool.refresh().await?;
+            assert_eq!(pool.available(), 2);
+
+            let languages = &wyoming.info().await.data["asr"][0]["models"][0]["languages"];
+            let expected = if reference.is_multilingual().await {
+                language_codes().len()
             } else {
-                None
+                1
             };
+            assert_eq!(languages.as_array().unwrap().len(), expected);
+        }
+
+        let truncated = std::env::temp_dir().join("whisper_cpp_model_reload.bin");
+        std::fs::write(&truncated, b"lmgg")?;
+        assert!(model.reload(&truncated, device()).await.is_err());
+        session.advance(params, &samples).await?;
+        assert!(session.segment_count() > 0);
+
+        Ok(())
+    }
 
-            let model = WhisperModel::new_from_file(model_path_str, device)?;
+    #[tokio::test]
+    async fn segment_metrics() -> Result<(), TestError> {
+        let model_paths = model_paths().await;
+        let samples = samples()?;
 
+        for model_path_str in model_paths {
+            let model = WhisperModel::new_from_file(model_path_str, device())?;
             let mut session = model.new_session().await?;
 
             let params = WhisperParams::new(WhisperSampling::default_greedy());
+            session.advance(params, &samples).await?;
+
+            assert!(session.segment_count() > 0);
+            for segment in 0..session.segment_count() {
+                let metrics = session.segment_metrics(segment)?;
+                assert!(metrics.avg_logprob <= 0.0);
+                assert!(metrics.compression_ratio > 0.0);
+                assert!((0.0..=1.0).contains(&metrics.min_token_probability));
+                assert!((0.0..=1.0).contains(&metrics.no_speech_probability));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Resumes a transcription on another session from the exported text context, which must give
+    /// the same result as carrying on with the original session.
+    #[tokio::test]
+    async fn context_carry_over() -> Result<(), TestError> {
+        let model_paths = model_paths().await;
+        let samples = samples()?;
+        let (first, second) = samples.split_at(samples.len() / 2);
+
+        let mut params = WhisperParams::new(WhisperSampling::default_greedy());
+        params.no_context = false;
+
+        for model_path_str in model_paths {
+            let model = WhisperModel::new_from_file(model_path_str, device())?;
+
+            let mut original = model.new_session().await?;
+            assert!(original.context_tokens().is_empty());
+
+            original.advance(params.clone(), first).await?;
+            let context = original.context_tokens();
+            assert!(!context.is_empty());
 
-            let mut file = std::fs::File::open(&sample_path_str)?;
-            let (header, data) = wav::read(&mut file)?;
-            let sixteens = data.as_sixteen().unwrap();
-            let samples: Vec<_> = sixteens[..sixteens.len() / header.channel_count as usize]
+            let mut resumed = model.new_session().await?;
+            resumed.set_context_tokens(context.clone());
+            assert_eq!(resumed.segment_count(), 0);
+            assert_eq!(resumed.context_tokens(), context);
+
+            original.advance(params.clone(), second).await?;
+            resumed.advance(params.clone(), second).await?;
+            let resumed_text = resumed.new_context()?;
+            assert_eq!(original.new_context()?, resumed_text);
+            assert_eq!(original.context_tokens(), resumed.context_tokens());
+
+            let tokens = original.token_count(0)?;
+            assert!(matches!(
+                original.token_id(0, tokens),
+                Err(WhisperSessionError::TokenOutOfRange { segment: 0, count, .. }) if count == tokens
+            ));
+
+            // The results of the previous runs are out of reach until the next run
+            original.reset();
+            assert_eq!(original.segment_count(), 0);
+            assert!(original.context_tokens().is_empty());
+            assert!(original.segments()?.is_empty());
+            assert!(matches!(
+                original.segment_text(0),
+                Err(WhisperSessionError::SegmentOutOfRange {
+                    segment: 0,
+                    count: 0
+                })
+            ));
+            assert!(original.segment_time(0).is_err());
+            assert!(original.token_count(0).is_err());
+            assert!(original.token_id(0, 0).is_err());
+            assert!(original.token_data(0, 0).is_err());
+            assert!(original.token_probability(0, 0).is_err());
+
+            // Runs failing before whisper.cpp starts leave the reset and the seeded context pending
+            let mut invalid = params.clone();
+            invalid.initial_prompt = "a nul \0 byte".to_string();
+
+            assert!(original.advance(invalid.clone(), second).await.is_err());
+            assert_eq!(original.segment_count(), 0);
+            assert!(original.context_tokens().is_empty());
+
+            resumed.set_context_tokens(context.clone());
+            assert!(resumed.advance(invalid, second).await.is_err());
+            assert_eq!(resumed.segment_count(), 0);
+            assert_eq!(resumed.context_tokens(), context);
+
+            resumed.advance(params.clone(), second).await?;
+            assert_eq!(resumed.new_context()?, resumed_text);
+        }
+
+        Ok(())
+    }
+
+    /// A session checked out of a pool must expose nothing of what its previous user transcribed.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn session_pool() -> Result<(), TestError> {
+        let model_paths = model_paths().await;
+        let samples = samples()?;
+
+        for model_path_str in model_paths {
+            let model = WhisperModel::new_from_file(model_path_str, device())?;
+            let pool = SessionPool::new(&model, NonZeroUsize::new(1).unwrap()).await?;
+
+            let mut session = pool.checkout().await;
+            assert_eq!(pool.available(), 0);
+            assert!(pool.try_checkout().is_none());
+
+            let mut params = WhisperParams::new(WhisperSampling::default_greedy());
+            params.no_context = false;
+            session.advance(params.clone(), &samples).await?;
+            assert!(session.segment_count() > 0);
+            drop(session);
+
+            let mut session = pool.try_checkout().unwrap();
+            assert_eq!(session.segment_count(), 0);
+            assert!(session.segments()?.is_empty());
+            assert!(session.context_tokens().is_empty());
+            assert!(session.segment_text(0).is_err());
+            assert!(session.segment_time(0).is_err());
+            assert!(session.token_count(0).is_err());
+            assert!(session.token_data(0, 0).is_err());
+
+            // Even when the first run of the next user fails
+            let mut invalid = params.clone();
+            invalid.initial_prompt = "a nul \0 byte".to_string();
+            assert!(session.advance(invalid, &samples).await.is_err());
+            assert_eq!(session.segment_count(), 0);
+            assert!(session.context_tokens().is_empty());
+
+            // Nor is the text of the previous user given as context
+            let mut fresh = model.new_session().await?;
+            fresh.advance(params.clone(), &samples).await?;
+            session.advance(params, &samples).await?;
+            assert_eq!(session.context_tokens(), fresh.context_tokens());
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn bilingual_cues() -> Result<(), TestError> {
+        let model_paths = model_paths().await;
+        let samples = samples()?;
+
+        for model_path_str in model_paths {
+            let model = WhisperModel::new_from_file(model_path_str, device())?;
+            let mut session = model.new_session().await?;
+
+            let params = WhisperParams::new(WhisperSampling::default_greedy());
+            let cues = session.advance_bilingual(params.clone(), &samples).await;
+
+            if !model.is_multilingual().await {
+                assert!(matches!(cues, Err(WhisperSessionError::NotMultilingual)));
+                continue;
+            }
+
+            let cues = cues?;
+            assert!(!cues.is_empty());
+            assert!(cues.windows(2).all(|w| w[0].start <= w[1].start));
+            assert!(cues.iter().all(|cue| cue.start <= cue.end));
+
+            // The cues are the transcription and the translation of a fresh session, aligned
+            let mut fresh = model.new_session().await?;
+            fresh.advance(params.clone(), &samples).await?;
+            let original = fresh.segments()?;
+            let mut translate = params;
+            translate.translate = true;
+            translate.no_context = true;
+            fresh.advance(translate, &samples).await?;
+            let translation = fresh.segments()?;
+            assert!(!original.is_empty() && !translation.is_empty());
+            assert_eq!(cues, align_bilingual(&original, &translation));
+
+            // Every word of both texts is found in the cues, in order
+            let words = |texts: Vec<&str>| -> Vec<String> {
+                texts
+                    .iter()
+                    .flat_map(|text| text.split_whitespace().map(str::to_string))
+                    .collect()
+            };
+            assert_eq!(
+                words(cues.iter().map(|cue| cue.original.as_str()).collect()),
+                words(original.iter().map(|s| s.text.as_str()).collect())
+            );
+            assert_eq!(
+                words(cues.iter().map(|cue| cue.translation.as_str()).collect()),
+                words(translation.iter().map(|s| s.text.as_str()).collect())
+            );
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn batch_transcription() -> Result<(), TestError> {
+        let model_paths = model_paths().await;
+        let sample_path = sample_path();
+        let expected = samples()?;
+
+        for model_path_str in model_paths {
+            let model = WhisperModel::new_from_file(model_path_str, device())?;
+
+            let mut session = model.new_session().await?;
+            let params = WhisperParams::new(WhisperSampling::default_greedy());
+            session.advance(params.clone(), &expected).await?;
+            let expected = session.segments()?;
+
+            let batch = BatchTranscriber::new(
+                &model,
+                NonZeroUsize::new(2).unwrap(),
+                NonZeroUsize::new(4).unwrap(),
+                params,
+            )
+            .await?;
+            assert_eq!(batch.sessions(), 2);
+
+            let inputs = vec![
+                sample_path.clone(),
+                "missing.wav".to_string(),
+                sample_path.clone(),
+                sample_path.clone(),
+            ];
+            let mut results = batch.run(inputs, |path| read_samples(path));
+
+            let mut completed = vec![];
+            while let Some(result) = results.next().await {
+                match result.result {
+                    Ok(segments) => assert_eq!(segments, expected),
+                    Err(BatchError::Load(TestError::FileNotFound(_))) => {
+                        assert_eq!(result.input, "missing.wav")
+                    }
+                    Err(e) => panic!("unexpected error for {}: {e}", result.input),
+                }
+                completed.push(result.index);
+            }
+
+            completed.sort();
+            assert_eq!(completed, [0, 1, 2, 3]);
+        }
+
+        Ok(())
+    }
+
+    /// Evaluates a manifest whose references are the transcript of the sample, with added words and
+    /// a missing file.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn evaluator() -> Result<(), TestError> {
+        let model_paths = model_paths().await;
+        let sample_path = sample_path();
+        let samples = samples()?;
+
+        for model_path_str in model_paths {
+            let model = WhisperModel::new_from_file(model_path_str, device())?;
+
+            let mut session = model.new_session().await?;
+            let params = WhisperParams::new(WhisperSampling::default_greedy());
+            session.advance(params.clone(), &samples).await?;
+            let transcript: String = session
+                .segments()?
+                .into_iter()
+                .map(|segment| segment.text)
+                .collect();
+
+            let manifest = [
+                (sample_path.as_str(), transcript.clone()),
+                ("missing.wav", transcript.clone()),
+                (sample_path.as_str(), format!("{transcript} and two more")),
+            ]
+            .map(|(audio, reference)| {
+                serde_json::json!({ "audio": audio, "reference": reference }).to_string()
+            })
+            .join("\n");
+            let manifest = EvalManifest::parse(&manifest, std::path::Path::new("")).unwrap();
+
+            let evaluator = Evaluator::new(
+                &model,
+                NonZeroUsize::new(2).unwrap(),
+                NonZeroUsize::new(4).unwrap(),
+                params.clone(),
+                WhisperTextNormalizer::English,
+            )
+            .await?;
+            let report = evaluator
+                .run(manifest, |path| read_samples(path.to_str().unwrap()))
+                .await;
+
+            assert_eq!(report.params, params);
+            assert_eq!(report.results.len(), 3);
+            assert_eq!(report.failed, 1);
+            assert_eq!(report.results[0].wer(), 0.0);
+            assert_eq!(report.results[0].cer(), 0.0);
+            assert!(report.results[1].error.is_some());
+            assert_eq!(report.results[1].audio, std::path::Path::new("missing.wav"));
+            assert_eq!(report.results[2].words.deletions, 3);
+            assert_eq!(report.words.errors(), 3);
+            assert_eq!(
+                report.words.reference_len(),
+                report.results[0].words.reference_len() * 2 + 3
+            );
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn openai_server() -> Result<(), TestError> {
+        let model_paths = model_paths().await;
+        let wav = std::fs::read(sample_path())?;
+
+        for model_path_str in model_paths {
+            let model = WhisperModel::new_from_file(model_path_str, device())?;
+            let pool = SessionPool::new(&model, NonZeroUsize::new(1).unwrap()).await?;
+            let params = WhisperParams::new(WhisperSampling::default_greedy());
+            let app = whisper_server::router(whisper_server::ServerState {
+                pool,
+                params,
+                live: Default::default(),
+            });
+
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+            let url = format!("http://{}/v1/audio", listener.local_addr()?);
+            let server = tokio::spawn(async move { axum::serve(listener, app).await });
+
+            let client = reqwest::Client::new();
+            let form = |format: &str| {
+                let file = reqwest::multipart::Part::bytes(wav.clone()).file_name("sample.wav");
+                reqwest::multipart::Form::new()
+                    .part("file", file)
+                    .text("model", "whisper-1")
+                    .text("response_format", format.to_string())
+            };
+
+            let response = client
+                .post(format!("{url}/transcriptions"))
+                .multipart(form("verbose_json"))
+                .send()
+                .await
+                .unwrap();
+            assert!(response.status().is_success());
+            let body: serde_json::Value = response.json().await.unwrap();
+            assert_eq!(body["task"], "transcribe");
+            assert_eq!(body["language"], "english");
+            assert!(!body["text"].as_str().unwrap().is_empty());
+            let segments = body["segments"].as_array().unwrap();
+            assert!(!segments.is_empty());
+            assert!(segments[0]["avg_logprob"].as_f64().unwrap() <= 0.0);
+
+            let response = client
+                .post(format!("{url}/translations"))
+                .multipart(form("srt"))
+                .send()
+                .await
+                .unwrap();
+            assert!(response.status().is_success());
+            assert!(response.text().await.unwrap().starts_with("1\n00:00:"));
+
+            let response = client
+                .post(format!("{url}/transcriptions"))
+                .multipart(reqwest::multipart::Form::new().text("model", "whisper-1"))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 400);
+            let body: serde_json::Value = response.json().await.unwrap();
+            assert_eq!(body["error"]["param"], "file");
+            assert_eq!(body["error"]["type"], "invalid_request_error");
+
+            server.abort();
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn live_server() -> Result<(), TestError> {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Message;
+        use whisper_server::live::{LiveClientMessage, LiveConfig, LiveMessage};
+
+        let model_paths = model_paths().await;
+        let samples = samples()?;
+
+        for model_path_str in model_paths {
+            let model = WhisperModel::new_from_file(model_path_str, device())?;
+            let pool = SessionPool::new(&model, NonZeroUsize::new(1).unwrap()).await?;
+            let params = WhisperParams::new(WhisperSampling::default_greedy());
+            let live = LiveConfig {
+                step: Duration::from_secs(2),
+                commit_after: Duration::from_secs(5),
+            };
+            let app = whisper_server::router(whisper_server::ServerState { pool, params, live });
+
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+            let url = format!("ws://{}/v1/audio/live", listener.local_addr()?);
+            let server = tokio::spawn(async move { axum::serve(listener, app).await });
+
+            let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+            for chunk in samples.chunks(1600) {
+                let bytes = chunk
+                    .iter()
+                    .flat_map(|sample| ((sample * 32768.0) as i16).to_le_bytes())
+                    .collect();
+                socket.send(Message::Binary(bytes)).await.unwrap();
+            }
+            let stop = serde_json::to_string(&LiveClientMessage::Stop).unwrap();
+            socket.send(Message::Text(stop)).await.unwrap();
+
+            let mut messages = vec![];
+            while let Some(message) = socket.next().await {
+                if let Message::Text(text) = message.unwrap() {
+                    let message: LiveMessage = serde_json::from_str(&text).unwrap();
+                    let done = message == LiveMessage::Done;
+                    messages.push(message);
+                    if done {
+                        break;
+                    }
+                }
+            }
+
+            assert!(matches!(messages[0], LiveMessage::Language { ref code, .. } if code == "en"));
+            assert!(messages
                 .iter()
-                .map(|v| *v as f32 / 32768.)
+                .any(|message| matches!(message, LiveMessage::Partial { .. })));
+            assert_eq!(messages.last(), Some(&LiveMessage::Done));
+
+            let segments: Vec<_> = messages
+                .iter()
+                .filter_map(|message| match message {
+                    LiveMessage::Segment { start, end, .. } => Some((*start, *end)),
+                    _ => None,
+                })
                 .collect();
+            assert!(!segments.is_empty());
+            assert!(segments.windows(2).all(|pair| pair[0].1 <= pair[1].0 + 0.5));
+
+            server.abort();
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn wyoming_server() -> Result<(), TestError> {
+        use serde_json::{json, Value};
+        use whisper_server::wyoming::{WyomingEvent, WyomingServer};
+
+        let model_paths = model_paths().await;
+        let samples = samples()?;
+
+        for model_path_str in model_paths {
+            let model = WhisperModel::new_from_file(model_path_str, device())?;
+            let pool = SessionPool::new(&model, NonZeroUsize::new(1).unwrap()).await?;
+            let params = WhisperParams::new(WhisperSampling::default_greedy());
+            let state = whisper_server::ServerState {
+                pool,
+                params,
+                live: Default::default(),
+            };
+            let server = WyomingServer::new(state, &model, "test");
+
+            let languages = &server.info().await.data["asr"][0]["models"][0]["languages"];
+            if model.is_multilingual().await {
+                assert_eq!(languages.as_array().unwrap().len(), language_codes().len());
+                assert_eq!(languages[0], "en");
+            } else {
+                assert_eq!(languages, &json!(["en"]));
+            }
+
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+            let address = listener.local_addr()?;
+            let server = tokio::spawn(server.serve(listener));
+
+            let (reader, mut writer) = tokio::net::TcpStream::connect(address).await?.into_split();
+            let mut reader = tokio::io::BufReader::new(reader);
+            WyomingEvent::new("describe", Value::Null)
+                .write(&mut writer)
+                .await
+                .unwrap();
+            let info = WyomingEvent::read(&mut reader).await.unwrap().unwrap();
+            assert_eq!(info.kind, "info");
+
+            let format = json!({ "rate": 16000, "width": 2, "channels": 1 });
+            let mut events = vec![
+                WyomingEvent::new("transcribe", json!({ "language": "en" })),
+                WyomingEvent::new("audio-start", format.clone()),
+            ];
+            for chunk in samples.chunks(1024) {
+                let payload = chunk
+                    .iter()
+                    .flat_map(|sample| ((sample * 32768.0) as i16).to_le_bytes())
+                    .collect();
+                events.push(WyomingEvent::new("audio-chunk", format.clone()).with_payload(payload));
+            }
+            events.push(WyomingEvent::new("audio-stop", Value::Null));
+            for event in events {
+                event.write(&mut writer).await.unwrap();
+            }
+
+            let transcript = WyomingEvent::read(&mut reader).await.unwrap().unwrap();
+            assert_eq!(transcript.kind, "transcript");
+            assert!(!transcript.str("text").unwrap().is_empty());
+            assert_eq!(transcript.str("language"), Some("en"));
+
+            server.abort();
+        }
+
+        Ok(())
+    }
+
+    /// Runs many sessions of the same model at once, on both OS threads and async tasks, dropping
+    /// the model and cancelling a transcription midway, to catch double frees and data races.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn sessions_across_threads() -> Result<(), TestError> {
+        const SESSIONS: usize = 4;
+
+        let model_paths = model_paths().await;
+        let samples = Arc::new(samples()?);
+
+        let params = || {
+            let mut params = WhisperParams::new(WhisperSampling::default_greedy());
+            params.thread_count = 2;
+            params
+        };
+
+        for model_path_str in model_paths {
+            let model = WhisperModel::new_from_file(model_path_str, device())?;
+
+            let mut threads = vec![];
+            for _ in 0..SESSIONS {
+                let mut session = model.new_session().await?;
+                let samples = samples.clone();
+                let params = params();
+
+                threads.push(std::thread::spawn(move || {
+                    session.advance_blocking(params, &samples)?;
+                    session.new_context()
+                }));
+            }
 
+            let mut tasks = JoinSet::new();
+            for _ in 0..SESSIONS {
+                let mut session = model.new_session().await?;
+                let samples = samples.clone();
+                let params = params();
+
+                tasks.spawn(async move {
+                    session.advance(params, &samples).await?;
+                    session.new_context()
+                });
+            }
+
+            let mut cancelled = model.new_session().await?;
+            let _ = timeout(
+                Duration::from_millis(1),
+                cancelled.advance(params(), &samples),
+            )
+            .await;
+
+            // Every session must keep the context alive on its own
+            drop(model);
+            drop(cancelled);
+
+            let mut results = vec![];
+            while let Some(res) = tasks.join_next().await {
+                results.push(res.unwrap()?);
+            }
+            for thread in threads {
+                results.push(thread.join().unwrap()?);
+            }
+
+            assert_eq!(results.len(), SESSIONS * 2);
+            assert!(
+                results.windows(2).all(|w| w[0] == w[1]),
+                "sessions transcribing the same audio disagree: {results:#?}"
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Runs transcriptions on a single threaded runtime, which must keep running other tasks
+    /// meanwhile, and cancels one midway, after which its session must still be usable.
+    #[tokio::test]
+    async fn advance_off_the_runtime() -> Result<(), TestError> {
+        let model_paths = model_paths().await;
+        let samples = samples()?;
+
+        let params = || {
+            let mut params = WhisperParams::new(WhisperSampling::default_greedy());
+            // Runs must not depend on whether the cancelled one finished first
+            params.no_context = true;
+            params
+        };
+
+        for model_path_str in model_paths {
+            let model = WhisperModel::new_from_file(model_path_str, device())?;
+            let mut first = model.new_session().await?;
+            let mut second = model.new_session().await?;
+
+            let ticker = tokio::spawn(tokio::time::sleep(Duration::from_millis(50)));
+            let (first_res, second_res) = tokio::join!(
+                first.advance(params(), &samples),
+                second.advance(params(), &samples)
+            );
+            first_res?;
+            second_res?;
+            assert!(ticker.is_finished(), "advance blocked the runtime");
+            assert_eq!(first.new_context()?, second.new_context()?);
+
+            let cancelled =
+                timeout(Duration::from_millis(1), second.advance(params(), &samples)).await;
+            assert!(cancelled.is_err());
+
+            // Waits for the cancelled run to finish, and replaces its result
+            second.advance(params(), &samples).await?;
+            assert_eq!(second.new_context()?, first.new_context()?);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn model_info() -> Result<(), TestError> {
+        let model_paths = model_paths().await;
+
+        for model_path_str in model_paths {
+            let info = WhisperModelInfo::read(&model_path_str)?;
+            let model = WhisperModel::new_from_file(&model_path_str, device())?;
+
+            assert_eq!(
+                info.hparams.is_multilingual(),
+                model.is_multilingual().await
+            );
+            assert!(info.hparams.model_type().is_some());
+            assert_eq!(info.mel_filters.n_mel, info.hparams.n_mels as usize);
+            assert!(info.vocab.len() <= info.hparams.n_vocab as usize);
+            assert!(info
+                .tensors
+                .iter()
+                .any(|tensor| tensor.name == "encoder.conv1.weight"));
+
+            let file_len = std::fs::metadata(&model_path_str)?.len();
+            let last = info.tensors.last().unwrap();
+            assert_eq!(last.offset + last.size(), file_len);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn memory_usage() -> Result<(), TestError> {
+        let model_paths = model_paths().await;
+        let samples = samples()?;
+
+        for model_path_str in model_paths {
+            let info = WhisperModelInfo::read(&model_path_str)?;
+
+            // whisper.cpp logs the sizes of the buffers it allocates while loading the model and
+            // creating a session, which are told apart from the ones of other tests by the thread
+            // logging them
+            let logged = Arc::new(std::sync::Mutex::new(vec![]));
+            set_logger(WhisperLogger::custom({
+                let logged = logged.clone();
+                move |record| {
+                    let thread = std::thread::current().id();
+                    logged
+                        .lock()
+                        .unwrap()
+                        .push((thread, record.message.to_string()));
+                }
+            }));
+            let path = model_path_str.clone();
+            let loaded = tokio::task::spawn_blocking(move || {
+                let model = WhisperModel::new_from_file(path, device())?;
+                let session = model.new_session_blocking()?;
+                Ok::<_, TestError>((model, session, std::thread::current().id()))
+            })
+            .await
+            .unwrap();
+            set_logger(WhisperLogger::default());
+            let (model, mut session, thread) = loaded?;
+
+            let logged = std::mem::take(&mut *logged.lock().unwrap());
+            // The sizes are logged as "<function>: <buffer> = <size> MB"
+            let logged_size = |buffer: &str| -> u64 {
+                logged
+                    .iter()
+                    .filter(|(id, message)| *id == thread && message.contains(buffer))
+                    .map(|(_, message)| {
+                        let size = message.rsplit('=').next().unwrap();
+                        let size = size.trim().trim_end_matches("MB").trim();
+                        (size.parse::<f64>().unwrap() * 1e6) as u64
+                    })
+                    .sum()
+            };
+            let weights = logged_size("model size");
+            let kv_cache = logged_size("kv self size") + logged_size("kv cross size");
+            let compute = logged_size("compute buffer");
+
+            // The weights and caches are within 10% of the logged sizes, which are rounded and, in
+            // some versions of whisper.cpp, in MiB rather than MB. The compute buffers are rounded
+            // up, to at least what whisper.cpp allocates and at most 2.5 times that.
+            let close = |estimate: u64, logged: u64| {
+                logged > 0 && estimate.abs_diff(logged) as f64 <= 0.1 * logged as f64
+            };
+            let estimate = info.memory_usage(Default::default(), 1);
+            assert!(close(estimate.weights, weights), "{estimate:?} {weights}");
+            assert!(
+                close(estimate.kv_cache, kv_cache),
+                "{estimate:?} {kv_cache}"
+            );
+            assert!(
+                compute > 0 && compute <= estimate.compute && estimate.compute <= compute * 5 / 2,
+                "{estimate:?} {compute}"
+            );
+
+            assert_eq!(
+                model.memory_usage().await,
+                info.memory_usage(Default::default(), 0)
+            );
+
+            let params = WhisperParams::new(WhisperSampling::BeamSearch {
+                beam_size: 3,
+                patience: -1.0,
+            });
+            let expected = WhisperMemoryParams::new(&params, samples.len());
+            assert_eq!(expected.decoders, 3);
             session.advance(params, &samples).await?;
-            let result = session.new_context()?;
+            assert_eq!(
+                session.memory_usage(),
+                WhisperMemoryUsage::session(&info.hparams, expected)
+            );
 
-            println!("\n{result}\n");
+            // The buffers of a session never shrink
+            let params = WhisperParams::new(WhisperSampling::Greedy { best_of: 1 });
+            session
+                .advance(params, &samples[..samples.len() / 2])
+                .await?;
+            assert_eq!(
+                session.memory_usage(),
+                WhisperMemoryUsage::session(&info.hparams, expected)
+            );
         }
 
         Ok(())
     }
+
+    /// Transcribes the spectrogram computed in Rust, which must give the same text as letting
+    /// whisper.cpp compute it from the samples.
+    #[tokio::test]
+    async fn mel_spectrogram() -> Result<(), TestError> {
+        let model_paths = model_paths().await;
+        let samples = samples()?;
+
+        for model_path_str in model_paths {
+            let model = WhisperModel::new_from_file(model_path_str, device())?;
+            let filters = model.mel_filters().await;
+            let mel = WhisperMel::new(&samples, &filters);
+            assert_eq!(mel.frames(), 1 + (samples.len() - 200) / 160);
+
+            let params = WhisperParams::new(WhisperSampling::default_greedy());
+            let mut session = model.new_session().await?;
+            session.advance(params.clone(), &samples).await?;
+            let expected = session.segments()?;
+            let expected_metrics = (0..session.segment_count())
+                .map(|segment| session.segment_metrics(segment))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let mut session = model.new_session().await?;
+            session.advance_mel(params.clone(), &mel).await?;
+            let segments = session.segments()?;
+            assert_eq!(segments.len(), expected.len());
+            for (segment, expected) in segments.iter().zip(&expected) {
+                assert_eq!(segment.text, expected.text);
+            }
+
+            // whisper.cpp has no getter for its spectrogram, so the two are compared through the
+            // probabilities of the tokens decoded from them
+            for (segment, expected) in expected_metrics.iter().enumerate() {
+                let metrics = session.segment_metrics(segment as u32)?;
+                assert!((metrics.avg_logprob - expected.avg_logprob).abs() < 1e-3);
+            }
+
+            let other =
+                WhisperMel::from_data(mel.n_mel() + 1, 0, vec![0.0; mel.n_mel() + 1]).unwrap();
+            assert!(matches!(
+                session.advance_mel(params, &other).await,
+                Err(WhisperSessionError::MelBands { .. })
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn quantized_model() -> Result<(), TestError> {
+        let model_paths = model_paths().await;
+        let samples = samples()?;
+        let output = std::env::temp_dir().join("whisper_cpp_quantized_model.bin");
+        let in_place = std::env::temp_dir().join("whisper_cpp_quantized_in_place.bin");
+        let part = std::env::temp_dir().join("whisper_cpp_quantized_in_place.bin.part");
+
+        for model_path_str in model_paths {
+            let info = WhisperModelInfo::read(&model_path_str)?;
+            if !matches!(info.hparams.ftype, 0 | 1) {
+                continue;
+            }
+
+            let mut written = 0;
+            let stats = quantize_with_progress(
+                &model_path_str,
+                &output,
+                WhisperQuantType::Q5_0,
+                |progress| {
+                    assert_eq!(progress.index, written);
+                    assert_eq!(progress.count, info.tensors.len());
+                    written += 1;
+                },
+            )?;
+            assert_eq!(written, info.tensors.len());
+            assert_eq!(stats.quantized + stats.copied, written);
+            assert_eq!(stats.input_size, info.tensor_bytes());
+            assert!(stats.ratio() < 0.5);
+            assert!(stats.histogram.iter().sum::<i64>() > 0);
+
+            let quantized = WhisperModelInfo::read(&output)?;
+            assert_eq!(quantized.hparams.ftype_name(), Some("q5_0"));
+            assert_eq!(quantized.tensor_bytes(), stats.output_size);
+            assert_eq!(quantized.vocab, info.vocab);
+
+            let model = WhisperModel::new_from_file(&output, device())?;
+            let mut session = model.new_session().await?;
+            let params = WhisperParams::new(WhisperSampling::default_greedy());
+            session.advance(params, &samples).await?;
+            assert!(session.segment_count() > 0);
+
+            // Quantizing a model in place replaces it only once the quantized model is complete
+            std::fs::copy(&model_path_str, &in_place)?;
+            quantize(&in_place, &in_place, WhisperQuantType::Q5_0)?;
+            assert_eq!(
+                WhisperModelInfo::read(&in_place)?.hparams.ftype_name(),
+                Some("q5_0")
+            );
+            assert!(matches!(
+                quantize(&in_place, &in_place, WhisperQuantType::Q8_0),
+                Err(WhisperQuantizeError::Unsupported { .. })
+            ));
+            assert_eq!(WhisperModelInfo::read(&in_place)?, quantized);
+            assert!(!part.exists());
+        }
+
+        Ok(())
+    }
+
+    /// Serves the files of `files` under `/models/`, returning the base URL of the models.
+    async fn serve_models(files: Vec<(&'static str, Vec<u8>)>) -> Result<String, TestError> {
+        use axum::extract::Path;
+        use axum::http::StatusCode;
+
+        let files: Arc<std::collections::HashMap<_, _>> = Arc::new(files.into_iter().collect());
+        let app = axum::Router::new().route(
+            "/models/:file",
+            axum::routing::get(|Path(file): Path<String>| async move {
+                files
+                    .get(file.as_str())
+                    .cloned()
+                    .ok_or(StatusCode::NOT_FOUND)
+            }),
+        );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}/models/", listener.local_addr()?);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        Ok(url)
+    }
+
+    fn manifest_entry(file: &str, contents: &[u8]) -> ModelManifestEntry {
+        use sha2::{Digest, Sha256};
+
+        ModelManifestEntry {
+            file: file.to_string(),
+            sha256: Sha256::digest(contents)
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect(),
+            size: Some(contents.len() as u64),
+        }
+    }
+
+    #[tokio::test]
+    async fn model_registry() -> Result<(), TestError> {
+        let dir = std::env::temp_dir().join("whisper_cpp_model_registry");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let contents = b"not a whisper model".to_vec();
+        let mut manifest = ModelManifest::default();
+        manifest
+            .models
+            .insert("fake".to_string(), manifest_entry("fake.bin", &contents));
+        for name in ["truncated", "absent"] {
+            manifest.models.insert(
+                name.to_string(),
+                manifest_entry(&format!("{name}.bin"), &contents),
+            );
+        }
+
+        let offline = ModelRegistry::new(&dir, manifest.clone()).unwrap();
+        assert!(matches!(
+            offline.fetch("fake").await,
+            Err(ModelRegistryError::Missing { .. })
+        ));
+        assert!(matches!(
+            offline.fetch("unknown").await,
+            Err(ModelRegistryError::UnknownModel(_))
+        ));
+
+        let url = serve_models(vec![
+            ("fake.bin", contents.clone()),
+            ("truncated.bin", contents[..8].to_vec()),
+        ])
+        .await?;
+        let registry = ModelRegistry::new(&dir, manifest.clone())
+            .unwrap()
+            .with_base_url(url);
+
+        // Concurrent fetches share a single download
+        let (path, other) = tokio::join!(registry.fetch("fake"), registry.fetch("fake"));
+        let path = path.unwrap();
+        assert_eq!(other.unwrap(), path);
+        assert_eq!(std::fs::read(&path)?, contents);
+        assert_eq!(offline.verify("fake").await.unwrap(), path);
+
+        // Corrupted files are downloaded again
+        std::fs::write(&path, b"not a whisper modem")?;
+        registry.invalidate("fake");
+        assert!(matches!(
+            registry.verify("fake").await,
+            Err(ModelRegistryError::Checksum { .. })
+        ));
+        registry.fetch("fake").await.unwrap();
+        assert_eq!(std::fs::read(&path)?, contents);
+
+        // Partial downloads never reach the cache
+        assert!(matches!(
+            registry.fetch("truncated").await,
+            Err(ModelRegistryError::Size { .. })
+        ));
+        assert!(matches!(
+            registry.fetch("absent").await,
+            Err(ModelRegistryError::Download { .. })
+        ));
+        let mut files = std::fs::read_dir(&dir)?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<Result<Vec<_>, _>>()?;
+        files.sort();
+        assert_eq!(files, ["fake.bin"]);
+
+        assert!(matches!(
+            registry.load("fake", None).await,
+            Err(ModelRegistryError::Model(WhisperError::ModelFile(_)))
+        ));
+
+        let mut invalid = manifest;
+        invalid.models.get_mut("fake").unwrap().file = "../fake.bin".to_string();
+        assert!(matches!(
+            ModelRegistry::new(&dir, invalid),
+            Err(ModelRegistryError::InvalidEntry { .. })
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn model_registry_load() -> Result<(), TestError> {
+        let model_paths = model_paths().await;
+        let dir = std::env::temp_dir().join("whisper_cpp_model_registry_load");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let contents = std::fs::read(&model_paths[0])?;
+        let mut manifest = ModelManifest::default();
+        manifest
+            .models
+            .insert("model".to_string(), manifest_entry("model.bin", &contents));
+
+        let url = serve_models(vec![("model.bin", contents)]).await?;
+        let registry = ModelRegistry::new(&dir, manifest)
+            .unwrap()
+            .with_base_url(url);
+
+        let model = registry.load("model", device()).await.unwrap();
+        let mut session = model.new_session().await?;
+        let params = WhisperParams::new(WhisperSampling::default_greedy());
+        session.advance(params, &samples()?).await?;
+        assert!(session.segment_count() > 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_model_files() {
+        let dir = std::env::temp_dir().join("whisper_cpp_invalid_model_files");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let bad_magic = dir.join("bad_magic.bin");
+        std::fs::write(&bad_magic, b"GGUF\0\0\0\0").unwrap();
+
+        let truncated = dir.join("truncated.bin");
+        std::fs::write(&truncated, b"lm").unwrap();
+
+        let check = |path: &std::path::Path| match WhisperModel::new_from_file(path, None) {
+            Err(WhisperError::ModelFile(e)) => e,
+            Err(e) => panic!("unexpected error for \"{}\": {e}", path.display()),
+            Ok(_) => panic!("\"{}\" should not load", path.display()),
+        };
+
+        assert!(matches!(
+            check(&dir.join("missing.bin")),
+            WhisperModelFileError::NotFound(_)
+        ));
+        assert!(matches!(check(&dir), WhisperModelFileError::Directory(_)));
+        assert!(matches!(
+            check(&bad_magic),
+            WhisperModelFileError::InvalidMagic { .. }
+        ));
+        assert!(matches!(
+            check(&truncated),
+            WhisperModelFileError::Truncated(_)
+        ));
+        assert!(matches!(
+            check(std::path::Path::new("model\0.bin")),
+            WhisperModelFileError::Nul(_)
+        ));
+    }
+
+    #[test]
+    fn text_normalizers() {
+        let english = WhisperTextNormalizer::English;
+        assert_eq!(
+            english.normalize(
+                "Mr. Smith won't pay twenty-five dollars and fifty cents, uh, for the colour TV [MUSIC]"
+            ),
+            "mister smith will not pay $25.50 for the color tv"
+        );
+        assert_eq!(
+            english
+                .normalize("It's nineteen ninety-nine, the twenty first century, about 5 percent."),
+            "it is 1999 the 21st century about 5%"
+        );
+        assert_eq!(
+            english.normalize(
+                "One hundred and five thousand people, a thousand times, one point five"
+            ),
+            "105000 people 1000 times 1.5"
+        );
+        assert_eq!(english.normalize("Wait a second (laughs)"), "wait a second");
+        assert_eq!(
+            english.normalize("It costs $1,500.00!"),
+            "it costs $1500.00"
+        );
+        assert_eq!(english.normalize("3.5 million"), "3500000");
+        assert_eq!(
+            english.normalize("  Hmm, we've  been to St. Louis  "),
+            "we have been to saint louis"
+        );
+
+        let basic = WhisperTextNormalizer::Basic {
+            remove_diacritics: false,
+        };
+        assert_eq!(
+            basic.normalize("Ça va? [Musique] (rires) Très bien!"),
+            "ça va très bien"
+        );
+        let ascii = WhisperTextNormalizer::Basic {
+            remove_diacritics: true,
+        };
+        assert_eq!(
+            ascii.normalize("Ça va? Très bien, Straße øre"),
+            "ca va tres bien strasse ore"
+        );
+
+        assert_eq!(
+            WhisperTextNormalizer::Verbatim.normalize("  Hello,  World "),
+            "Hello, World"
+        );
+
+        // Spellings loaded from OpenAI's english.json replace the built-in ones
+        assert!(set_english_spellings("[]").is_err());
+        set_english_spellings(r#"{"colour": "color", "mediaeval": "medieval"}"#).unwrap();
+        assert_eq!(english.normalize("A mediaeval colour"), "a medieval color");
+    }
+
+    #[test]
+    fn error_rates() {
+        let alignment = align_words("the cat sat on the mat", "the bat sat the mat too");
+        let counts = EvalCounts::from_alignment(&alignment);
+        assert_eq!(
+            counts,
+            EvalCounts {
+                hits: 4,
+                substitutions: 1,
+                deletions: 1,
+                insertions: 1,
+            }
+        );
+        assert_eq!(counts.reference_len(), 6);
+        assert_eq!(counts.rate(), 0.5);
+        assert_eq!(
+            format_alignment(&alignment).lines().collect::<Vec<_>>(),
+            [
+                "REF: the cat sat on the mat ***",
+                "HYP: the bat sat ** the mat too",
+                "         S       D          I",
+            ]
+        );
+
+        let chars = EvalCounts::from_alignment(&align_chars("kitten", "sitting"));
+        assert_eq!(chars.errors(), 3);
+        assert_eq!(chars.reference_len(), 6);
+
+        let empty = EvalCounts::from_alignment(&align_words("", "hello there"));
+        assert_eq!(empty.insertions, 2);
+        assert_eq!(empty.rate(), 2.0);
+        assert_eq!(EvalCounts::from_alignment(&align_words("", "")).rate(), 0.0);
+
+        let entry = EvalEntry {
+            audio: "a.wav".into(),
+            reference: "Twenty two apples.".to_string(),
+        };
+        let segments =
+            [" 22".to_string(), " apples and pears".to_string()].map(|text| WhisperSegment {
+                text,
+                start: 0,
+                end: 100,
+            });
+        let result = EvalResult::new(&entry, &segments, WhisperTextNormalizer::English);
+        assert_eq!(result.hypothesis, "22 apples and pears");
+        assert_eq!(result.words.insertions, 2);
+        let failed = EvalResult::failed(&entry, "no such file", WhisperTextNormalizer::English);
+
+        let params = WhisperParams::new(WhisperSampling::default_greedy());
+        let report = EvalReport::new(
+            params,
+            WhisperTextNormalizer::English,
+            vec![result.clone(), failed, result],
+        );
+        assert_eq!(report.failed, 1);
+        assert_eq!(report.words.reference_len(), 4);
+        assert_eq!(report.wer(), 1.0);
+    }
+
+    #[test]
+    fn eval_manifest() {
+        let manifest = EvalManifest::parse(
+            r#"{"audio": "clips/a.wav", "reference": "Hello."}
+
+            {"audio": "/data/b.wav", "reference": "World."}"#,
+            std::path::Path::new("/corpus"),
+        )
+        .unwrap();
+        assert_eq!(
+            manifest.entries,
+            [
+                EvalEntry {
+                    audio: "/corpus/clips/a.wav".into(),
+                    reference: "Hello.".to_string(),
+                },
+                EvalEntry {
+                    audio: "/data/b.wav".into(),
+                    reference: "World.".to_string(),
+                },
+            ]
+        );
+
+        let invalid = EvalManifest::parse(
+            "{\"audio\": \"a.wav\", \"reference\": \"\"}\n{\"audio\": \"b.wav\"}",
+            std::path::Path::new(""),
+        );
+        assert!(matches!(invalid, Err(EvalError::Entry { line: 2, .. })));
+    }
+
+    #[test]
+    fn presets() {
+        let strategy = |params: WhisperParams| -> WhisperSampling {
+            serde_json::from_value(serde_json::to_value(params).unwrap()["strategy"].take())
+                .unwrap()
+        };
+        assert_eq!(
+            strategy(WhisperPreset::FastGreedy.params()),
+            WhisperSampling::Greedy { best_of: 1 }
+        );
+        assert_eq!(
+            strategy(WhisperPreset::AccurateBeamSearch.params()),
+            WhisperSampling::BeamSearch {
+                beam_size: 5,
+                patience: -1.0,
+            }
+        );
+        assert_eq!(
+            strategy(WhisperParams::new(WhisperSampling::BeamSearch {
+                beam_size: 3,
+                patience: 0.0,
+            })),
+            WhisperSampling::BeamSearch {
+                beam_size: 3,
+                patience: -1.0,
+            }
+        );
+    }
+
+    #[test]
+    fn params_config() {
+        let from_toml: WhisperParamsConfig = toml::from_str(
+            r#"
+            preset = "accurate-beam-search"
+            language = "de"
+            thread_count = 4
+            strategy = { type = "beam_search", beam_size = 8, patience = -1.0 }
+            "#,
+        )
+        .unwrap();
+
+        let from_json: WhisperParamsConfig = serde_json::from_str(
+            r#"{
+                "preset": "accurate-beam-search",
+                "language": "de",
+                "thread_count": 4,
+                "strategy": { "type": "beam_search", "beam_size": 8, "patience": -1.0 }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(from_toml, from_json);
+
+        let mut expected = WhisperPreset::AccurateBeamSearch.params();
+        WhisperParamsOverrides {
+            strategy: Some(WhisperSampling::BeamSearch {
+                beam_size: 8,
+                patience: -1.0,
+            }),
+            thread_count: Some(4),
+            language: Some("de".to_string()),
+            ..Default::default()
+        }
+        .apply(&mut expected);
+
+        assert_eq!(from_toml.params(), expected);
+
+        let params = WhisperPreset::Subtitles.params();
+        let json = serde_json::to_string(&params).unwrap();
+        assert_eq!(
+            serde_json::from_str::<WhisperParams>(&json).unwrap(),
+            params
+        );
+    }
 }
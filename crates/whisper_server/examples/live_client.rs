@@ -0,0 +1,71 @@
+//! Streams a 16 kHz wav file to a live transcription server at the pace it would be recorded,
+//! printing the messages of the server.
+//!
+//! ```sh
+//! cargo run -p whisper_server --example live_client -- sample.wav ws://127.0.0.1:8080/v1/audio/live
+//! ```
+
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use hound::WavReader;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+use whisper_server::live::{LiveClientMessage, LiveMessage};
+
+/// The duration of the audio sent in each message.
+const CHUNK: Duration = Duration::from_millis(100);
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let path = args.next().ok_or("usage: live_client <wav file> [url]")?;
+    let url = args
+        .next()
+        .unwrap_or_else(|| "ws://127.0.0.1:8080/v1/audio/live".to_string());
+
+    let mut reader = WavReader::open(path)?;
+    let spec = reader.spec();
+    if spec.sample_rate != 16000 || spec.channels != 1 || spec.bits_per_sample != 16 {
+        return Err("the wav file must be 16 kHz, mono and 16-bit".into());
+    }
+    let samples = reader.samples::<i16>().collect::<Result<Vec<_>, _>>()?;
+
+    let (socket, _) = connect_async(url).await?;
+    let (mut sink, mut stream) = socket.split();
+
+    let sender = tokio::spawn(async move {
+        let chunk = (16000 * CHUNK.as_millis() / 1000) as usize;
+        let mut interval = tokio::time::interval(CHUNK);
+
+        for samples in samples.chunks(chunk) {
+            interval.tick().await;
+            let bytes = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+            sink.send(Message::Binary(bytes)).await?;
+        }
+
+        let stop = serde_json::to_string(&LiveClientMessage::Stop).unwrap();
+        sink.send(Message::Text(stop)).await
+    });
+
+    while let Some(message) = stream.next().await {
+        let Message::Text(text) = message? else {
+            continue;
+        };
+
+        match serde_json::from_str(&text)? {
+            LiveMessage::Language { name, .. } => println!("language: {name}"),
+            LiveMessage::Partial { text } => println!("  ... {text}"),
+            LiveMessage::Segment { start, end, text } => {
+                println!("[{start:>7.2} -> {end:>7.2}] {text}")
+            }
+            LiveMessage::Error { message } => eprintln!("error: {message}"),
+            LiveMessage::Done => break,
+        }
+    }
+
+    sender.await??;
+
+    Ok(())
+}
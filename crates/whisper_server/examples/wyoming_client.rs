@@ -0,0 +1,77 @@
+//! Sends a 16 kHz wav file to a Wyoming server, printing the languages it supports and the
+//! transcript.
+//!
+//! ```sh
+//! cargo run -p whisper_server --example wyoming_client -- sample.wav 127.0.0.1:10300 en
+//! ```
+
+use hound::WavReader;
+use serde_json::{json, Value};
+use tokio::io::BufReader;
+use tokio::net::TcpStream;
+
+use whisper_server::wyoming::WyomingEvent;
+
+/// Samples per `audio-chunk`.
+const CHUNK: usize = 1024;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let path = args
+        .next()
+        .ok_or("usage: wyoming_client <wav file> [address] [language]")?;
+    let address = args.next().unwrap_or_else(|| "127.0.0.1:10300".to_string());
+    let language = args.next();
+
+    let mut reader = WavReader::open(path)?;
+    let spec = reader.spec();
+    if spec.sample_rate != 16000 || spec.bits_per_sample != 16 {
+        return Err("the wav file must be 16 kHz and 16-bit".into());
+    }
+    let samples = reader.samples::<i16>().collect::<Result<Vec<_>, _>>()?;
+
+    let (reader, mut writer) = TcpStream::connect(address).await?.into_split();
+    let mut reader = BufReader::new(reader);
+
+    WyomingEvent::new("describe", Value::Null)
+        .write(&mut writer)
+        .await?;
+    let info = WyomingEvent::read(&mut reader).await?.ok_or("no info")?;
+    let languages = &info.data["asr"][0]["models"][0]["languages"];
+    println!("languages: {languages}");
+
+    if let Some(language) = language {
+        WyomingEvent::new("transcribe", json!({ "language": language }))
+            .write(&mut writer)
+            .await?;
+    }
+
+    let format = json!({ "rate": 16000, "width": 2, "channels": spec.channels });
+    WyomingEvent::new("audio-start", format.clone())
+        .write(&mut writer)
+        .await?;
+    for chunk in samples.chunks(CHUNK * spec.channels as usize) {
+        let payload = chunk.iter().flat_map(|s| s.to_le_bytes()).collect();
+        WyomingEvent::new("audio-chunk", format.clone())
+            .with_payload(payload)
+            .write(&mut writer)
+            .await?;
+    }
+    WyomingEvent::new("audio-stop", Value::Null)
+        .write(&mut writer)
+        .await?;
+
+    while let Some(event) = WyomingEvent::read(&mut reader).await? {
+        match event.kind.as_str() {
+            "transcript" => {
+                println!("{}", event.str("text").unwrap_or_default());
+                break;
+            }
+            "error" => return Err(event.str("text").unwrap_or_default().into()),
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,430 @@
+//! An HTTP server exposing a [`WhisperModel`](whisper_cpp::WhisperModel) through the same
+//! endpoints as OpenAI's audio API, so its clients can be pointed at it, and through a WebSocket
+//! for live transcription. The [`wyoming`] module serves it to Home Assistant.
+
+use axum::extract::multipart::MultipartError;
+use axum::extract::{DefaultBodyLimit, Multipart, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Serialize;
+use thiserror::Error;
+
+use whisper_cpp::{
+    decode_wav, format_srt, format_vtt, language_name, SessionPool, WhisperParams, WhisperSegment,
+    WhisperSessionError, SAMPLE_RATE,
+};
+
+pub mod live;
+pub mod wyoming;
+
+use live::LiveConfig;
+
+/// The largest accepted upload, the same as OpenAI's.
+pub const MAX_FILE_SIZE: usize = 25 * 1024 * 1024;
+
+/// What every request is served with.
+#[derive(Clone)]
+pub struct ServerState {
+    /// The sessions requests are transcribed with, a request waits for one to be available.
+    pub pool: SessionPool,
+
+    /// The parameters requests start from, before applying their own fields.
+    pub params: WhisperParams,
+
+    /// How live connections are transcribed.
+    pub live: LiveConfig,
+}
+
+/// Builds the routes of the server:
+/// - `POST /v1/audio/transcriptions`
+/// - `POST /v1/audio/translations`
+/// - `GET /v1/audio/live`, see [`live`]
+pub fn router(state: ServerState) -> Router {
+    Router::new()
+        .route("/v1/audio/transcriptions", post(transcriptions))
+        .route("/v1/audio/translations", post(translations))
+        .route("/v1/audio/live", get(live::live))
+        .layer(DefaultBodyLimit::max(MAX_FILE_SIZE))
+        .with_state(state)
+}
+
+async fn transcriptions(
+    State(state): State<ServerState>,
+    multipart: Multipart,
+) -> Result<Response, ApiError> {
+    let request = AudioRequest::read(multipart, Task::Transcribe).await?;
+    request.run(&state).await
+}
+
+async fn translations(
+    State(state): State<ServerState>,
+    multipart: Multipart,
+) -> Result<Response, ApiError> {
+    let request = AudioRequest::read(multipart, Task::Translate).await?;
+    request.run(&state).await
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Task {
+    Transcribe,
+    Translate,
+}
+
+impl Task {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Transcribe => "transcribe",
+            Self::Translate => "translate",
+        }
+    }
+}
+
+/// The `response_format` field of a request.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ResponseFormat {
+    #[default]
+    Json,
+    Text,
+    Srt,
+    VerboseJson,
+    Vtt,
+}
+
+impl ResponseFormat {
+    /// Parses the value of the `response_format` field.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "json" => Some(Self::Json),
+            "text" => Some(Self::Text),
+            "srt" => Some(Self::Srt),
+            "verbose_json" => Some(Self::VerboseJson),
+            "vtt" => Some(Self::Vtt),
+            _ => None,
+        }
+    }
+}
+
+/// The fields of a multipart transcription or translation request.
+struct AudioRequest {
+    task: Task,
+    samples: Vec<f32>,
+    language: Option<String>,
+    prompt: Option<String>,
+    response_format: ResponseFormat,
+    temperature: Option<f32>,
+}
+
+impl AudioRequest {
+    async fn read(mut multipart: Multipart, task: Task) -> Result<Self, ApiError> {
+        let mut samples = None;
+        let mut request = Self {
+            task,
+            samples: vec![],
+            language: None,
+            prompt: None,
+            response_format: ResponseFormat::default(),
+            temperature: None,
+        };
+
+        while let Some(field) = multipart.next_field().await? {
+            let Some(name) = field.name().map(str::to_string) else {
+                continue;
+            };
+
+            match name.as_str() {
+                "file" => {
+                    let bytes = field.bytes().await?;
+                    let decoded = tokio::task::spawn_blocking(move || decode_wav(&bytes))
+                        .await
+                        .expect("decoding does not panic")
+                        .map_err(|e| ApiError::invalid(e.to_string(), "file"))?;
+                    samples = Some(decoded);
+                }
+                "language" if task == Task::Transcribe => {
+                    request.language = Some(field.text().await?);
+                }
+                "prompt" => request.prompt = Some(field.text().await?),
+                "response_format" => {
+                    let value = field.text().await?;
+                    request.response_format = ResponseFormat::parse(&value).ok_or_else(|| {
+                        ApiError::invalid(
+                            format!("'{value}' is not a supported response format"),
+                            "response_format",
+                        )
+                    })?;
+                }
+                "temperature" => {
+                    let value = field.text().await?;
+                    let temperature = value.parse().map_err(|_| {
+                        ApiError::invalid(format!("'{value}' is not a number"), "temperature")
+                    })?;
+                    request.temperature = Some(temperature);
+                }
+                // The model is chosen when starting the server
+                _ => {}
+            }
+        }
+
+        request.samples =
+            samples.ok_or_else(|| ApiError::invalid("the file field is missing", "file"))?;
+
+        Ok(request)
+    }
+
+    async fn run(self, state: &ServerState) -> Result<Response, ApiError> {
+        let mut params = state.params.clone();
+        params.translate = self.task == Task::Translate;
+        // Sessions are shared between clients, the text of a request must not prompt the next one
+        params.no_context = true;
+        params.language = self.language.unwrap_or_else(|| "auto".to_string());
+        if let Some(prompt) = self.prompt {
+            params.initial_prompt = prompt;
+        }
+        if let Some(temperature) = self.temperature {
+            params.temperature = temperature;
+        }
+        let temperature = params.temperature;
+
+        let mut session = state.pool.checkout().await;
+        session.advance(params, &self.samples).await?;
+
+        let segments = session.segments()?;
+        let text = segments
+            .iter()
+            .map(|segment| segment.text.as_str())
+            .collect::<String>()
+            .trim()
+            .to_string();
+
+        let response = match self.response_format {
+            ResponseFormat::Json => Json(JsonResponse { text }).into_response(),
+            ResponseFormat::Text => plain(text + "\n"),
+            ResponseFormat::Srt => plain(format_srt(&segments)),
+            ResponseFormat::Vtt => plain(format_vtt(&segments)),
+            ResponseFormat::VerboseJson => {
+                let segments = segments
+                    .into_iter()
+                    .enumerate()
+                    .map(|(id, segment)| {
+                        let index = id as u32;
+                        let metrics = session.segment_metrics(index)?;
+                        let tokens = (0..session.token_count(index)?)
+                            .map(|token| session.token_id(index, token))
+                            .collect::<Result<_, _>>()?;
+
+                        Ok(VerboseSegment::new(
+                            id,
+                            segment,
+                            tokens,
+                            temperature,
+                            metrics,
+                        ))
+                    })
+                    .collect::<Result<_, WhisperSessionError>>()?;
+
+                Json(VerboseJsonResponse {
+                    task: self.task.name(),
+                    language: language_name(session.lang_id()).unwrap_or_default(),
+                    duration: self.samples.len() as f64 / SAMPLE_RATE as f64,
+                    text,
+                    segments,
+                })
+                .into_response()
+            }
+        };
+
+        Ok(response)
+    }
+}
+
+fn plain(body: String) -> Response {
+    ([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], body).into_response()
+}
+
+#[derive(Serialize)]
+struct JsonResponse {
+    text: String,
+}
+
+#[derive(Serialize)]
+struct VerboseJsonResponse {
+    task: &'static str,
+    language: &'static str,
+    /// In seconds.
+    duration: f64,
+    text: String,
+    segments: Vec<VerboseSegment>,
+}
+
+#[derive(Serialize)]
+struct VerboseSegment {
+    id: usize,
+    /// The start of the 30 seconds window of the segment, in centiseconds.
+    seek: i64,
+    /// In seconds.
+    start: f64,
+    /// In seconds.
+    end: f64,
+    text: String,
+    tokens: Vec<i32>,
+    temperature: f32,
+    avg_logprob: f32,
+    compression_ratio: f32,
+    /// whisper.cpp does not expose it, this is the heuristic of
+    /// [`whisper_cpp::WhisperSegmentMetrics::no_speech_probability`].
+    no_speech_prob: f32,
+}
+
+impl VerboseSegment {
+    fn new(
+        id: usize,
+        segment: WhisperSegment,
+        tokens: Vec<i32>,
+        temperature: f32,
+        metrics: whisper_cpp::WhisperSegmentMetrics,
+    ) -> Self {
+        Self {
+            id,
+            seek: segment.start - segment.start % 3000,
+            start: segment.start as f64 / 100.0,
+            end: segment.end as f64 / 100.0,
+            text: segment.text,
+            tokens,
+            temperature,
+            avg_logprob: metrics.avg_logprob,
+            compression_ratio: metrics.compression_ratio,
+            no_speech_prob: metrics.no_speech_probability,
+        }
+    }
+}
+
+/// An error, sent back in the same shape as OpenAI's.
+#[derive(Debug, Error)]
+pub enum ApiError {
+    #[error("{message}")]
+    InvalidRequest {
+        message: String,
+        param: Option<&'static str>,
+    },
+    #[error("invalid multipart body: {0}")]
+    Multipart(#[from] MultipartError),
+    #[error("transcription failed: {0}")]
+    Session(#[from] WhisperSessionError),
+}
+
+impl ApiError {
+    fn invalid(message: impl Into<String>, param: &'static str) -> Self {
+        Self::InvalidRequest {
+            message: message.into(),
+            param: Some(param),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: ErrorBody,
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    message: String,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    param: Option<&'static str>,
+    code: Option<&'static str>,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, kind, param) = match &self {
+            Self::InvalidRequest { param, .. } => {
+                (StatusCode::BAD_REQUEST, "invalid_request_error", *param)
+            }
+            Self::Multipart(e) => (e.status(), "invalid_request_error", None),
+            Self::Session(_) => (StatusCode::INTERNAL_SERVER_ERROR, "server_error", None),
+        };
+
+        if status.is_server_error() {
+            tracing::error!("{self}");
+        }
+
+        let body = ErrorResponse {
+            error: ErrorBody {
+                message: self.to_string(),
+                kind,
+                param,
+                code: None,
+            },
+        };
+
+        (status, Json(body)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn response_formats() {
+        assert_eq!(ResponseFormat::parse("json"), Some(ResponseFormat::Json));
+        assert_eq!(
+            ResponseFormat::parse("verbose_json"),
+            Some(ResponseFormat::VerboseJson)
+        );
+        assert_eq!(ResponseFormat::parse("vtt"), Some(ResponseFormat::Vtt));
+        assert_eq!(ResponseFormat::parse("JSON"), None);
+    }
+
+    async fn error_response(error: ApiError) -> (StatusCode, serde_json::Value) {
+        let response = error.into_response();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn error_body() {
+        let error = ApiError::invalid("the file field is missing", "file");
+        assert_eq!(
+            error_response(error).await,
+            (
+                StatusCode::BAD_REQUEST,
+                serde_json::json!({
+                    "error": {
+                        "message": "the file field is missing",
+                        "type": "invalid_request_error",
+                        "param": "file",
+                        "code": null,
+                    }
+                })
+            )
+        );
+
+        let error = WhisperSessionError::SegmentOutOfRange {
+            segment: 2,
+            count: 1,
+        };
+        let message = format!("transcription failed: {error}");
+        assert_eq!(
+            error_response(error.into()).await,
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                serde_json::json!({
+                    "error": {
+                        "message": message,
+                        "type": "server_error",
+                        "param": null,
+                        "code": null,
+                    }
+                })
+            )
+        );
+    }
+}
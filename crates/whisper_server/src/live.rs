@@ -0,0 +1,487 @@
+//! Live transcription over a WebSocket.
+//!
+//! Clients connect to `GET /v1/audio/live`, with the optional query parameters `language`,
+//! `prompt`, `translate` and `encoding` (`pcm_s16le`, the default, `pcm_f32le` or, with the `opus`
+//! feature, `opus`), then send their 16 kHz mono audio as binary messages. Raw PCM may be split
+//! anywhere, while each Opus message must hold a single packet.
+//!
+//! The server replies with [`LiveMessage`]s, serialized as JSON text messages. Sending
+//! `{"type":"stop"}` transcribes the remaining audio, which is answered by
+//! [`LiveMessage::Done`].
+
+use std::time::Duration;
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, State};
+use axum::response::Response;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use whisper_cpp::{language_code, language_name, WhisperSegment, WhisperSessionError, SAMPLE_RATE};
+
+use crate::ServerState;
+
+/// How often live connections are transcribed, and when their text is final.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LiveConfig {
+    /// How much new audio triggers a transcription of the current window.
+    pub step: Duration,
+
+    /// How long the window grows before every segment but the last one is finalized and its
+    /// audio dropped. The last segment is kept, as its end may still be cut off.
+    pub commit_after: Duration,
+}
+
+impl Default for LiveConfig {
+    fn default() -> Self {
+        Self {
+            step: Duration::from_secs(1),
+            commit_after: Duration::from_secs(10),
+        }
+    }
+}
+
+/// The longest window, in centiseconds, after which every segment is finalized, keeping the
+/// window within the 30 seconds Whisper processes at once.
+const MAX_WINDOW: i64 = 2800;
+
+/// The audio encoding of the binary messages of a connection.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LiveEncoding {
+    /// Signed 16-bit little-endian samples.
+    #[default]
+    PcmS16le,
+
+    /// 32-bit little-endian float samples.
+    PcmF32le,
+
+    /// One Opus packet per message.
+    #[cfg(feature = "opus")]
+    Opus,
+}
+
+/// The query parameters of a live connection.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct LiveQuery {
+    /// The spoken language, detected from the first transcription if not set.
+    pub language: Option<String>,
+
+    /// Text the first transcription is prompted with, later ones are prompted with the last
+    /// finalized segments.
+    pub prompt: Option<String>,
+
+    /// Translate the audio to English instead of transcribing it.
+    pub translate: bool,
+
+    pub encoding: LiveEncoding,
+}
+
+/// A message sent by the server.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LiveMessage {
+    /// The language of the audio, sent once if it was detected.
+    Language { code: String, name: String },
+
+    /// The text of the audio which is not final yet, replacing the previous partial text.
+    Partial { text: String },
+
+    /// A final segment, with timestamps in seconds since the start of the connection.
+    Segment { start: f64, end: f64, text: String },
+
+    /// A failure, which ends the connection unless caused by a malformed message.
+    Error { message: String },
+
+    /// Every segment was sent, in reply to a stop message.
+    Done,
+}
+
+/// A message sent by the client, as text.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LiveClientMessage {
+    /// Transcribe the remaining audio and end the connection.
+    Stop,
+}
+
+#[derive(Debug, Error)]
+enum LiveError {
+    #[error("websocket error: {0}")]
+    Socket(#[from] axum::Error),
+    #[error("failed to serialize message: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("transcription failed: {0}")]
+    Session(#[from] WhisperSessionError),
+    #[cfg(feature = "opus")]
+    #[error("invalid opus packet: {0}")]
+    Opus(#[from] audiopus::Error),
+}
+
+pub(crate) async fn live(
+    ws: WebSocketUpgrade,
+    Query(query): Query<LiveQuery>,
+    State(state): State<ServerState>,
+) -> Response {
+    ws.on_upgrade(move |socket| async move {
+        let decoder = match AudioDecoder::new(query.encoding) {
+            Ok(decoder) => decoder,
+            Err(e) => {
+                tracing::warn!("failed to start live connection: {e}");
+                fail(socket, e).await;
+                return;
+            }
+        };
+
+        let mut connection = LiveConnection::new(socket, query, state, decoder);
+        if let Err(e) = connection.run().await {
+            tracing::warn!("live connection failed: {e}");
+            fail(connection.socket, e).await;
+        }
+    })
+}
+
+/// Tells the client about `error`, then closes the connection.
+async fn fail(mut socket: WebSocket, error: LiveError) {
+    let message = LiveMessage::Error {
+        message: error.to_string(),
+    };
+    // The socket itself may be what failed
+    let _ = send(&mut socket, &message).await;
+    let _ = socket.close().await;
+}
+
+async fn send(socket: &mut WebSocket, message: &LiveMessage) -> Result<(), LiveError> {
+    let json = serde_json::to_string(message)?;
+    socket.send(Message::Text(json)).await?;
+    Ok(())
+}
+
+struct LiveConnection {
+    socket: WebSocket,
+    state: ServerState,
+    decoder: AudioDecoder,
+    window: Window,
+    language: String,
+    prompt: String,
+    translate: bool,
+}
+
+impl LiveConnection {
+    fn new(socket: WebSocket, query: LiveQuery, state: ServerState, decoder: AudioDecoder) -> Self {
+        Self {
+            socket,
+            state,
+            decoder,
+            window: Window::default(),
+            language: query.language.unwrap_or_else(|| "auto".to_string()),
+            prompt: query.prompt.unwrap_or_default(),
+            translate: query.translate,
+        }
+    }
+
+    async fn run(&mut self) -> Result<(), LiveError> {
+        let step = (self.state.live.step.as_secs_f64() * SAMPLE_RATE as f64) as usize;
+
+        while let Some(message) = self.socket.recv().await {
+            match message? {
+                Message::Binary(bytes) => match self.decoder.decode(&bytes) {
+                    Ok(samples) => {
+                        self.window.push(&samples);
+
+                        if self.window.pending >= step.max(1) {
+                            self.transcribe(false).await?;
+                        }
+                    }
+                    // Only a malformed packet fails to decode, the next ones may be fine
+                    Err(e) => {
+                        let message = LiveMessage::Error {
+                            message: e.to_string(),
+                        };
+                        self.send(&message).await?;
+                    }
+                },
+                Message::Text(text) => match serde_json::from_str(&text) {
+                    Ok(LiveClientMessage::Stop) => {
+                        self.transcribe(true).await?;
+                        self.send(&LiveMessage::Done).await?;
+                        break;
+                    }
+                    Err(e) => {
+                        let message = LiveMessage::Error {
+                            message: format!("invalid message: {e}"),
+                        };
+                        self.send(&message).await?;
+                    }
+                },
+                Message::Close(_) => break,
+                Message::Ping(_) | Message::Pong(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Transcribes the window, finalizing everything if `last` is set.
+    async fn transcribe(&mut self, last: bool) -> Result<(), LiveError> {
+        let mut params = self.state.params.clone();
+        params.no_context = true;
+        params.translate = self.translate;
+        params.language = self.language.clone();
+        params.initial_prompt = self.prompt.clone();
+
+        let mut session = self.state.pool.checkout().await;
+        session.advance(params, &self.window.samples).await?;
+        let segments = session.segments()?;
+        let lang_id = session.lang_id();
+        drop(session);
+
+        self.window.pending = 0;
+
+        if self.language == "auto" && !segments.is_empty() {
+            if let (Some(code), Some(name)) = (language_code(lang_id), language_name(lang_id)) {
+                self.language = code.to_string();
+                let message = LiveMessage::Language {
+                    code: code.to_string(),
+                    name: name.to_string(),
+                };
+                self.send(&message).await?;
+            }
+        }
+
+        let commit_after = (self.state.live.commit_after.as_secs_f64() * 100.0) as i64;
+        let (committed, partial) = self.window.commit(segments, commit_after, last);
+
+        if !committed.is_empty() {
+            self.prompt = text(&committed);
+        }
+        for segment in committed {
+            let message = LiveMessage::Segment {
+                start: segment.start as f64 / 100.0,
+                end: segment.end as f64 / 100.0,
+                text: segment.text.trim().to_string(),
+            };
+            self.send(&message).await?;
+        }
+
+        if !last {
+            let message = LiveMessage::Partial {
+                text: text(&partial),
+            };
+            self.send(&message).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn send(&mut self, message: &LiveMessage) -> Result<(), LiveError> {
+        send(&mut self.socket, message).await
+    }
+}
+
+fn text(segments: &[WhisperSegment]) -> String {
+    segments
+        .iter()
+        .map(|segment| segment.text.as_str())
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Turns binary messages into samples.
+enum AudioDecoder {
+    PcmS16le(Vec<u8>),
+    PcmF32le(Vec<u8>),
+    #[cfg(feature = "opus")]
+    Opus(audiopus::coder::Decoder),
+}
+
+impl AudioDecoder {
+    fn new(encoding: LiveEncoding) -> Result<Self, LiveError> {
+        Ok(match encoding {
+            LiveEncoding::PcmS16le => Self::PcmS16le(vec![]),
+            LiveEncoding::PcmF32le => Self::PcmF32le(vec![]),
+            #[cfg(feature = "opus")]
+            LiveEncoding::Opus => Self::Opus(audiopus::coder::Decoder::new(
+                audiopus::SampleRate::Hz16000,
+                audiopus::Channels::Mono,
+            )?),
+        })
+    }
+
+    fn decode(&mut self, bytes: &[u8]) -> Result<Vec<f32>, LiveError> {
+        Ok(match self {
+            Self::PcmS16le(rest) => pcm(rest, bytes, |sample: [u8; 2]| {
+                i16::from_le_bytes(sample) as f32 / 32768.0
+            }),
+            Self::PcmF32le(rest) => pcm(rest, bytes, f32::from_le_bytes),
+            #[cfg(feature = "opus")]
+            Self::Opus(decoder) => {
+                // The longest Opus packet lasts 120 ms
+                let mut samples = vec![0.0; SAMPLE_RATE * 120 / 1000];
+                let len = decoder.decode_float(
+                    Some(bytes.try_into()?),
+                    samples.as_mut_slice().try_into()?,
+                    false,
+                )?;
+                samples.truncate(len);
+                samples
+            }
+        })
+    }
+}
+
+/// Decodes the samples of `bytes`, following the incomplete sample left in `rest` by the last
+/// message, and leaves the incomplete sample at its end in `rest`.
+fn pcm<const N: usize>(rest: &mut Vec<u8>, bytes: &[u8], sample: fn([u8; N]) -> f32) -> Vec<f32> {
+    rest.extend_from_slice(bytes);
+    let complete = rest.len() - rest.len() % N;
+
+    let samples = rest[..complete]
+        .chunks_exact(N)
+        .map(|chunk| sample(chunk.try_into().unwrap()))
+        .collect();
+    rest.drain(..complete);
+
+    samples
+}
+
+/// The audio which is not final yet.
+#[derive(Default)]
+struct Window {
+    samples: Vec<f32>,
+
+    /// The start of the window since the start of the connection, in centiseconds.
+    offset: i64,
+
+    /// Samples pushed since the last transcription.
+    pending: usize,
+}
+
+impl Window {
+    const SAMPLES_PER_CENTI: usize = SAMPLE_RATE / 100;
+
+    fn push(&mut self, samples: &[f32]) {
+        self.samples.extend_from_slice(samples);
+        self.pending += samples.len();
+    }
+
+    /// Splits the segments of the window, which are relative to it, into the final ones and the
+    /// partial ones, dropping the audio of the final ones.
+    ///
+    /// Every segment is final if `all` is set, or if the window is too long for Whisper. Otherwise,
+    /// every segment but the last one is once the window is `commit_after` centiseconds long.
+    fn commit(
+        &mut self,
+        mut segments: Vec<WhisperSegment>,
+        commit_after: i64,
+        all: bool,
+    ) -> (Vec<WhisperSegment>, Vec<WhisperSegment>) {
+        let len = (self.samples.len() / Self::SAMPLES_PER_CENTI) as i64;
+
+        let (cut, split) = if all || len >= MAX_WINDOW {
+            (len, segments.len())
+        } else if len >= commit_after && segments.len() >= 2 {
+            let last = segments.len() - 1;
+            (segments[last].start.clamp(0, len), last)
+        } else {
+            (0, 0)
+        };
+
+        for segment in &mut segments {
+            segment.start += self.offset;
+            segment.end += self.offset;
+        }
+        let partial = segments.split_off(split);
+
+        let cut_samples = (cut as usize * Self::SAMPLES_PER_CENTI).min(self.samples.len());
+        self.samples.drain(..cut_samples);
+        self.offset += cut;
+
+        (segments, partial)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(text: &str, start: i64, end: i64) -> WhisperSegment {
+        WhisperSegment {
+            text: text.to_string(),
+            start,
+            end,
+        }
+    }
+
+    #[test]
+    fn split_pcm_samples() {
+        let mut decoder = AudioDecoder::new(LiveEncoding::PcmS16le).unwrap();
+        let bytes: Vec<u8> = [0i16, 16384, -32768]
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect();
+
+        assert_eq!(decoder.decode(&bytes[..3]).unwrap(), [0.0]);
+        assert_eq!(decoder.decode(&bytes[3..]).unwrap(), [0.5, -1.0]);
+
+        let mut decoder = AudioDecoder::new(LiveEncoding::PcmF32le).unwrap();
+        let bytes = 0.25f32.to_le_bytes();
+        assert!(decoder.decode(&bytes[..1]).unwrap().is_empty());
+        assert_eq!(decoder.decode(&bytes[1..]).unwrap(), [0.25]);
+    }
+
+    #[test]
+    fn window_commits() {
+        let second = vec![0.0; SAMPLE_RATE];
+        let mut window = Window::default();
+        for _ in 0..5 {
+            window.push(&second);
+        }
+
+        // Too short to commit anything
+        let segments = vec![segment("a", 0, 200), segment("b", 200, 500)];
+        let (committed, partial) = window.commit(segments.clone(), 1000, false);
+        assert!(committed.is_empty());
+        assert_eq!(partial, segments);
+
+        // Long enough, everything but the last segment is committed
+        for _ in 0..5 {
+            window.push(&second);
+        }
+        let segments = vec![segment("a", 0, 200), segment("b", 250, 1000)];
+        let (committed, partial) = window.commit(segments, 1000, false);
+        assert_eq!(committed, [segment("a", 0, 200)]);
+        assert_eq!(partial, [segment("b", 250, 1000)]);
+        assert_eq!(window.offset, 250);
+        assert_eq!(window.samples.len(), 750 * 160);
+
+        // Timestamps are relative to the start of the connection
+        let (committed, partial) = window.commit(vec![segment("b", 0, 750)], 1000, true);
+        assert_eq!(committed, [segment("b", 250, 1000)]);
+        assert!(partial.is_empty());
+        assert_eq!(window.offset, 1000);
+        assert!(window.samples.is_empty());
+    }
+
+    #[test]
+    fn messages() {
+        let message = LiveMessage::Segment {
+            start: 1.5,
+            end: 3.0,
+            text: "Hello".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_value(&message).unwrap(),
+            serde_json::json!({ "type": "segment", "start": 1.5, "end": 3.0, "text": "Hello" })
+        );
+        assert_eq!(
+            serde_json::to_string(&LiveMessage::Done).unwrap(),
+            r#"{"type":"done"}"#
+        );
+
+        let stop: LiveClientMessage = serde_json::from_str(r#"{"type":"stop"}"#).unwrap();
+        assert_eq!(stop, LiveClientMessage::Stop);
+    }
+}
@@ -0,0 +1,171 @@
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::Parser;
+use tokio::net::TcpListener;
+
+use whisper_cpp::{
+    set_logger, SessionPool, WhisperLogLevel, WhisperLogger, WhisperModel, WhisperParamsOverrides,
+    WhisperPreset,
+};
+use whisper_server::live::LiveConfig;
+use whisper_server::wyoming::WyomingServer;
+use whisper_server::{router, ServerState};
+
+/// Serves a whisper.cpp model through OpenAI's `/v1/audio/transcriptions` and
+/// `/v1/audio/translations` endpoints, and live through the `/v1/audio/live` WebSocket.
+///
+/// On Unix, sending `SIGHUP` reloads the model file, in place of the current model.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Args {
+    /// Path to the ggml model.
+    #[arg(short, long)]
+    model: PathBuf,
+
+    /// The address to listen on.
+    #[arg(short, long, default_value = "127.0.0.1:8080")]
+    listen: SocketAddr,
+
+    /// Number of requests transcribed concurrently, each holding its own session.
+    #[arg(short, long, default_value = "1")]
+    sessions: NonZeroUsize,
+
+    /// Number of threads used by each session.
+    #[arg(short, long)]
+    threads: Option<u32>,
+
+    /// The preset the parameters of every request start from.
+    #[arg(long, default_value = "default")]
+    preset: WhisperPreset,
+
+    /// Also serve the Wyoming protocol on this address, for Home Assistant.
+    #[arg(long)]
+    wyoming: Option<SocketAddr>,
+
+    /// The longest audio transcribed at once for Wyoming clients, in seconds.
+    #[arg(long, default_value_t = 30)]
+    wyoming_max_utterance_secs: u64,
+
+    /// Milliseconds of new audio after which live connections are transcribed again.
+    #[arg(long, default_value_t = 1000)]
+    live_step_ms: u64,
+
+    /// Milliseconds of audio after which the text of live connections is finalized.
+    #[arg(long, default_value_t = 10000)]
+    live_commit_ms: u64,
+
+    /// Do not use the GPU.
+    #[arg(long)]
+    no_gpu: bool,
+
+    /// The index of the GPU to use.
+    #[arg(long, default_value_t = 0)]
+    gpu_device: u32,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+
+    tracing_subscriber::fmt::init();
+    set_logger(WhisperLogger::default().with_min_level(WhisperLogLevel::Warn));
+
+    match serve(args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            tracing::error!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn serve(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    let device = (!args.no_gpu).then_some(args.gpu_device);
+    let model = WhisperModel::new_from_file(&args.model, device)?;
+    let pool = SessionPool::new(&model, args.sessions).await?;
+
+    let mut params = args.preset.params();
+    WhisperParamsOverrides {
+        thread_count: args.threads,
+        print_progress: Some(false),
+        ..Default::default()
+    }
+    .apply(&mut params);
+
+    let live = LiveConfig {
+        step: Duration::from_millis(args.live_step_ms),
+        commit_after: Duration::from_millis(args.live_commit_ms),
+    };
+
+    let state = ServerState { pool, params, live };
+
+    let wyoming = match args.wyoming {
+        Some(address) => {
+            let name = args.model.file_stem().unwrap_or_default().to_string_lossy();
+            let server = WyomingServer::new(state.clone(), &model, name)
+                .with_max_utterance(Duration::from_secs(args.wyoming_max_utterance_secs));
+            let listener = TcpListener::bind(address).await?;
+            tracing::info!("serving wyoming on {}", listener.local_addr()?);
+            Some(tokio::spawn(server.serve(listener)))
+        }
+        None => None,
+    };
+
+    #[cfg(unix)]
+    tokio::spawn(reload_on_hangup(
+        model,
+        state.pool.clone(),
+        args.model.clone(),
+        device,
+    ));
+
+    let listener = TcpListener::bind(args.listen).await?;
+    tracing::info!("listening on {}", listener.local_addr()?);
+
+    let http = axum::serve(listener, router(state));
+    match wyoming {
+        Some(wyoming) => tokio::select! {
+            res = http => res?,
+            res = wyoming => res??,
+        },
+        None => http.await?,
+    }
+
+    Ok(())
+}
+
+/// Reloads the model from `path` whenever the process receives `SIGHUP`, so that a new version of
+/// the file can be rolled out without dropping connections.
+///
+/// The idle sessions of `pool` then move to the new model, so the previous one is freed as soon as
+/// the sessions running on it are done.
+#[cfg(unix)]
+async fn reload_on_hangup(
+    model: WhisperModel,
+    pool: SessionPool,
+    path: PathBuf,
+    device: Option<u32>,
+) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            tracing::warn!("cannot reload the model on SIGHUP: {e}");
+            return;
+        }
+    };
+
+    while hangups.recv().await.is_some() {
+        tracing::info!("reloading {}", path.display());
+        if let Err(e) = model.reload(&path, device).await {
+            tracing::error!("failed to reload {}: {e}", path.display());
+        } else if let Err(e) = pool.refresh().await {
+            tracing::error!("failed to move the sessions to the reloaded model: {e}");
+        }
+    }
+}
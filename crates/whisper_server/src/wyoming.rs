@@ -0,0 +1,524 @@
+//! A [Wyoming] protocol server, the protocol Home Assistant talks to speech-to-text services with.
+//!
+//! Each event is a line of JSON holding its type and the lengths of the JSON data and binary
+//! payload following it. A client asks for the service description with `describe`, answered by
+//! `info`, optionally selects a language with `transcribe`, then streams its audio with
+//! `audio-start`, `audio-chunk` and `audio-stop`, which is answered by a `transcript`.
+//!
+//! [Wyoming]: https://github.com/rhasspy/wyoming
+
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::{json, Map, Value};
+use thiserror::Error;
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
+use tokio::net::{TcpListener, TcpStream};
+
+use whisper_cpp::{language_code, language_codes, WhisperModel, WhisperSessionError, SAMPLE_RATE};
+
+use crate::ServerState;
+
+/// The version of the protocol spoken, sent in every event.
+pub const WYOMING_VERSION: &str = "1.5.2";
+
+/// The longest header, data or payload accepted.
+const MAX_LENGTH: usize = 16 * 1024 * 1024;
+
+/// The default longest utterance, see [`WyomingServer::with_max_utterance`].
+pub const MAX_UTTERANCE: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Error)]
+pub enum WyomingError {
+    #[error("i/o error: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid event: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("invalid event: {0}")]
+    Invalid(&'static str),
+    #[error("event is larger than {MAX_LENGTH} bytes")]
+    TooLarge,
+}
+
+/// A message of the protocol.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WyomingEvent {
+    /// The type of the event, such as `audio-chunk`.
+    pub kind: String,
+
+    pub data: Map<String, Value>,
+
+    pub payload: Vec<u8>,
+}
+
+impl WyomingEvent {
+    /// Creates an event without payload, `data` being a JSON object or [`Value::Null`].
+    pub fn new(kind: impl Into<String>, data: Value) -> Self {
+        Self {
+            kind: kind.into(),
+            data: match data {
+                Value::Object(data) => data,
+                _ => Map::new(),
+            },
+            payload: vec![],
+        }
+    }
+
+    /// Sets the binary payload of the event.
+    pub fn with_payload(mut self, payload: Vec<u8>) -> Self {
+        self.payload = payload;
+        self
+    }
+
+    /// Returns the string field `key` of the data of the event.
+    pub fn str(&self, key: &str) -> Option<&str> {
+        self.data.get(key).and_then(Value::as_str)
+    }
+
+    /// Returns the integer field `key` of the data of the event.
+    pub fn u64(&self, key: &str) -> Option<u64> {
+        self.data.get(key).and_then(Value::as_u64)
+    }
+
+    /// Reads the next event, returning [`None`] once the stream is closed.
+    ///
+    /// Data sent inside the header, as older versions of the protocol do, is merged with the data
+    /// following it.
+    pub async fn read<R>(reader: &mut R) -> Result<Option<Self>, WyomingError>
+    where
+        R: AsyncBufRead + Unpin,
+    {
+        let mut line = vec![];
+        let mut limited = (&mut *reader).take(MAX_LENGTH as u64);
+        if limited.read_until(b'\n', &mut line).await? == 0 {
+            return Ok(None);
+        }
+        if line.last() != Some(&b'\n') {
+            return Err(if line.len() >= MAX_LENGTH {
+                WyomingError::TooLarge
+            } else {
+                WyomingError::Invalid("truncated header")
+            });
+        }
+
+        let header: Map<String, Value> = serde_json::from_slice(&line)?;
+        let kind = header
+            .get("type")
+            .and_then(Value::as_str)
+            .ok_or(WyomingError::Invalid("missing type"))?
+            .to_string();
+        let length = |key| match header.get(key) {
+            None | Some(Value::Null) => Ok(0),
+            Some(value) => match value.as_u64() {
+                Some(len) if len as usize <= MAX_LENGTH => Ok(len as usize),
+                Some(_) => Err(WyomingError::TooLarge),
+                None => Err(WyomingError::Invalid("invalid length")),
+            },
+        };
+        let data_length = length("data_length")?;
+        let payload_length = length("payload_length")?;
+
+        let mut data = match header.get("data") {
+            Some(Value::Object(data)) => data.clone(),
+            _ => Map::new(),
+        };
+        if data_length > 0 {
+            let mut bytes = vec![0; data_length];
+            reader.read_exact(&mut bytes).await?;
+            let extra: Map<String, Value> = serde_json::from_slice(&bytes)?;
+            data.extend(extra);
+        }
+
+        let mut payload = vec![0; payload_length];
+        reader.read_exact(&mut payload).await?;
+
+        Ok(Some(Self {
+            kind,
+            data,
+            payload,
+        }))
+    }
+
+    /// Writes the event, its data following the header.
+    pub async fn write<W>(&self, writer: &mut W) -> Result<(), WyomingError>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let data = if self.data.is_empty() {
+            vec![]
+        } else {
+            serde_json::to_vec(&self.data)?
+        };
+
+        let mut header = serde_json::to_vec(&json!({
+            "type": self.kind,
+            "version": WYOMING_VERSION,
+            "data_length": (!data.is_empty()).then_some(data.len()),
+            "payload_length": (!self.payload.is_empty()).then_some(self.payload.len()),
+        }))?;
+        header.push(b'\n');
+
+        writer.write_all(&header).await?;
+        writer.write_all(&data).await?;
+        writer.write_all(&self.payload).await?;
+        writer.flush().await?;
+
+        Ok(())
+    }
+}
+
+/// Serves the sessions of a [`ServerState`] to Wyoming clients.
+#[derive(Clone)]
+pub struct WyomingServer {
+    state: ServerState,
+    model: WhisperModel,
+    name: Arc<str>,
+    max_utterance: Duration,
+}
+
+impl WyomingServer {
+    /// Creates a server for the sessions of `state`, which must belong to `model`, advertised
+    /// under `name`.
+    pub fn new(state: ServerState, model: &WhisperModel, name: impl Into<String>) -> Self {
+        Self {
+            state,
+            model: model.clone(),
+            name: name.into().into(),
+            max_utterance: MAX_UTTERANCE,
+        }
+    }
+
+    /// Sets the longest audio accepted between `audio-start` and `audio-stop`, [`MAX_UTTERANCE`]
+    /// by default. Longer audio is dropped and answered by an `error` event.
+    pub fn with_max_utterance(mut self, max_utterance: Duration) -> Self {
+        self.max_utterance = max_utterance;
+        self
+    }
+
+    /// The `info` event describing the server, with the languages of the current model, which
+    /// change when it is reloaded.
+    pub async fn info(&self) -> WyomingEvent {
+        let languages = if self.model.is_multilingual().await {
+            language_codes()
+        } else {
+            vec!["en"]
+        };
+
+        info(&self.name, &languages)
+    }
+
+    /// Accepts connections until accepting fails.
+    pub async fn serve(self, listener: TcpListener) -> io::Result<()> {
+        loop {
+            let (stream, address) = listener.accept().await?;
+            let server = self.clone();
+
+            tokio::spawn(async move {
+                if let Err(e) = server.handle(stream).await {
+                    tracing::warn!("wyoming connection from {address} failed: {e}");
+                }
+            });
+        }
+    }
+
+    async fn handle(&self, stream: TcpStream) -> Result<(), WyomingError> {
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+
+        let mut language = None;
+        let max_samples = (self.max_utterance.as_secs_f64() * SAMPLE_RATE as f64) as usize;
+        let mut utterance = Utterance::new(max_samples);
+
+        while let Some(event) = WyomingEvent::read(&mut reader).await? {
+            match event.kind.as_str() {
+                "describe" => self.info().await.write(&mut writer).await?,
+                "transcribe" => language = event.str("language").map(str::to_string),
+                "audio-start" => utterance.start(),
+                "audio-chunk" => {
+                    if let Some(error) = utterance.push(&event) {
+                        error.write(&mut writer).await?;
+                    }
+                }
+                "audio-stop" if utterance.rejected => language = None,
+                "audio-stop" => {
+                    let samples = &utterance.samples;
+                    let transcript = match self.transcribe(language.take(), samples).await {
+                        Ok((text, language)) => WyomingEvent::new(
+                            "transcript",
+                            json!({ "text": text, "language": language }),
+                        ),
+                        Err(e) => {
+                            tracing::error!("wyoming transcription failed: {e}");
+                            let error = json!({ "text": e.to_string(), "code": "transcription" });
+                            WyomingEvent::new("error", error)
+                        }
+                    };
+                    transcript.write(&mut writer).await?;
+                    utterance.start();
+                }
+                kind => tracing::debug!("ignoring wyoming event {kind}"),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Transcribes `samples`, returning their text and language.
+    async fn transcribe(
+        &self,
+        language: Option<String>,
+        samples: &[f32],
+    ) -> Result<(String, Option<&'static str>), WhisperSessionError> {
+        let mut params = self.state.params.clone();
+        params.no_context = true;
+        params.language = language.unwrap_or_else(|| "auto".to_string());
+
+        let mut session = self.state.pool.checkout().await;
+        session.advance(params, samples).await?;
+
+        let text = session
+            .segments()?
+            .iter()
+            .map(|segment| segment.text.as_str())
+            .collect::<String>()
+            .trim()
+            .to_string();
+
+        Ok((text, language_code(session.lang_id())))
+    }
+}
+
+fn info(name: &str, languages: &[&str]) -> WyomingEvent {
+    let attribution = json!({
+        "name": "ggerganov",
+        "url": "https://github.com/ggerganov/whisper.cpp",
+    });
+
+    WyomingEvent::new(
+        "info",
+        json!({
+            "asr": [{
+                "name": "whisper.cpp",
+                "description": "whisper.cpp speech-to-text",
+                "attribution": attribution,
+                "installed": true,
+                "version": env!("CARGO_PKG_VERSION"),
+                "models": [{
+                    "name": name,
+                    "description": name,
+                    "attribution": attribution,
+                    "installed": true,
+                    "languages": languages,
+                    "version": null,
+                }],
+            }],
+        }),
+    )
+}
+
+/// The audio received since the last `audio-start`.
+struct Utterance {
+    samples: Vec<f32>,
+
+    /// The most samples kept, the audio is rejected past them.
+    max_samples: usize,
+
+    /// Set once a chunk of the current audio was rejected, which was reported by an error.
+    rejected: bool,
+}
+
+impl Utterance {
+    fn new(max_samples: usize) -> Self {
+        Self {
+            samples: vec![],
+            max_samples,
+            rejected: false,
+        }
+    }
+
+    /// Drops the current audio, to start a new utterance.
+    fn start(&mut self) {
+        self.samples = vec![];
+        self.rejected = false;
+    }
+
+    /// Adds the samples of an `audio-chunk`, returning the `error` event to reply with if they are
+    /// rejected. The chunks following a rejected one are ignored until the next `audio-start`.
+    fn push(&mut self, event: &WyomingEvent) -> Option<WyomingEvent> {
+        if self.rejected {
+            return None;
+        }
+
+        let error = match AudioFormat::of(event) {
+            Ok(format) => {
+                self.samples.extend(format.decode(&event.payload));
+                if self.samples.len() <= self.max_samples {
+                    return None;
+                }
+
+                let seconds = self.max_samples as f64 / SAMPLE_RATE as f64;
+                let message = format!("audio is longer than {seconds} seconds");
+                json!({ "text": message, "code": "audio-too-long" })
+            }
+            Err(message) => json!({ "text": message, "code": "unsupported-audio" }),
+        };
+
+        self.start();
+        self.rejected = true;
+        Some(WyomingEvent::new("error", error))
+    }
+}
+
+/// The most channels an `audio-chunk` may interleave.
+const MAX_CHANNELS: u64 = 8;
+
+/// The format of the PCM samples of an `audio-chunk`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct AudioFormat {
+    /// Bytes per sample.
+    width: usize,
+    channels: usize,
+}
+
+impl AudioFormat {
+    fn of(event: &WyomingEvent) -> Result<Self, String> {
+        let rate = event.u64("rate").unwrap_or(SAMPLE_RATE as u64);
+        let width = event.u64("width").unwrap_or(2);
+        let channels = event.u64("channels").unwrap_or(1);
+
+        if rate != SAMPLE_RATE as u64 {
+            return Err(format!(
+                "audio is sampled at {rate} Hz, only {SAMPLE_RATE} Hz is supported"
+            ));
+        }
+        if width != 2 && width != 4 {
+            return Err(format!(
+                "audio samples are {width} bytes wide, only 2 and 4 are supported"
+            ));
+        }
+        if !(1..=MAX_CHANNELS).contains(&channels) {
+            return Err(format!(
+                "audio has {channels} channels, only 1 to {MAX_CHANNELS} are supported"
+            ));
+        }
+
+        Ok(Self {
+            width: width as usize,
+            channels: channels as usize,
+        })
+    }
+
+    /// Decodes signed little-endian samples, mixing their channels down to mono.
+    fn decode(self, payload: &[u8]) -> impl Iterator<Item = f32> + '_ {
+        payload
+            .chunks_exact(self.width * self.channels)
+            .map(move |frame| {
+                let sum: f32 = frame
+                    .chunks_exact(self.width)
+                    .map(|sample| match *sample {
+                        [a, b] => i16::from_le_bytes([a, b]) as f32 / 32768.0,
+                        [a, b, c, d] => i32::from_le_bytes([a, b, c, d]) as f32 / 2147483648.0,
+                        _ => unreachable!(),
+                    })
+                    .sum();
+
+                sum / self.channels as f32
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn event_round_trip() {
+        let event = WyomingEvent::new("audio-chunk", json!({ "rate": 16000, "width": 2 }))
+            .with_payload(vec![1, 2, 3, 4]);
+        let stop = WyomingEvent::new("audio-stop", Value::Null);
+
+        let mut bytes = vec![];
+        event.write(&mut bytes).await.unwrap();
+        stop.write(&mut bytes).await.unwrap();
+
+        let mut reader = bytes.as_slice();
+        assert_eq!(WyomingEvent::read(&mut reader).await.unwrap(), Some(event));
+        assert_eq!(WyomingEvent::read(&mut reader).await.unwrap(), Some(stop));
+        assert_eq!(WyomingEvent::read(&mut reader).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn inline_data() {
+        let bytes = b"{\"type\":\"transcribe\",\"data\":{\"language\":\"de\"}}\n";
+        let event = WyomingEvent::read(&mut bytes.as_slice())
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(event.kind, "transcribe");
+        assert_eq!(event.str("language"), Some("de"));
+    }
+
+    #[tokio::test]
+    async fn invalid_events() {
+        let truncated = b"{\"type\":\"audio-chunk\",\"payload_length\":4}\n\x01";
+        assert!(WyomingEvent::read(&mut truncated.as_slice()).await.is_err());
+
+        let untyped = b"{\"data\":{}}\n";
+        assert!(WyomingEvent::read(&mut untyped.as_slice()).await.is_err());
+    }
+
+    #[test]
+    fn utterance_limit() {
+        let chunk = |samples: usize| {
+            WyomingEvent::new("audio-chunk", json!({ "rate": 16000, "width": 2 }))
+                .with_payload(vec![0; samples * 2])
+        };
+        let mut utterance = Utterance::new(4);
+
+        assert_eq!(utterance.push(&chunk(3)), None);
+        assert_eq!(utterance.samples.len(), 3);
+
+        let error = utterance.push(&chunk(2)).unwrap();
+        assert_eq!(error.kind, "error");
+        assert_eq!(error.str("code"), Some("audio-too-long"));
+        assert!(utterance.rejected);
+        assert_eq!(utterance.samples.capacity(), 0);
+
+        // The rest of the audio is ignored, until the next one starts
+        assert_eq!(utterance.push(&chunk(1)), None);
+        assert!(utterance.samples.is_empty());
+
+        utterance.start();
+        assert_eq!(utterance.push(&chunk(4)), None);
+        assert_eq!(utterance.samples.len(), 4);
+
+        let error = utterance.push(&WyomingEvent::new("audio-chunk", json!({ "rate": 8000 })));
+        assert_eq!(error.unwrap().str("code"), Some("unsupported-audio"));
+    }
+
+    #[test]
+    fn audio_formats() {
+        let chunk = |rate: u64, width: u64, channels: u64| {
+            let data = json!({ "rate": rate, "width": width, "channels": channels });
+            AudioFormat::of(&WyomingEvent::new("audio-chunk", data))
+        };
+
+        assert!(chunk(44100, 2, 1).is_err());
+        assert!(chunk(16000, 3, 1).is_err());
+        assert!(chunk(16000, 2, 0).is_err());
+        assert!(chunk(16000, 2, 9).is_err());
+        assert!(chunk(16000, 4, u64::MAX).is_err());
+        assert!(chunk(16000, u64::MAX, 1).is_err());
+
+        let stereo = chunk(16000, 2, 2).unwrap();
+        let payload: Vec<u8> = [16384i16, 0, -32768, 0]
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect();
+        assert_eq!(stereo.decode(&payload).collect::<Vec<_>>(), [0.25, -0.5]);
+    }
+}